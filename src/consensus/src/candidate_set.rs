@@ -18,7 +18,10 @@
 
 use crate::causal_graph::CausalGraph;
 use crate::parameters::*;
+use crypto::Hash;
 use events::Event;
+use hashbrown::{HashMap, HashSet};
+use network::NodeId;
 use std::sync::Arc;
 
 #[derive(Clone, Debug)]
@@ -31,11 +34,49 @@ pub struct CandidateSet {
     /// for the `CandidateSet`.
     pub voters: Vec<Arc<Event>>,
 
-    /// Total number of votes.
+    /// The number of distinct nodes that vote
+    /// for the `CandidateSet`.
     pub votes: u16,
 
-    /// Total number of proposals.
+    /// The number of distinct nodes that propose
+    /// for the `CandidateSet`.
     pub proposals: u16,
+
+    /// The number of validators that the votes
+    /// are counted against.
+    node_count: u16,
+
+    /// The events that follow the parent of the set,
+    /// mapped by the node that has issued them.
+    issued: HashMap<NodeId, Vec<Arc<Event>>>,
+
+    /// The nodes that have issued events of the set
+    /// or events that follow it.
+    inside: HashSet<NodeId>,
+
+    /// The nodes that have issued events which follow
+    /// the parent of the set but not the set itself.
+    outside: HashSet<NodeId>,
+
+    /// The nodes whose events are not counted.
+    excluded: HashSet<NodeId>,
+
+    /// The distinct nodes, other than its own, that have
+    /// issued events following each event in the set.
+    followers: HashMap<Hash, HashSet<NodeId>>,
+
+    /// The distinct nodes, other than its own, that have
+    /// issued voting events following each event in the set.
+    voting_followers: HashMap<Hash, HashSet<NodeId>>,
+
+    /// The hashes of the voting events.
+    voter_hashes: HashSet<Hash>,
+
+    /// The nodes that vote for the set.
+    voting_nodes: HashSet<NodeId>,
+
+    /// The nodes that propose for the set.
+    proposing_nodes: HashSet<NodeId>,
 }
 
 impl CandidateSet {
@@ -45,23 +86,667 @@ impl CandidateSet {
             voters: Vec::new(),
             votes: 0,
             proposals: 0,
+            node_count: 0,
+            issued: HashMap::new(),
+            inside: HashSet::new(),
+            outside: HashSet::new(),
+            excluded: HashSet::new(),
+            followers: HashMap::new(),
+            voting_followers: HashMap::new(),
+            voter_hashes: HashSet::new(),
+            voting_nodes: HashSet::new(),
+            proposing_nodes: HashSet::new(),
         }
     }
 
     /// Returns `true` if the events in the `CandidateSet`
-    /// are valid for inclusion into the total order.
+    /// are valid for inclusion into the total order, which
+    /// is when at least `required_proposal(node_count)`
+    /// distinct nodes propose for it.
     pub fn is_valid(&self, node_count: u16) -> bool {
-        self.proposals >= proposal_requirement(node_count)
+        self.proposals >= required_proposal(node_count)
     }
 
     /// Counts the number of votes and proposals
     /// of the `CandidateSet` based on the provided
     /// `CausalGraph`.
     ///
+    /// An event votes for the `CandidateSet` if it follows
+    /// all of its events and if it is followed by events from
+    /// at least `eligibility_requirement(node_count)` distinct
+    /// nodes other than its own. A vote is also a proposal if
+    /// it is followed by voting events from at least
+    /// `proposal_requirement(node_count)` distinct nodes other
+    /// than its own.
+    ///
+    /// Votes and proposals are counted once per node. The nodes
+    /// that have forked their own history by issuing conflicting
+    /// events are not counted at all and neither are the nodes
+    /// that have issued events following competing sets, which
+    /// are the other events that follow the parent of the set.
+    /// A node is thus counted for at most one of the competing
+    /// sets so two of them cannot be valid at the same time.
+    ///
     /// This function will panic if any event in
     /// the `CandidateSet` is not residing in the
     /// provided `CausalGraph`.
-    pub fn count_votes(&mut self, causal_graph: Arc<CausalGraph>) {
-        unimplemented!();
+    pub fn count_votes(&mut self, causal_graph: &CausalGraph, node_count: u16) {
+        for event in self.events.iter() {
+            if !causal_graph.contains(event.clone()) {
+                panic!("The candidate set contains an event that is not in the causal graph!");
+            }
+        }
+
+        self.node_count = node_count;
+        self.voters.clear();
+        self.issued.clear();
+        self.inside.clear();
+        self.outside.clear();
+        self.followers.clear();
+        self.voting_followers.clear();
+        self.voter_hashes.clear();
+        self.voting_nodes.clear();
+        self.proposing_nodes.clear();
+
+        let level = self.level(causal_graph);
+        let mut following = Vec::new();
+
+        self.excluded = equivocators(&level);
+
+        for event in level.into_iter() {
+            let node_id = event.node_id();
+            let ancestors = self.ancestors(causal_graph, &event);
+
+            if ancestors.is_some() || self.contains(&event) {
+                self.inside.insert(node_id.clone());
+            } else {
+                self.outside.insert(node_id.clone());
+            }
+
+            if let Some(ancestors) = ancestors {
+                following.push((event.clone(), ancestors));
+            }
+
+            self.issued
+                .entry(node_id)
+                .or_insert_with(Vec::new)
+                .push(event);
+        }
+
+        let switched: Vec<NodeId> = self.inside.intersection(&self.outside).cloned().collect();
+        self.excluded.extend(switched);
+
+        for (event, ancestors) in following {
+            self.count_follower(&event, ancestors);
+        }
+
+        self.votes = self.voting_nodes.len() as u16;
+        self.proposals = self.proposing_nodes.len() as u16;
+    }
+
+    /// Counts the votes and proposals brought by an event
+    /// that has just been pushed to the `CausalGraph` and
+    /// that follows the parent of the `CandidateSet`.
+    ///
+    /// Only the events that the new event follows are counted
+    /// again, unless its node has to be excluded in which case
+    /// all of the votes are counted again.
+    pub fn add_event(&mut self, causal_graph: &CausalGraph, event: &Arc<Event>) {
+        let node_id = event.node_id();
+        let ancestors = self.ancestors(causal_graph, event);
+        let forked = self.issued.get(&node_id).map_or(false, |issued| {
+            issued.iter().any(|e| e.stamp().concurrent(event.stamp()))
+        });
+
+        self.issued
+            .entry(node_id.clone())
+            .or_insert_with(Vec::new)
+            .push(event.clone());
+
+        if ancestors.is_some() || self.contains(event) {
+            self.inside.insert(node_id.clone());
+        } else {
+            self.outside.insert(node_id.clone());
+        }
+
+        let switched = self.inside.contains(&node_id) && self.outside.contains(&node_id);
+
+        if (forked || switched) && !self.excluded.contains(&node_id) {
+            // The node was counted until now
+            let node_count = self.node_count;
+            self.count_votes(causal_graph, node_count);
+            return;
+        }
+
+        if let Some(ancestors) = ancestors {
+            self.count_follower(event, ancestors);
+            self.votes = self.voting_nodes.len() as u16;
+            self.proposals = self.proposing_nodes.len() as u16;
+        }
+    }
+
+    /// Returns `true` if the given event is part of the set.
+    fn contains(&self, event: &Arc<Event>) -> bool {
+        self.events.iter().any(|e| e.hash() == event.hash())
+    }
+
+    /// Returns the events that follow the parent of the set.
+    ///
+    /// If the parent is not in the causal graph, these are
+    /// the first event of the set and its followers.
+    fn level(&self, causal_graph: &CausalGraph) -> Vec<Arc<Event>> {
+        let first = match self.events.first() {
+            Some(first) => first,
+            None => return Vec::new(),
+        };
+
+        match first.parent_hash() {
+            Some(ref parent_hash) if causal_graph.contains_hash(parent_hash) => {
+                causal_graph.followers(parent_hash)
+            }
+            _ => {
+                let mut level = causal_graph.followers(&first.hash().unwrap());
+                level.push(first.clone());
+                level
+            }
+        }
+    }
+
+    /// Returns the events that follow the set and that are
+    /// followed by the given event, starting with its parent.
+    ///
+    /// Returns `None` if the given event does not follow
+    /// every event in the set.
+    fn ancestors(&self, causal_graph: &CausalGraph, event: &Arc<Event>) -> Option<Vec<Arc<Event>>> {
+        let root = self.events.first()?.parent_hash();
+        let mut ancestors = Vec::new();
+        let mut found = 0;
+        let mut current = event.clone();
+
+        while found < self.events.len() {
+            let parent_hash = current.parent_hash()?;
+
+            // Competing sets share the parent of the set
+            if root.as_ref() == Some(&parent_hash) {
+                return None;
+            }
+
+            let parent = causal_graph.get(&parent_hash)?;
+
+            if self.contains(&parent) {
+                found += 1;
+            } else if found == 0 {
+                ancestors.push(parent.clone());
+            }
+
+            current = parent;
+        }
+
+        Some(ancestors)
+    }
+
+    /// Records the node of an event following the set as a
+    /// follower of the events that it follows, which may in
+    /// turn become voters.
+    fn count_follower(&mut self, event: &Arc<Event>, ancestors: Vec<Arc<Event>>) {
+        let node_id = event.node_id();
+
+        for ancestor in ancestors.iter() {
+            if ancestor.node_id() != node_id {
+                self.followers
+                    .entry(ancestor.hash().unwrap())
+                    .or_insert_with(HashSet::new)
+                    .insert(node_id.clone());
+            }
+        }
+
+        for (i, ancestor) in ancestors.iter().enumerate() {
+            self.count_voter(ancestor, &ancestors[i + 1..]);
+        }
+
+        self.count_voter(event, &ancestors);
+    }
+
+    /// Marks an event as a voter if it is followed by enough
+    /// distinct nodes and records its node as a voting
+    /// follower of the events that it follows.
+    fn count_voter(&mut self, event: &Arc<Event>, ancestors: &[Arc<Event>]) {
+        let hash = event.hash().unwrap();
+        let node_id = event.node_id();
+
+        if self.voter_hashes.contains(&hash) || self.excluded.contains(&node_id) {
+            return;
+        }
+
+        let followers = self.followers.get(&hash).map_or(0, |f| f.len()) as u16;
+
+        if followers < eligibility_requirement(self.node_count) {
+            return;
+        }
+
+        self.voter_hashes.insert(hash);
+        self.voters.push(event.clone());
+        self.voting_nodes.insert(node_id.clone());
+        self.count_proposer(event);
+
+        for ancestor in ancestors.iter() {
+            if ancestor.node_id() != node_id {
+                self.voting_followers
+                    .entry(ancestor.hash().unwrap())
+                    .or_insert_with(HashSet::new)
+                    .insert(node_id.clone());
+                self.count_proposer(ancestor);
+            }
+        }
+    }
+
+    /// Marks the node of a voting event as a proposer if the
+    /// event is followed by enough distinct voting nodes.
+    fn count_proposer(&mut self, event: &Arc<Event>) {
+        let hash = event.hash().unwrap();
+
+        if !self.voter_hashes.contains(&hash) {
+            return;
+        }
+
+        let voting_followers = self.voting_followers.get(&hash).map_or(0, |f| f.len()) as u16;
+
+        if voting_followers >= proposal_requirement(self.node_count) {
+            self.proposing_nodes.insert(event.node_id());
+        }
+    }
+}
+
+/// Returns the ids of the nodes that have issued two of
+/// the given events whose stamps are concurrent.
+///
+/// Each event of an honest node happens after its previous
+/// one so concurrent events of the same node are a fork of
+/// its own history.
+fn equivocators(events: &[Arc<Event>]) -> HashSet<NodeId> {
+    let mut by_node: HashMap<NodeId, Vec<&Arc<Event>>> = HashMap::new();
+
+    for event in events.iter() {
+        by_node
+            .entry(event.node_id())
+            .or_insert_with(Vec::new)
+            .push(event);
+    }
+
+    by_node
+        .into_iter()
+        .filter(|(_, events)| {
+            events.iter().enumerate().any(|(i, e)| {
+                events[i + 1..]
+                    .iter()
+                    .any(|o| e.stamp().concurrent(o.stamp()))
+            })
+        })
+        .map(|(node_id, _)| node_id)
+        .collect()
+}
+
+/// Returns the number of distinct nodes, other than the
+/// owner of the given event, that have issued events
+/// following it which match the given condition.
+pub(crate) fn distinct_followers<F>(causal_graph: &CausalGraph, event: &Arc<Event>, fun: F) -> u16
+where
+    F: Fn(&Arc<Event>) -> bool,
+{
+    let node_id = event.node_id();
+    let nodes: HashSet<NodeId> = causal_graph
+        .followers(&event.hash().unwrap())
+        .iter()
+        .filter(|e| e.node_id() != node_id && fun(e))
+        .map(|e| e.node_id())
+        .collect();
+
+    nodes.len() as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use causality::Stamp;
+    use crypto::Identity;
+    use rand::{thread_rng, Rng};
+
+    fn node_ids(count: usize) -> Vec<NodeId> {
+        (0..count)
+            .map(|_| NodeId(*Identity::new().pkey()))
+            .collect()
+    }
+
+    /// Returns a chain of events following the event with
+    /// the given hash that are issued in turn by each of the
+    /// given nodes.
+    fn chain(parent: &Hash, nodes: &[NodeId], len: usize) -> Vec<Arc<Event>> {
+        let mut parent = parent.clone();
+        let mut events = Vec::with_capacity(len);
+
+        for i in 0..len {
+            let hash = Hash::random();
+            let node_id = nodes[i % nodes.len()].clone();
+
+            events.push(Arc::new(Event::Dummy(
+                node_id,
+                hash.clone(),
+                Some(parent),
+                Stamp::seed(),
+            )));
+            parent = hash;
+        }
+
+        events
+    }
+
+    fn causal_graph(root: Arc<Event>, events: &[Arc<Event>]) -> CausalGraph {
+        let mut cg = CausalGraph::new(root.node_id(), root);
+        let mut events = events.to_vec();
+
+        // The causal graph should be the same regardless
+        // of the order in which the events are pushed.
+        thread_rng().shuffle(&mut events);
+
+        for e in events {
+            cg.push(e);
+        }
+
+        cg
+    }
+
+    #[test]
+    /// Causal graph structure:
+    ///
+    /// A -> B -> C1 -> C2 -> ... -> C12
+    ///
+    /// The events C1..C12 are issued in turn by all
+    /// four nodes so the set {A, B} is valid.
+    fn it_counts_votes_of_honest_voters() {
+        let n = node_ids(4);
+        let A_hash = Hash::random();
+        let B_hash = Hash::random();
+        let A = Arc::new(Event::Dummy(
+            n[0].clone(),
+            A_hash.clone(),
+            None,
+            Stamp::seed(),
+        ));
+        let B = Arc::new(Event::Dummy(
+            n[1].clone(),
+            B_hash.clone(),
+            Some(A_hash),
+            Stamp::seed(),
+        ));
+        let C = chain(
+            &B_hash,
+            &[n[2].clone(), n[3].clone(), n[0].clone(), n[1].clone()],
+            12,
+        );
+
+        let mut events = vec![B.clone()];
+        events.extend_from_slice(&C);
+
+        let cg = causal_graph(A.clone(), &events);
+        let mut set = CandidateSet::new(&[A.clone(), B.clone()]);
+        set.count_votes(&cg, 4);
+
+        // The last two events are not followed by
+        // enough distinct nodes in order to vote.
+        assert_eq!(set.voters.len(), 10);
+        assert!(set.voters.iter().all(|v| C[..10].contains(v)));
+
+        // Each node is counted once
+        assert_eq!(set.votes, 4);
+        assert_eq!(set.proposals, 4);
+        assert!(set.is_valid(4));
+
+        // Three voters of distinct nodes are followed
+        // by enough distinct voters in order to propose.
+        let cg = causal_graph(A.clone(), &events[..8]);
+        let mut set = CandidateSet::new(&[A.clone(), B.clone()]);
+        set.count_votes(&cg, 4);
+
+        assert_eq!(set.proposals, 3);
+        assert!(set.is_valid(4));
+
+        let cg = causal_graph(A.clone(), &events[..7]);
+        let mut set = CandidateSet::new(&[A, B]);
+        set.count_votes(&cg, 4);
+
+        assert_eq!(set.proposals, 2);
+        assert!(!set.is_valid(4));
+    }
+
+    #[test]
+    /// Causal graph structure:
+    ///
+    /// A -> B -> C1 -> C2 -> ... -> C12
+    ///      |
+    ///      D1 -> D2 -> ... -> D12
+    ///
+    /// The C events are issued by the first two nodes and
+    /// the D events by the other two so neither side of the
+    /// partition can vote for the set {A, B}.
+    fn it_does_not_count_votes_of_partitioned_voters() {
+        let n = node_ids(4);
+        let A_hash = Hash::random();
+        let B_hash = Hash::random();
+        let A = Arc::new(Event::Dummy(
+            n[0].clone(),
+            A_hash.clone(),
+            None,
+            Stamp::seed(),
+        ));
+        let B = Arc::new(Event::Dummy(
+            n[1].clone(),
+            B_hash.clone(),
+            Some(A_hash),
+            Stamp::seed(),
+        ));
+        let C = chain(&B_hash, &[n[0].clone(), n[1].clone()], 12);
+        let D = chain(&B_hash, &[n[2].clone(), n[3].clone()], 12);
+
+        let mut events = vec![B.clone()];
+        events.extend_from_slice(&C);
+        events.extend_from_slice(&D);
+
+        let cg = causal_graph(A.clone(), &events);
+        let mut set = CandidateSet::new(&[A.clone(), B.clone()]);
+        set.count_votes(&cg, 4);
+
+        assert_eq!(set.votes, 0);
+        assert_eq!(set.proposals, 0);
+        assert!(set.voters.is_empty());
+        assert!(!set.is_valid(4));
+
+        // Only the events following the whole
+        // set are allowed to vote for it.
+        let mut set = CandidateSet::new(&[A, B, C[0].clone(), D[0].clone()]);
+        set.count_votes(&cg, 4);
+
+        assert_eq!(set.votes, 0);
+        assert!(!set.is_valid(4));
+    }
+
+    #[test]
+    /// Causal graph structure:
+    ///
+    /// A -> B -> C1 -> C2 -> ... -> C12
+    ///      |
+    ///      F -> G1 -> G2 -> ... -> G6
+    ///
+    /// The fourth node is byzantine and issues the event F,
+    /// which is concurrent with its event C1, and both are
+    /// followed by events of the other nodes. None of the
+    /// events of the fourth node must be counted as votes.
+    fn it_does_not_count_votes_of_byzantine_voters() {
+        let n = node_ids(4);
+        let A_hash = Hash::random();
+        let B_hash = Hash::random();
+        let C1_hash = Hash::random();
+        let F_hash = Hash::random();
+        let (s_c, s_f) = Stamp::seed().fork();
+        let A = Arc::new(Event::Dummy(
+            n[0].clone(),
+            A_hash.clone(),
+            None,
+            Stamp::seed(),
+        ));
+        let B = Arc::new(Event::Dummy(
+            n[1].clone(),
+            B_hash.clone(),
+            Some(A_hash),
+            Stamp::seed(),
+        ));
+        let C1 = Arc::new(Event::Dummy(
+            n[3].clone(),
+            C1_hash.clone(),
+            Some(B_hash.clone()),
+            s_c.event(),
+        ));
+        let C = chain(
+            &C1_hash,
+            &[n[2].clone(), n[0].clone(), n[1].clone(), n[3].clone()],
+            11,
+        );
+        let F = Arc::new(Event::Dummy(
+            n[3].clone(),
+            F_hash.clone(),
+            Some(B_hash),
+            s_f.event(),
+        ));
+        let G = chain(&F_hash, &[n[0].clone(), n[1].clone(), n[2].clone()], 6);
+
+        assert!(C1.stamp().concurrent(F.stamp()));
+
+        let mut honest_events = vec![B.clone(), C1.clone()];
+        honest_events.extend_from_slice(&C);
+
+        let mut events = honest_events.clone();
+        events.push(F.clone());
+        events.extend_from_slice(&G);
+
+        let honest_cg = causal_graph(A.clone(), &honest_events);
+        let cg = causal_graph(A.clone(), &events);
+        let mut honest_set = CandidateSet::new(&[A.clone(), B.clone()]);
+        let mut set = CandidateSet::new(&[A.clone(), B.clone()]);
+        honest_set.count_votes(&honest_cg, 4);
+        set.count_votes(&cg, 4);
+
+        assert_eq!(honest_set.votes, 4);
+        assert!(honest_set.voters.contains(&C1));
+
+        // Both conflicting events are followed
+        // by enough distinct nodes in order to vote.
+        assert!(distinct_followers(&cg, &C1, |_| true) >= eligibility_requirement(4));
+        assert!(distinct_followers(&cg, &F, |_| true) >= eligibility_requirement(4));
+
+        assert_eq!(set.votes, 3);
+        assert_eq!(set.proposals, 3);
+        assert!(set.voters.iter().all(|v| v.node_id() != n[3]));
+        assert!(set.voters.contains(&G[0]));
+        assert!(set.is_valid(4));
+
+        // A byzantine node cannot vote on its own
+        // regardless of how many events it issues.
+        let G_hash = Hash::random();
+        let G = Arc::new(Event::Dummy(
+            n[3].clone(),
+            G_hash.clone(),
+            None,
+            Stamp::seed(),
+        ));
+        let H: Vec<Arc<Event>> = (0..12)
+            .map(|_| {
+                Arc::new(Event::Dummy(
+                    n[3].clone(),
+                    Hash::random(),
+                    Some(G_hash.clone()),
+                    Stamp::seed(),
+                ))
+            })
+            .collect();
+
+        let cg = causal_graph(G.clone(), &H);
+        let mut set = CandidateSet::new(&[G]);
+        set.count_votes(&cg, 4);
+
+        assert_eq!(set.votes, 0);
+        assert!(!set.is_valid(4));
+    }
+
+    #[test]
+    /// Causal graph structure:
+    ///
+    /// A -> X -> C1 -> C2 -> ... -> C16
+    /// |
+    /// Y -> D1 -> D2
+    ///
+    /// The second node issues the events that follow Y after
+    /// some of its events following X have been counted. The
+    /// votes counted as the events are pushed should be the
+    /// same as the votes counted all at once.
+    fn it_counts_votes_incrementally() {
+        let n = node_ids(4);
+        let A_hash = Hash::random();
+        let X_hash = Hash::random();
+        let Y_hash = Hash::random();
+        let A = Arc::new(Event::Dummy(
+            n[0].clone(),
+            A_hash.clone(),
+            None,
+            Stamp::seed(),
+        ));
+        let X = Arc::new(Event::Dummy(
+            n[0].clone(),
+            X_hash.clone(),
+            Some(A_hash.clone()),
+            Stamp::seed(),
+        ));
+        let Y = Arc::new(Event::Dummy(
+            n[1].clone(),
+            Y_hash.clone(),
+            Some(A_hash),
+            Stamp::seed(),
+        ));
+        let C = chain(
+            &X_hash,
+            &[n[2].clone(), n[3].clone(), n[0].clone(), n[1].clone()],
+            16,
+        );
+        let D = chain(&Y_hash, &[n[1].clone()], 2);
+
+        let mut events = vec![X.clone()];
+        events.extend_from_slice(&C[..8]);
+        events.push(Y);
+        events.extend_from_slice(&D);
+        events.extend_from_slice(&C[8..]);
+
+        let mut cg = CausalGraph::new(A.node_id(), A);
+        let mut set = CandidateSet::new(&[X.clone()]);
+        cg.push(X.clone());
+        set.count_votes(&cg, 4);
+
+        for e in events[1..].iter() {
+            cg.push(e.clone());
+            set.add_event(&cg, e);
+
+            let mut counted = CandidateSet::new(&[X.clone()]);
+            counted.count_votes(&cg, 4);
+
+            let voters: HashSet<Hash> = set.voters.iter().map(|v| v.hash().unwrap()).collect();
+            let counted_voters: HashSet<Hash> =
+                counted.voters.iter().map(|v| v.hash().unwrap()).collect();
+
+            assert_eq!(set.votes, counted.votes);
+            assert_eq!(set.proposals, counted.proposals);
+            assert_eq!(voters, counted_voters);
+        }
+
+        // The second node is no longer counted
+        assert!(set.voters.iter().all(|v| v.node_id() != n[1]));
+        assert_eq!(set.votes, 3);
+        assert!(set.is_valid(4));
     }
 }
@@ -24,6 +24,10 @@ use network::NodeId;
 use std::collections::VecDeque;
 use std::sync::Arc;
 
+/// The maximum number of pruned events
+/// that are still recognized.
+const MAX_PRUNED: usize = 1 << 16;
+
 #[derive(Clone, Debug)]
 pub struct CausalGraph {
     /// Graph structure holding the causal graph
@@ -52,6 +56,15 @@ pub struct CausalGraph {
     /// event in the graph and the number of events that it 
     /// follows.
     highest_following: (Vec<Arc<Event>>, usize),
+
+    /// The first 8 bytes of the hashes of the
+    /// events that have been pruned.
+    pruned: HashSet<u64>,
+
+    /// The compact hashes of the pruned events in the
+    /// order in which they have been pruned, so that the
+    /// oldest ones are forgotten past `MAX_PRUNED`.
+    pruned_order: VecDeque<u64>,
 }
 
 impl CausalGraph {
@@ -72,6 +85,8 @@ impl CausalGraph {
             pending: HashSet::new(),
             highest: (vec![root_event], 0),
             highest_following: (vec![], 0),
+            pruned: HashSet::new(),
+            pruned_order: VecDeque::new(),
         }
     }
 
@@ -94,6 +109,198 @@ impl CausalGraph {
         self.lookup_table.get(&event.hash().unwrap()).is_some()
     }
 
+    /// Returns `true` if the event with the given hash
+    /// is in the causal graph.
+    pub fn contains_hash(&self, hash: &Hash) -> bool {
+        self.lookup_table.contains_key(hash)
+    }
+
+    /// Returns `true` if the event with the given hash
+    /// has been pruned from the causal graph.
+    pub fn is_pruned(&self, hash: &Hash) -> bool {
+        self.pruned.contains(&compact_hash(hash))
+    }
+
+    /// Removes the events that do not follow the event with
+    /// the given hash, which becomes the root of the causal
+    /// graph, and returns the hashes of the removed events.
+    ///
+    /// This is meant to be called with the last finalized
+    /// event, as only the events that follow it are still
+    /// needed in order to count votes. The hashes of the
+    /// last `MAX_PRUNED` removed events are kept in a compact
+    /// form so that they are still recognized.
+    pub fn prune(&mut self, root_hash: &Hash) -> Vec<Hash> {
+        let root_id = match self.lookup_table.get(root_hash) {
+            Some(id) => id.clone(),
+            None => return Vec::new(),
+        };
+
+        let mut keep: HashSet<VertexId> = HashSet::new();
+        let mut to_traverse = vec![root_id.clone()];
+
+        while let Some(id) = to_traverse.pop() {
+            to_traverse.extend(self.graph.out_neighbors(&id).cloned());
+            keep.insert(id);
+        }
+
+        // Pending events are not yet attached so they
+        // can only follow other pending events.
+        let to_remove: Vec<(Hash, VertexId)> = self.lookup_table
+            .iter()
+            .filter(|(_, id)| !keep.contains(*id) && !self.pending.contains(*id))
+            .map(|(hash, id)| (hash.clone(), id.clone()))
+            .collect();
+
+        for (hash, id) in to_remove.iter() {
+            self.lookup_table.remove(hash);
+            self.ends.remove(id);
+            self.graph.remove(id);
+            self.remember_pruned(hash);
+        }
+
+        self.recompute_highest(&root_id);
+
+        to_remove.into_iter().map(|(hash, _)| hash).collect()
+    }
+
+    /// Records the hash of a pruned event and forgets
+    /// about the oldest one past `MAX_PRUNED`.
+    fn remember_pruned(&mut self, hash: &Hash) {
+        let compact = compact_hash(hash);
+
+        if self.pruned.insert(compact) {
+            self.pruned_order.push_back(compact);
+        }
+
+        if self.pruned_order.len() > MAX_PRUNED {
+            let oldest = self.pruned_order.pop_front().unwrap();
+            self.pruned.remove(&oldest);
+        }
+    }
+
+    /// Recomputes the cached highest events by
+    /// traversing the graph from the given root.
+    fn recompute_highest(&mut self, root_id: &VertexId) {
+        let mut depths: Vec<(VertexId, usize)> = Vec::new();
+        let mut to_traverse = vec![(root_id.clone(), 0)];
+
+        while let Some((id, depth)) = to_traverse.pop() {
+            to_traverse.extend(self.graph.out_neighbors(&id).map(|n| (n.clone(), depth + 1)));
+            depths.push((id, depth));
+        }
+
+        // The ends know the number of events that they
+        // follow, from which we find the one of the root.
+        let root_following = depths
+            .iter()
+            .filter_map(|(id, depth)| self.ends.get(id).map(|following| following - depth))
+            .next()
+            .unwrap_or(0);
+
+        let mut highest: (Vec<Arc<Event>>, usize) = (vec![], 0);
+        let mut highest_following: (Vec<Arc<Event>>, usize) = (vec![], 0);
+
+        for (id, depth) in depths {
+            let following = root_following + depth;
+            let event = self.graph.fetch(&id).unwrap().clone();
+
+            if highest.0.is_empty() || following > highest.1 {
+                highest = (vec![event.clone()], following);
+            } else if following == highest.1 {
+                highest.0.push(event.clone());
+            }
+
+            if following == 0 || event.node_id() == self.node_id {
+                continue;
+            }
+
+            if following > highest_following.1 {
+                highest_following = (vec![event], following);
+            } else if following == highest_following.1 {
+                highest_following.0.push(event);
+            }
+        }
+
+        self.highest = highest;
+        self.highest_following = highest_following;
+    }
+
+    /// Returns the event with the given hash.
+    pub fn get(&self, hash: &Hash) -> Option<Arc<Event>> {
+        let id = self.lookup_table.get(hash)?;
+        self.graph.fetch(id).cloned()
+    }
+
+    /// Returns the hashes of the parents of the pending
+    /// events that are not in the causal graph.
+    pub fn missing_parents(&self) -> Vec<Hash> {
+        let mut missing = HashSet::new();
+
+        for id in self.pending.iter() {
+            let event = self.graph.fetch(id).unwrap();
+
+            if let Some(parent_hash) = event.parent_hash() {
+                if !self.lookup_table.contains_key(&parent_hash) {
+                    missing.insert(parent_hash);
+                }
+            }
+        }
+
+        missing.into_iter().collect()
+    }
+
+    /// Returns at most `count` events that follow the event
+    /// with the given hash, in breadth-first order so that
+    /// parents always come before their children.
+    pub fn following(&self, hash: &Hash, count: usize) -> Vec<Arc<Event>> {
+        let mut result = Vec::with_capacity(count);
+        let mut queue: VecDeque<VertexId> = match self.lookup_table.get(hash) {
+            Some(id) => self.graph.out_neighbors(id).cloned().collect(),
+            None => return result,
+        };
+
+        while let Some(id) = queue.pop_front() {
+            if result.len() >= count {
+                break;
+            }
+
+            result.push(self.graph.fetch(&id).unwrap().clone());
+            queue.extend(self.graph.out_neighbors(&id).cloned());
+        }
+
+        result
+    }
+
+    /// Returns the events that directly follow the
+    /// event with the given hash in the causal graph.
+    pub(crate) fn direct_followers(&self, hash: &Hash) -> Vec<Arc<Event>> {
+        match self.lookup_table.get(hash) {
+            Some(id) => self.graph
+                .out_neighbors(id)
+                .map(|n| self.graph.fetch(n).unwrap().clone())
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Returns all of the events that follow the
+    /// event with the given hash in the causal graph.
+    pub(crate) fn followers(&self, hash: &Hash) -> Vec<Arc<Event>> {
+        let mut result = Vec::new();
+        let mut to_traverse: Vec<VertexId> = match self.lookup_table.get(hash) {
+            Some(id) => self.graph.out_neighbors(id).cloned().collect(),
+            None => return result,
+        };
+
+        while let Some(id) = to_traverse.pop() {
+            result.push(self.graph.fetch(&id).unwrap().clone());
+            to_traverse.extend(self.graph.out_neighbors(&id).cloned());
+        }
+
+        result
+    }
+
     pub fn push(&mut self, event: Arc<Event>) {
         if event.parent_hash().is_none() {
             panic!("Pushing an event without a parent hash is illegal!");
@@ -196,11 +403,8 @@ impl CausalGraph {
         if highest.len() == 1 {
             highest[0].clone()
         } else {
-            // Pick one of the highest events at random
-            // TODO: Use a deterministic random function here:
-            // drf(&highest)
-
-            highest[0].clone()
+            // Break the tie the same way on every node
+            drf(&highest)
         }
     }
 
@@ -210,10 +414,8 @@ impl CausalGraph {
         let highest = if highest.len() == 1 {
             highest[0].clone()
         } else {
-            // Pick one of the highest events at random
-            // TODO: Use a deterministic random function here:
-            // drf(&highest)
-            highest[0].clone()
+            // Break the tie the same way on every node
+            drf(&highest)
         };
 
         if highest.node_id() != *node_id {
@@ -223,7 +425,8 @@ impl CausalGraph {
         if highest.parent_hash().is_none() {
             None
         } else {
-            let id = self.lookup_table.get(&highest.parent_hash().unwrap()).unwrap();
+            // The parent may have been pruned
+            let id = self.lookup_table.get(&highest.parent_hash().unwrap())?;
             let event = self.graph.fetch(id).unwrap();
 
             if event.node_id() == *node_id {
@@ -242,11 +445,8 @@ impl CausalGraph {
         } else if highest_following.len() == 1 {
             Some(highest_following[0].clone())
         } else {
-            // Pick one of the highest following events at random
-            // TODO: Use a deterministic random function here:
-            // Some(drf(&highest_following))
-
-            Some(highest_following[0].clone())
+            // Break the tie the same way on every node
+            Some(drf(&highest_following))
         }
     }
 
@@ -326,6 +526,39 @@ impl CausalGraph {
     }
 }
 
+/// Returns the first 8 bytes of the given hash.
+fn compact_hash(hash: &Hash) -> u64 {
+    hash.0[..8].iter().fold(0u64, |acc, byte| (acc << 8) | *byte as u64)
+}
+
+/// Deterministic random function which picks one of the
+/// given events.
+///
+/// The pick is seeded by the hash of the sorted hashes of
+/// the events so that every node picks the same event
+/// regardless of the order in which it received them.
+fn drf(events: &[Arc<Event>]) -> Arc<Event> {
+    let mut hashes: Vec<Hash> = events.iter().map(|e| e.hash().unwrap()).collect();
+    let mut buf: Vec<u8> = Vec::with_capacity(hashes.len() * 32);
+
+    hashes.sort();
+    hashes.dedup();
+
+    for hash in hashes.iter() {
+        buf.extend_from_slice(&hash.0);
+    }
+
+    let seed = crypto::hash_slice(&buf);
+    let seed = seed.0[..8].iter().fold(0u64, |acc, byte| (acc << 8) | *byte as u64);
+    let picked = hashes[(seed % hashes.len() as u64) as usize];
+
+    events
+        .iter()
+        .find(|e| e.hash().unwrap() == picked)
+        .unwrap()
+        .clone()
+}
+
 #[cfg(test)]
 mod tests {
     #[macro_use]
@@ -395,6 +628,155 @@ mod tests {
         assert_eq!(cg.compute_highest_following(&n1, A), Some(D));
     }
 
+    #[test]
+    fn it_forgets_the_oldest_pruned_events() {
+        let n = NodeId(*Identity::new().pkey());
+        let A = Arc::new(Event::Dummy(n.clone(), Hash::random(), None, Stamp::seed()));
+        let mut cg = CausalGraph::new(n, A);
+        let hashes: Vec<Hash> = (0..MAX_PRUNED + 1).map(|_| Hash::random()).collect();
+
+        for hash in hashes.iter() {
+            cg.remember_pruned(hash);
+        }
+
+        assert!(!cg.is_pruned(&hashes[0]));
+        assert!(hashes[1..].iter().all(|h| cg.is_pruned(h)));
+        assert_eq!(cg.pruned.len(), MAX_PRUNED);
+        assert_eq!(cg.pruned_order.len(), MAX_PRUNED);
+    }
+
+    #[test]
+    fn drf_is_independent_of_order() {
+        let i = Identity::new();
+        let n = NodeId(*i.pkey());
+        let mut events: Vec<Arc<Event>> = (0..10)
+            .map(|_| Arc::new(Event::Dummy(n.clone(), Hash::random(), None, Stamp::seed())))
+            .collect();
+
+        let picked = drf(&events);
+
+        for _ in 0..10 {
+            thread_rng().shuffle(&mut events);
+            assert_eq!(drf(&events), picked);
+        }
+
+        // A different set of tied events yields a different seed
+        let mut picks = HashSet::new();
+
+        for i in 0..10 {
+            picks.insert(drf(&events[i..]).hash().unwrap());
+        }
+
+        assert!(picks.len() > 1);
+    }
+
+    #[test]
+    /// Causal graph structure:
+    ///
+    /// A -> B1
+    /// |
+    /// B2
+    /// |
+    /// ...
+    /// |
+    /// B6
+    ///
+    /// All B events are tied as the highest events and
+    /// every causal graph should pick the same one.
+    fn it_breaks_ties_deterministically() {
+        let nodes: Vec<NodeId> = (0..7).map(|_| NodeId(*Identity::new().pkey())).collect();
+        let A_hash = Hash::random();
+        let A = Arc::new(Event::Dummy(nodes[0].clone(), A_hash.clone(), None, Stamp::seed()));
+        let B: Vec<Arc<Event>> = nodes[1..]
+            .iter()
+            .map(|n| Arc::new(Event::Dummy(n.clone(), Hash::random(), Some(A_hash.clone()), Stamp::seed())))
+            .collect();
+
+        let mut picked = Vec::new();
+
+        for _ in 0..10 {
+            let mut events = B.clone();
+            let mut cg = CausalGraph::new(nodes[0].clone(), A.clone());
+
+            // The picked events should be the same regardless
+            // of the order in which the events are pushed.
+            thread_rng().shuffle(&mut events);
+
+            for e in events {
+                cg.push(e);
+            }
+
+            let highest = cg.highest();
+
+            assert!(B.contains(&highest));
+            assert_eq!(cg.highest_exclusive(&nodes[0]), Some(highest.clone()));
+            assert_eq!(cg.highest_exclusive(&highest.node_id()), Some(A.clone()));
+
+            picked.push((highest, cg.highest_following().unwrap()));
+        }
+
+        assert!(picked.iter().all(|p| *p == picked[0]));
+    }
+
+    #[test]
+    /// Causal graph structure:
+    ///
+    /// A -> B -> C -> D -> E
+    /// |
+    /// -> X1 -> X2 -> X3 -> X4
+    ///
+    /// The graph is pruned at B, after which E is pushed.
+    fn it_prunes_events() {
+        let nodes: Vec<NodeId> = (0..3).map(|_| NodeId(*Identity::new().pkey())).collect();
+        let A_hash = Hash::random();
+        let A = Arc::new(Event::Dummy(nodes[0].clone(), A_hash.clone(), None, Stamp::seed()));
+        let mut main = vec![A.clone()];
+        let mut branch = vec![A.clone()];
+
+        for i in 0..3 {
+            let parent_hash = main.last().unwrap().hash();
+            let node = nodes[(i + 1) % 3].clone();
+            main.push(Arc::new(Event::Dummy(node, Hash::random(), parent_hash, Stamp::seed())));
+        }
+
+        for i in 0..4 {
+            let parent_hash = branch.last().unwrap().hash();
+            let node = nodes[(i + 2) % 3].clone();
+            branch.push(Arc::new(Event::Dummy(node, Hash::random(), parent_hash, Stamp::seed())));
+        }
+
+        let B = main[1].clone();
+        let D = main[3].clone();
+        let X4 = branch[4].clone();
+        let E = Arc::new(Event::Dummy(nodes[1].clone(), Hash::random(), D.hash(), Stamp::seed()));
+        let mut events: Vec<Arc<Event>> = main[1..].iter().chain(branch[1..].iter()).cloned().collect();
+        let mut cg = CausalGraph::new(nodes[0].clone(), A.clone());
+
+        thread_rng().shuffle(&mut events);
+
+        for e in events {
+            cg.push(e);
+        }
+
+        assert_eq!(cg.highest(), X4);
+        assert!(cg.prune(&Hash::random()).is_empty());
+        assert_eq!(cg.prune(&B.hash().unwrap()).len(), 5);
+        assert_eq!(cg.highest(), D);
+        assert_eq!(cg.highest_following(), Some(main[2].clone()));
+        assert!(cg.contains(B.clone()));
+        assert!(!cg.contains(A.clone()));
+        assert!(cg.is_pruned(&A.hash().unwrap()));
+        assert!(branch[1..].iter().all(|e| cg.is_pruned(&e.hash().unwrap()) && cg.get(&e.hash().unwrap()).is_none()));
+        assert!(!cg.is_pruned(&D.hash().unwrap()));
+
+        cg.push(E.clone());
+
+        assert_eq!(cg.highest(), E);
+        assert_eq!(cg.highest_following(), Some(E.clone()));
+        assert_eq!(cg.highest_exclusive(&nodes[0]), Some(E.clone()));
+        assert_eq!(cg.highest_exclusive(&E.node_id()), Some(D));
+    }
+
     quickcheck! {
         fn is_direct_follower() -> bool {
             let i1 = Identity::new();
@@ -0,0 +1,312 @@
+/*
+  Copyright 2018 The Purple Library Authors
+  This file is part of the Purple Library.
+
+  The Purple Library is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  The Purple Library is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with the Purple Library. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use crypto::Hash;
+use events::Event;
+use hashbrown::HashMap;
+use hashdb::HashDB;
+use persistence::PersistentDb;
+use std::fmt;
+use std::io::Cursor;
+use std::mem;
+use std::sync::Arc;
+
+/// Stores the state of the consensus machine in a
+/// dedicated column of the database.
+///
+/// Each event is stored under its hash and each event
+/// in the causal graph is referenced by a key formed
+/// by the hash of its parent and its own hash, so that
+/// the causal graph can be traversed from the last
+/// finalized event.
+///
+/// The finalized events are kept along with their
+/// position in the total order once they have been
+/// pruned so that they can be served to lagging nodes.
+///
+/// The changes are kept in a batch until `commit`
+/// is called, which writes them atomically.
+#[derive(Clone)]
+pub struct ConsensusDb {
+    db: PersistentDb,
+
+    /// The changes that are not yet written to the
+    /// database. A value of `None` is a removal.
+    batch: HashMap<Vec<u8>, Option<Vec<u8>>>,
+}
+
+impl fmt::Debug for ConsensusDb {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ConsensusDb")
+    }
+}
+
+impl ConsensusDb {
+    pub fn new(db: PersistentDb) -> ConsensusDb {
+        ConsensusDb {
+            db,
+            batch: HashMap::new(),
+        }
+    }
+
+    /// Returns the stored event with the given hash.
+    ///
+    /// Returns `None` if the stored event does
+    /// not match the given hash.
+    pub(crate) fn event(&self, hash: &Hash) -> Option<Arc<Event>> {
+        let bin = self.get(hash)?;
+        let event = Event::from_bytes(&bin).ok()?;
+
+        if event.hash().as_ref() != Some(hash) {
+            return None;
+        }
+
+        Some(Arc::new(event))
+    }
+
+    /// Stores an event. Returns `false` if the
+    /// event cannot be serialized.
+    pub(crate) fn put_event(&mut self, event: &Event) -> bool {
+        match (event.hash(), event.to_bytes()) {
+            (Some(hash), Ok(bin)) => {
+                self.put(hash.0.to_vec(), bin);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Removes the event with the given hash
+    /// along with the references to its children.
+    pub(crate) fn remove_event(&mut self, hash: &Hash) {
+        self.delete(hash.0.to_vec());
+        self.remove_children(hash);
+    }
+
+    /// Removes the references to the children
+    /// of the event with the given hash.
+    pub(crate) fn remove_children(&mut self, hash: &Hash) {
+        for key in self.children_keys(hash) {
+            self.delete(key);
+        }
+    }
+
+    /// Records the event with the given hash as the finalized
+    /// event at the given position in the total order. The
+    /// root event is at position 0.
+    pub(crate) fn put_finalized(&mut self, position: u64, hash: &Hash) {
+        let mut bin: Vec<u8> = Vec::with_capacity(8);
+        bin.write_u64::<BigEndian>(position).unwrap();
+
+        self.put(position_key(hash).0.to_vec(), bin);
+        self.put(finalized_key(position).0.to_vec(), hash.0.to_vec());
+    }
+
+    /// Returns the position in the total order of the
+    /// finalized event with the given hash.
+    pub(crate) fn finalized_position(&self, hash: &Hash) -> Option<u64> {
+        let bin = self.get(&position_key(hash))?;
+        Cursor::new(&bin[..]).read_u64::<BigEndian>().ok()
+    }
+
+    /// Returns the finalized event at the given
+    /// position in the total order.
+    pub(crate) fn finalized(&self, position: u64) -> Option<Arc<Event>> {
+        let bin = self.get(&finalized_key(position))?;
+
+        if bin.len() != 32 {
+            return None;
+        }
+
+        let mut hash = Hash::default();
+        hash.0.copy_from_slice(&bin);
+
+        self.event(&hash)
+    }
+
+    /// Returns the hashes of the stored children
+    /// of the event with the given hash.
+    pub(crate) fn children(&self, hash: &Hash) -> Vec<Hash> {
+        self.children_keys(hash)
+            .iter()
+            .filter(|key| key.len() == 64)
+            .map(|key| {
+                let mut child = Hash::default();
+                child.0.copy_from_slice(&key[32..]);
+                child
+            })
+            .collect()
+    }
+
+    /// References an event from its parent.
+    pub(crate) fn add_child(&mut self, event: &Event) {
+        let (hash, parent_hash) = match (event.hash(), event.parent_hash()) {
+            (Some(hash), Some(parent_hash)) => (hash, parent_hash),
+            _ => return,
+        };
+
+        let mut key = children_key(&parent_hash).0.to_vec();
+        key.extend_from_slice(&hash.0);
+
+        self.put(key, Vec::new());
+    }
+
+    /// Returns the stored state of the consensus machine.
+    pub(crate) fn state(&self) -> Option<Vec<u8>> {
+        self.get(&state_key())
+    }
+
+    /// Stores the state of the consensus machine.
+    pub(crate) fn put_state(&mut self, state: &[u8]) {
+        self.put(state_key().0.to_vec(), state.to_vec());
+    }
+
+    /// Writes the changes made since the last
+    /// commit in a single atomic transaction.
+    pub(crate) fn commit(&mut self) {
+        if self.batch.is_empty() {
+            return;
+        }
+
+        let batch = mem::replace(&mut self.batch, HashMap::new());
+        self.db.write_batch(batch);
+    }
+
+    fn get(&self, key: &Hash) -> Option<Vec<u8>> {
+        match self.batch.get(&key.0[..]) {
+            Some(val) => val.clone(),
+            None => self.db.get(key).map(|bin| bin.to_vec()),
+        }
+    }
+
+    fn put(&mut self, key: Vec<u8>, val: Vec<u8>) {
+        self.batch.insert(key, Some(val));
+    }
+
+    fn delete(&mut self, key: Vec<u8>) {
+        self.batch.insert(key, None);
+    }
+
+    /// Returns the keys that reference the children of
+    /// the event with the given hash, including the ones
+    /// in the batch.
+    fn children_keys(&self, hash: &Hash) -> Vec<Vec<u8>> {
+        let prefix = children_key(hash);
+        let mut keys: Vec<Vec<u8>> = self
+            .db
+            .prefixed(&prefix.0)
+            .into_iter()
+            .map(|(key, _)| key)
+            .filter(|key| !self.batch.contains_key(key))
+            .collect();
+
+        keys.extend(
+            self.batch
+                .iter()
+                .filter(|(key, val)| key.starts_with(&prefix.0) && val.is_some())
+                .map(|(key, _)| key.clone()),
+        );
+
+        keys
+    }
+}
+
+fn children_key(hash: &Hash) -> Hash {
+    let mut key = b"children".to_vec();
+    key.extend_from_slice(&hash.0);
+
+    crypto::hash_slice(&key)
+}
+
+fn position_key(hash: &Hash) -> Hash {
+    let mut key = b"position".to_vec();
+    key.extend_from_slice(&hash.0);
+
+    crypto::hash_slice(&key)
+}
+
+fn finalized_key(position: u64) -> Hash {
+    let mut key = b"finalized".to_vec();
+    key.write_u64::<BigEndian>(position).unwrap();
+
+    crypto::hash_slice(&key)
+}
+
+fn state_key() -> Hash {
+    crypto::hash_slice(b"consensus_state")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use causality::Stamp;
+    use kvdb_rocksdb::{Database, DatabaseConfig};
+    use network::NodeId;
+    use tempfile::tempdir;
+
+    #[test]
+    fn it_writes_the_changes_on_commit() {
+        let dir = tempdir().unwrap();
+        let config = DatabaseConfig::with_columns(None);
+        let db = Arc::new(Database::open(&config, dir.path().to_str().unwrap()).unwrap());
+        let storage = PersistentDb::new(db, None);
+        let mut consensus_db = ConsensusDb::new(storage.clone());
+
+        consensus_db.put_state(b"state");
+        consensus_db.put_finalized(1, &Hash::random());
+
+        // The changes are visible before they are written
+        assert_eq!(consensus_db.state(), Some(b"state".to_vec()));
+        assert!(ConsensusDb::new(storage.clone()).state().is_none());
+
+        consensus_db.commit();
+
+        assert_eq!(ConsensusDb::new(storage).state(), Some(b"state".to_vec()));
+    }
+
+    #[test]
+    fn it_stores_the_children_of_an_event() {
+        let dir = tempdir().unwrap();
+        let config = DatabaseConfig::with_columns(None);
+        let db = Arc::new(Database::open(&config, dir.path().to_str().unwrap()).unwrap());
+        let storage = PersistentDb::new(db, None);
+        let mut consensus_db = ConsensusDb::new(storage.clone());
+        let node_id = NodeId::new([0; 32]);
+        let parent_hash = Hash::random();
+        let children: Vec<Event> = (0..2)
+            .map(|_| Event::Dummy(node_id.clone(), Hash::random(), Some(parent_hash.clone()), Stamp::seed()))
+            .collect();
+
+        consensus_db.add_child(&children[0]);
+        consensus_db.commit();
+        consensus_db.add_child(&children[1]);
+
+        let mut expected: Vec<Hash> = children.iter().map(|e| e.hash().unwrap()).collect();
+        let mut stored = consensus_db.children(&parent_hash);
+        expected.sort();
+        stored.sort();
+
+        assert_eq!(stored, expected);
+
+        consensus_db.remove_children(&parent_hash);
+        consensus_db.commit();
+
+        assert!(ConsensusDb::new(storage).children(&parent_hash).is_empty());
+    }
+}
@@ -16,20 +16,96 @@
   along with the Purple Library. If not, see <http://www.gnu.org/licenses/>.
 */
 
-use crate::candidate_set::CandidateSet;
+use crate::candidate_set::{distinct_followers, CandidateSet};
 use crate::causal_graph::CausalGraph;
+use crate::consensus_db::ConsensusDb;
+use crate::parameters::*;
+use crate::pow;
 use crate::validator_state::ValidatorState;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use crypto::Hash;
 use events::Event;
+use hashbrown::{HashMap, HashSet};
 use network::NodeId;
 use parking_lot::{Mutex, RwLock};
+use std::io::Cursor;
+use std::sync::mpsc::{channel, Receiver, Sender};
 use std::sync::Arc;
 
-#[derive(Clone, Debug)]
+/// The maximum number of orphans that are kept aside.
+const MAX_ORPHANS: usize = 4096;
+
+/// The maximum number of orphans issued by the
+/// same node that are kept aside.
+const MAX_NODE_ORPHANS: usize = 256;
+
+#[derive(Clone, Debug, PartialEq)]
 pub enum CGError {
     AlreadyInCG,
     NoEventFound,
     NoCandidateSetFound,
     InvalidEvent,
+
+    /// The hash of the event does not match its contents.
+    InvalidHash,
+
+    /// The event is not signed by its sender.
+    InvalidSignature,
+
+    /// The sender of the event is not a validator.
+    NotValidator,
+
+    /// The proof of a `Join` event does not satisfy the
+    /// difficulty in force at the parent of the event.
+    InvalidProof,
+
+    /// The stamp of the event does not happen
+    /// after the stamp of its parent.
+    InvalidStamp,
+
+    /// The parent of the event is issued by
+    /// the same node as the event.
+    FollowsOwnEvent,
+
+    /// The last event of the sender of a `Heartbeat` is
+    /// not followed by enough events from distinct nodes.
+    IneligibleHeartbeat,
+
+    /// The sender of a `Join` event is already a validator.
+    AlreadyValidator,
+
+    /// The sender of a `Leave` event has not issued
+    /// enough `Heartbeat` events in order to leave.
+    EarlyLeave,
+
+    /// The sender of a `Heartbeat` event has issued the
+    /// maximum number of `Heartbeat` events and must leave.
+    LeaveLimitReached,
+
+    /// The parent of the event has already been
+    /// pruned from the causal graph.
+    PrunedParent,
+}
+
+impl CGError {
+    /// Returns `true` if the event is rejected because of the
+    /// current state of the machine rather than because of its
+    /// contents, so that it may be valid for other nodes.
+    ///
+    /// The validator set and the eligibility of the validators
+    /// depend on how far a node is in the total order, so the
+    /// node that relayed such an event should not be considered
+    /// to misbehave.
+    pub fn is_transient(&self) -> bool {
+        match *self {
+            CGError::NotValidator
+            | CGError::IneligibleHeartbeat
+            | CGError::EarlyLeave
+            | CGError::LeaveLimitReached
+            | CGError::PrunedParent => true,
+            _ => false,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -37,221 +113,992 @@ pub struct ConsensusMachine {
     causal_graph: Arc<RwLock<CausalGraph>>,
     candidate_sets: Vec<Arc<Mutex<CandidateSet>>>,
     validators: Vec<Arc<Mutex<ValidatorState>>>,
+
+    /// Received events whose parent is not yet in
+    /// the causal graph, mapped by the parent hash.
+    orphans: HashMap<Hash, Vec<Arc<Event>>>,
+
+    /// The orphans mapped by their own hash along
+    /// with the order in which they were received.
+    orphan_index: HashMap<Hash, (u64, Arc<Event>)>,
+
+    /// The number of orphans issued by each node.
+    node_orphans: HashMap<NodeId, usize>,
+
+    /// The number of orphans received so far.
+    orphan_seq: u64,
+
+    /// The last event included in the total order.
+    last_finalized: Arc<Event>,
+
+    /// The number of events included in the total order.
+    finalized_count: u64,
+
+    /// Subscribers to the stream of finalized events.
+    subscribers: Vec<Sender<Arc<Event>>>,
+
+    /// The current retarget window, which holds the number
+    /// of leading zero bits required for the proof of a
+    /// `Join` event that follows the last finalized event.
+    window: pow::RetargetWindow,
+
+    /// The database to which the state of the
+    /// machine is persisted.
+    db: Option<ConsensusDb>,
 }
 
 impl ConsensusMachine {
     pub fn new(node_id: NodeId, root_event: Arc<Event>) -> ConsensusMachine {
         ConsensusMachine {
-            causal_graph: Arc::new(RwLock::new(CausalGraph::new(node_id, root_event))),
+            causal_graph: Arc::new(RwLock::new(CausalGraph::new(node_id, root_event.clone()))),
             candidate_sets: Vec::new(),
             validators: Vec::new(),
+            orphans: HashMap::new(),
+            orphan_index: HashMap::new(),
+            node_orphans: HashMap::new(),
+            orphan_seq: 0,
+            last_finalized: root_event,
+            finalized_count: 0,
+            subscribers: Vec::new(),
+            window: pow::RetargetWindow::new(pow::DEFAULT_DIFFICULTY),
+            db: None,
         }
     }
 
-    pub fn is_valid(&self, event: Arc<Event>) -> bool {
-        unimplemented!();
+    /// Creates a consensus machine which persists its state
+    /// to the given database, overwriting any stored state.
+    pub fn with_db(node_id: NodeId, root_event: Arc<Event>, mut db: ConsensusDb) -> ConsensusMachine {
+        db.put_finalized(0, &root_event.hash().unwrap());
+
+        let mut machine = ConsensusMachine::new(node_id, root_event);
+
+        machine.db = Some(db);
+        machine.write_state();
+        machine
     }
 
-    /// Attempts to push an atomic reference to an
-    /// event to the causal graph. This function also
-    /// validates the event in accordance with the rest
-    /// of the causal graph.
+    /// Loads the consensus machine stored in the given database
+    /// or creates a new one starting from the given root event if
+    /// there is no stored state.
     ///
-    /// This will return `Err(CGError::AlreadyInCG)` if the event
-    /// is already situated in the `CausalGraph`.
-    pub fn push(&mut self, event: Arc<Event>) -> Result<(), CGError> {
-        let mut g = self.causal_graph.write();
+    /// The causal graph is rebuilt by traversing the stored
+    /// events that follow the last finalized event, after which
+    /// the candidate sets are counted again. This will return
+    /// an error if the stored state is not consistent.
+    pub fn load(node_id: NodeId, root_event: Arc<Event>, mut db: ConsensusDb) -> Result<ConsensusMachine, &'static str> {
+        let bin = match db.state() {
+            Some(bin) => bin,
+            None => return Ok(ConsensusMachine::with_db(node_id, root_event, db)),
+        };
+
+        let fields: Vec<Vec<u8>> = match rlp::Rlp::new(&bin).as_list() {
+            Ok(fields) => fields,
+            Err(_) => return Err("Bad consensus state"),
+        };
+
+        if fields.len() != 3 {
+            return Err("Bad consensus state");
+        }
 
-        if g.contains(event.clone()) {
-            return Err(CGError::AlreadyInCG);
+        if fields[0].len() != 57 {
+            return Err("Bad header length");
         }
 
-        g.push(event);
+        let mut last_finalized_hash = Hash::default();
+        last_finalized_hash.0.copy_from_slice(&fields[0][..32]);
 
-        Ok(())
+        let mut rdr = Cursor::new(&fields[0][32..]);
+        let finalized_count = rdr.read_u64::<BigEndian>().unwrap();
+        let difficulty = rdr.read_u8().unwrap();
+        let window_joins = rdr.read_u64::<BigEndian>().unwrap();
+        let window_start = rdr.read_u64::<BigEndian>().unwrap();
+
+        // The root event is not stored
+        db.put_finalized(0, &root_event.hash().unwrap());
+
+        let last_finalized = if root_event.hash() == Some(last_finalized_hash) {
+            root_event
+        } else {
+            db.event(&last_finalized_hash).ok_or("Missing last finalized event")?
+        };
+
+        let mut machine = ConsensusMachine::new(node_id, last_finalized);
+
+        machine.finalized_count = finalized_count;
+        machine.window = pow::RetargetWindow {
+            difficulty,
+            joins: window_joins,
+            start: window_start,
+        };
+
+        // Rebuild the causal graph starting
+        // from the last finalized event.
+        let mut to_traverse = vec![last_finalized_hash];
+
+        while let Some(parent_hash) = to_traverse.pop() {
+            for hash in db.children(&parent_hash) {
+                let event = db.event(&hash).ok_or("Missing event")?;
+
+                if event.parent_hash() != Some(parent_hash) {
+                    return Err("Bad parent hash");
+                }
+
+                if !(*event).clone().verify_hash() {
+                    return Err("Invalid event hash");
+                }
+
+                if machine.causal_graph.read().contains_hash(&hash) {
+                    continue;
+                }
+
+                machine.causal_graph.write().push(event);
+                to_traverse.push(hash);
+            }
+        }
+
+        let validators: Vec<Vec<u8>> = match rlp::Rlp::new(&fields[1]).as_list() {
+            Ok(validators) => validators,
+            Err(_) => return Err("Bad validator set"),
+        };
+
+        for bin in validators {
+            let validator = ValidatorState::from_bytes(&bin)?;
+
+            if machine.is_validator(validator.node_id()) {
+                return Err("Duplicate validator");
+            }
+
+            machine.validators.push(Arc::new(Mutex::new(validator)));
+        }
+
+        if fields[2].len() % 32 != 0 {
+            return Err("Bad orphan hashes");
+        }
+
+        machine.db = Some(db.clone());
+        machine.count_candidate_sets();
+
+        for chunk in fields[2].chunks(32) {
+            let mut hash = Hash::default();
+            hash.0.copy_from_slice(chunk);
+
+            // An orphan may have been removed
+            // before the state was written.
+            if let Some(orphan) = db.event(&hash) {
+                let _ = machine.push(orphan);
+            }
+        }
+
+        machine.finalize();
+        machine.update_followers();
+        machine.write_state();
+
+        Ok(machine)
     }
 
-    /// Returns the highest event that is currently
-    /// residing in the causal graph.
-    pub fn highest(&self) -> Arc<Event> {
-        let graph = &(*self.causal_graph).read();
-        graph.highest()
+    /// Returns the difficulty of the proof of a `Join`
+    /// event that follows the last finalized event.
+    pub fn difficulty(&self) -> u8 {
+        self.window.difficulty
     }
 
-    /// Returns the highest event in the causal graph
-    /// that **does not** belong to the node with the
-    /// given `NodeId`.
-    pub fn highest_exclusive(&self, node_id: &NodeId) -> Option<Arc<Event>> {
-        let graph = &(*self.causal_graph).read();
-        graph.highest_exclusive(node_id)
+    /// Sets the difficulty of the proof of a `Join` event.
+    pub fn set_difficulty(&mut self, difficulty: u8) {
+        self.window.difficulty = difficulty;
     }
 
-    /// Return the highest event that follows the our latest
-    /// sent event in the causal graph that **does not**
-    /// belong to ourselves.
-    pub fn highest_following(&mut self) -> Option<Arc<Event>> {
-        let graph = &(*self.causal_graph).read();
-        graph.highest_following()
+    /// Returns the difficulty that the proof of a `Join` event
+    /// following the event with the given hash must satisfy.
+    ///
+    /// This is the difficulty in force once the event is
+    /// finalized. The events that follow the last finalized
+    /// event can only be finalized in the order of the path
+    /// leading to it, so every node computes the same value
+    /// regardless of how far it is in the total order.
+    ///
+    /// Returns `None` if the event is not in the causal graph.
+    fn difficulty_at(&self, hash: &Hash) -> Option<u8> {
+        let graph = self.causal_graph.read();
+        let last_finalized_hash = self.last_finalized.hash().unwrap();
+        let mut path = Vec::new();
+        let mut current = graph.get(hash)?;
+
+        while current.hash() != Some(last_finalized_hash) {
+            let parent_hash = current.parent_hash()?;
+            path.push(current);
+            current = graph.get(&parent_hash)?;
+        }
+
+        let mut window = self.window;
+        let mut joined = HashSet::new();
+
+        for (i, event) in path.iter().rev().enumerate() {
+            let node_id = event.node_id();
+            let is_join = match **event {
+                Event::Join(_) => !self.is_validator(&node_id) && joined.insert(node_id),
+                _ => false,
+            };
+
+            window.finalize(self.finalized_count + i as u64, is_join);
+        }
+
+        Some(window.difficulty)
     }
 
-    /// Return the highest event that follows the given
-    /// given event in the causal graph that **does not**
-    /// belong to the node with the given `NodeId`.
-    pub fn compute_highest_following(&mut self, node_id: &NodeId, event: Arc<Event>) -> Option<Arc<Event>> {
-        let graph = &(*self.causal_graph).read();
-        graph.compute_highest_following(node_id, event)
+    /// Returns a receiver that yields the events that
+    /// are finalized from now on, in total order.
+    ///
+    /// Every honest node yields the same sequence
+    /// of events.
+    pub fn subscribe(&mut self) -> Receiver<Arc<Event>> {
+        let (sender, receiver) = channel();
+        self.subscribers.push(sender);
+        receiver
     }
 
-    /// Returns true if the second event happened exactly after the first event.
-    pub(crate) fn is_direct_follower(&self, event1: Arc<Event>, event2: Arc<Event>) -> bool {
-        self.causal_graph.read().is_direct_follower(event1, event2)
+    /// Returns the last event included in the total order.
+    ///
+    /// This is the root event if no event has been
+    /// finalized yet.
+    pub fn last_finalized(&self) -> Arc<Event> {
+        self.last_finalized.clone()
     }
-}
 
-#[cfg(test)]
-mod tests {
-    #[macro_use]
-    use quickcheck::*;
-    use super::*;
-    use causality::Stamp;
-    use crypto::{Hash, Identity};
-    use rand::{thread_rng, Rng};
+    /// Returns the number of events included in the total order.
+    pub fn finalized_count(&self) -> u64 {
+        self.finalized_count
+    }
 
-    #[test]
-    /// Causal graph structure:
+    /// Includes the events of the candidate sets that
+    /// became valid into the total order and returns
+    /// them in order.
     ///
-    /// A -> B -> C -> D -> E -> F
-    /// |
-    /// A' -> B' -> C' -> D'
-    ///       |
-    ///       B''
+    /// The candidate sets are formed by the events that
+    /// directly follow the last finalized event. A node is
+    /// counted for at most one of these sets, the one that
+    /// its events follow, so a set can only become valid
+    /// in two different graphs if more than a third of the
+    /// nodes fork their history or issue events following
+    /// competing sets. Honest nodes do neither, so the set
+    /// that is picked does not depend on the order in which
+    /// the events are received. The lowest event hash only
+    /// breaks ties between sets that are never valid at the
+    /// same time when the nodes are honest.
     ///
-    /// The intended result for calling the function on A should be F
-    /// and the intended result for A' should be D'.
-    fn highest_following() {
-        let i1 = Identity::new();
-        let i2 = Identity::new();
-        let i3 = Identity::new();
-        let n1 = NodeId(*i1.pkey());
-        let n2 = NodeId(*i2.pkey());
-        let n3 = NodeId(*i3.pkey());
-        let seed = Stamp::seed();
-        let (s_a, s_b) = seed.fork();
-        let (s_b, s_c) = s_b.fork();
-        let (s_a, s_d) = s_a.fork();
+    /// The events that do not follow the last finalized
+    /// event are then pruned from the causal graph as
+    /// they are no longer needed in order to count votes.
+    fn finalize(&mut self) -> Vec<Arc<Event>> {
+        let mut finalized = Vec::new();
+
+        loop {
+            let node_count = self.validators.len() as u16;
+            let valid = self
+                .candidate_sets
+                .iter()
+                .map(|set| set.lock())
+                .filter(|set| set.is_valid(node_count))
+                .min_by_key(|set| set.events[0].hash().unwrap())
+                .map(|set| set.events.clone());
+
+            match valid {
+                Some(events) => {
+                    for event in events.iter() {
+                        self.apply(event);
+                        finalized.push(event.clone());
+                        self.last_finalized = event.clone();
+                        self.finalized_count += 1;
+
+                        if let Some(db) = self.db.as_mut() {
+                            db.put_finalized(self.finalized_count, &event.hash().unwrap());
+                        }
+                    }
+
+                    // The validator set changes as `Join`
+                    // and `Leave` events are finalized.
+                    self.count_candidate_sets();
+                }
+                None => break,
+            }
+        }
 
-        let s_a = s_a.event();
-        let A_hash = Hash::random();
-        let A = Event::Dummy(n1.clone(), A_hash.clone(), None, s_a.clone());
+        if !finalized.is_empty() {
+            let pruned = self.causal_graph
+                .write()
+                .prune(&self.last_finalized.hash().unwrap());
+
+            if let Some(db) = self.db.as_mut() {
+                for hash in pruned.iter() {
+                    // The finalized events are kept in order
+                    // to serve them to lagging nodes.
+                    if db.finalized_position(hash).is_some() {
+                        db.remove_children(hash);
+                    } else {
+                        db.remove_event(hash);
+                    }
+                }
+            }
 
-        let s_c = s_c.join(s_a.peek()).event();
-        let A_prime_hash = Hash::random();
-        let A_prime = Event::Dummy(
-            n3.clone(),
-            A_prime_hash.clone(),
-            Some(A_hash.clone()),
-            s_c.clone(),
-        );
+            // Forget about the subscribers that went away
+            self.subscribers
+                .retain(|s| finalized.iter().all(|e| s.send(e.clone()).is_ok()));
+        }
 
-        let s_c = s_c.event();
-        let B_prime_hash = Hash::random();
-        let B_prime = Event::Dummy(
-            n3.clone(),
-            B_prime_hash.clone(),
-            Some(A_prime_hash),
-            s_c.clone(),
-        );
+        finalized
+    }
 
-        let s_d = s_d.join(s_c.peek()).event();
-        let B_second = Event::Dummy(
-            n3.clone(),
-            Hash::random(),
-            Some(B_prime_hash.clone()),
-            s_d.clone(),
-        );
+    /// Forms the candidate sets from the events that directly
+    /// follow the last finalized event and counts their votes.
+    fn count_candidate_sets(&mut self) {
+        let node_count = self.validators.len() as u16;
+        let graph = self.causal_graph.read();
+
+        self.candidate_sets = graph
+            .direct_followers(&self.last_finalized.hash().unwrap())
+            .iter()
+            .map(|e| {
+                let mut set = CandidateSet::new(&[e.clone()]);
+                set.count_votes(&graph, node_count);
+                Arc::new(Mutex::new(set))
+            })
+            .collect();
+    }
 
-        let s_c = s_c.event();
-        let C_prime_hash = Hash::random();
-        let C_prime = Event::Dummy(
-            n3.clone(),
-            C_prime_hash.clone(),
-            Some(B_prime_hash),
-            s_c.clone(),
-        );
+    /// Adds the node with the given id to the validator set.
+    pub fn add_validator(&mut self, node_id: &NodeId) {
+        if !self.is_validator(node_id) {
+            self.validators.push(Arc::new(Mutex::new(ValidatorState::new(node_id))));
+            self.count_candidate_sets();
+            self.write_state();
+        }
+    }
 
-        let s_c = s_c.event();
-        let D_prime = Event::Dummy(n3, Hash::random(), Some(C_prime_hash.clone()), s_c);
+    /// Returns the number of validators.
+    pub fn validator_count(&self) -> usize {
+        self.validators.len()
+    }
 
-        let s_b = s_b.join(s_a.peek()).event();
-        let B_hash = Hash::random();
-        let B = Event::Dummy(n2.clone(), B_hash.clone(), Some(A_hash), s_b.clone());
+    /// Returns `true` if the node with the given
+    /// id is in the validator set.
+    pub fn is_validator(&self, node_id: &NodeId) -> bool {
+        self.validators.iter().any(|v| v.lock().node_id() == node_id)
+    }
 
-        let s_a = s_a.join(s_b.peek()).event();
-        let C_hash = Hash::random();
-        let C = Event::Dummy(n1.clone(), C_hash.clone(), Some(B_hash), s_a.clone());
+    /// Returns the state of the validator with the given id.
+    pub fn validator(&self, node_id: &NodeId) -> Option<ValidatorState> {
+        self.validators
+            .iter()
+            .map(|v| v.lock())
+            .find(|v| v.node_id() == node_id)
+            .map(|v| v.clone())
+    }
 
-        let s_b = s_b.join(s_a.peek()).event();
-        let D_hash = Hash::random();
-        let D = Event::Dummy(n2.clone(), D_hash.clone(), Some(C_hash), s_b.clone());
+    /// Returns `true` if the event is valid in accordance
+    /// with the rest of the causal graph.
+    pub fn is_valid(&self, event: Arc<Event>) -> bool {
+        self.validate(&event).is_ok()
+    }
 
-        let s_a = s_a.join(s_b.peek()).event();
-        let E_hash = Hash::random();
-        let E = Event::Dummy(n1.clone(), E_hash.clone(), Some(D_hash), s_a.clone());
+    /// Checks that the event is issued by its sender and that
+    /// the sender is allowed to issue it. This does not require
+    /// the parent of the event so orphans are checked as well.
+    ///
+    /// The proof of a `Join` event is checked once its parent
+    /// is known since the difficulty depends on the parent.
+    fn verify_sender(&self, event: &Arc<Event>) -> Result<(), CGError> {
+        let mut checked = (**event).clone();
 
-        let s_b = s_b.join(s_a.peek()).event();
-        let F_hash = Hash::random();
-        let F = Event::Dummy(n2.clone(), F_hash, Some(E_hash), s_b.clone());
+        if !checked.verify_hash() {
+            return Err(CGError::InvalidHash);
+        }
 
-        let events = vec![
-            A, B, C, D, E, F, A_prime, B_prime, C_prime, D_prime, B_second,
-        ];
+        if !checked.verify_sig() {
+            return Err(CGError::InvalidSignature);
+        }
 
-        let events: Vec<Arc<Event>> = events.iter().map(|e| Arc::new(e.clone())).collect();
+        let node_id = event.node_id();
 
-        let A = events[0].clone();
-        let F = events[5].clone();
-        let A_prime = events[6].clone();
-        let D_prime = events[9].clone();
+        match **event {
+            Event::Join(_) => {
+                if self.is_validator(&node_id) {
+                    return Err(CGError::AlreadyValidator);
+                }
+            }
+            _ => {
+                if !self.is_validator(&node_id) {
+                    return Err(CGError::NotValidator);
+                }
+            }
+        }
 
-        let (_, events) = events.split_at(1);
-        let mut events: Vec<Arc<Event>> = events.iter().cloned().collect();
+        Ok(())
+    }
 
-        // The causal graph should be the same regardless
-        // of the order in which the events are pushed.
-        thread_rng().shuffle(&mut events);
+    /// Validates an event against the causal graph.
+    ///
+    /// This will return `Err(CGError::NoEventFound)` if the
+    /// parent of the event is not in the `CausalGraph`.
+    fn validate(&self, event: &Arc<Event>) -> Result<(), CGError> {
+        self.verify_sender(event)?;
+
+        let node_count = self.validators.len() as u16;
+
+        if let Some(validator) = self.validator(&event.node_id()) {
+            match **event {
+                Event::Leave(_) if !validator.can_leave(node_count) => {
+                    return Err(CGError::EarlyLeave);
+                }
+                Event::Heartbeat(_) if validator.must_leave(node_count) => {
+                    return Err(CGError::LeaveLimitReached);
+                }
+
+                // A node can only send a heartbeat once its last
+                // event is followed by enough distinct nodes.
+                Event::Heartbeat(_) if !validator.can_send_heartbeat(node_count) => {
+                    return Err(CGError::IneligibleHeartbeat);
+                }
+                _ => {}
+            }
+        }
 
-        let mut machine = ConsensusMachine::new(n1.clone(), A.clone());
+        let graph = self.causal_graph.read();
+        let parent = match event.parent_hash() {
+            Some(parent_hash) => graph.get(&parent_hash).ok_or(CGError::NoEventFound)?,
+            None => return Err(CGError::InvalidEvent),
+        };
 
-        for e in events {
-            machine.push(e).unwrap();
+        // A node can only follow the events of other nodes
+        if parent.node_id() == event.node_id() {
+            return Err(CGError::FollowsOwnEvent);
         }
 
-        assert_eq!(machine.highest_following().unwrap(), F.clone());
-        assert_eq!(machine.compute_highest_following(&n1, A.clone()).unwrap(), F);
-        assert_eq!(
-            machine.compute_highest_following(&n2, A_prime.clone()).unwrap(),
-            D_prime
-        );
+        if !event.stamp().happened_after(parent.stamp()) {
+            return Err(CGError::InvalidStamp);
+        }
+
+        drop(graph);
+
+        if let Event::Join(ref join) = **event {
+            let difficulty = self
+                .difficulty_at(&parent.hash().unwrap())
+                .ok_or(CGError::NoEventFound)?;
+
+            if !pow::verify(join, difficulty) {
+                return Err(CGError::InvalidProof);
+            }
+        }
+
+        Ok(())
     }
 
-    #[test]
-    /// Causal graph structure:
+    /// Attempts to push an atomic reference to an
+    /// event to the causal graph. This function also
+    /// validates the event in accordance with the rest
+    /// of the causal graph.
     ///
-    /// A -> B -> C -> D -> E -> F
-    /// |
-    /// A' -> B' -> C' -> D'
-    ///       |
-    ///       A''
+    /// Events whose parent is not yet in the causal graph
+    /// are kept aside and validated once the parent is
+    /// pushed, as long as they are issued by a node that
+    /// is allowed to issue them.
     ///
-    /// The intended result for calling the function should be F.
-    fn highest() {
-        let i1 = Identity::new();
-        let i2 = Identity::new();
-        let i3 = Identity::new();
-        let n1 = NodeId(*i1.pkey());
-        let n2 = NodeId(*i2.pkey());
-        let n3 = NodeId(*i3.pkey());
-        let seed = Stamp::seed();
+    /// This will return `Err(CGError::AlreadyInCG)` if the event
+    /// is already situated in the `CausalGraph` or if it has
+    /// been pruned and `Err(CGError::PrunedParent)` if its
+    /// parent has been pruned.
+    pub fn push(&mut self, event: Arc<Event>) -> Result<(), CGError> {
+        if event.hash().is_none() || event.parent_hash().is_none() {
+            return Err(CGError::InvalidEvent);
+        }
+
+        let hash = event.hash().unwrap();
+        let parent_hash = event.parent_hash().unwrap();
+
+        if self.contains(&hash) {
+            return Err(CGError::AlreadyInCG);
+        }
+
+        if self.is_pruned(&parent_hash) {
+            self.remove_orphans(&hash);
+            self.commit();
+            return Err(CGError::PrunedParent);
+        }
+
+        if !self.causal_graph.read().contains_hash(&parent_hash) {
+            self.verify_sender(&event)?;
+            self.park_orphan(event);
+            self.write_state();
+            return Ok(());
+        }
+
+        if let Err(err) = self.validate(&event) {
+            self.remove_orphans(&hash);
+            self.commit();
+            return Err(err);
+        }
+
+        self.insert(event);
+
+        // Push the orphans that were waiting on the event
+        let mut to_push = vec![hash];
+
+        while let Some(parent_hash) = to_push.pop() {
+            let orphans = match self.orphans.remove(&parent_hash) {
+                Some(orphans) => orphans,
+                None => continue,
+            };
+
+            for orphan in orphans {
+                let orphan_hash = orphan.hash().unwrap();
+                self.unlink_orphan(&orphan_hash);
+
+                if self.validate(&orphan).is_ok() {
+                    self.insert(orphan);
+                    to_push.push(orphan_hash);
+                } else {
+                    self.remove_orphans(&orphan_hash);
+                }
+            }
+        }
+
+        self.finalize();
+        self.update_followers();
+        self.write_state();
+
+        Ok(())
+    }
+
+    /// Pushes a validated event to the causal graph, counts
+    /// the votes that it brings to the candidate sets and
+    /// records it as the last event of its sender.
+    fn insert(&mut self, event: Arc<Event>) {
+        let node_id = event.node_id();
+
+        self.causal_graph.write().push(event.clone());
+
+        {
+            let graph = self.causal_graph.read();
+
+            for set in self.candidate_sets.iter() {
+                set.lock().add_event(&graph, &event);
+            }
+
+            // The event forms a new candidate set
+            if event.parent_hash() == self.last_finalized.hash() {
+                let mut set = CandidateSet::new(&[event.clone()]);
+
+                set.count_votes(&graph, self.validators.len() as u16);
+                self.candidate_sets.push(Arc::new(Mutex::new(set)));
+            }
+        }
+
+        if let Some(db) = self.db.as_mut() {
+            if db.put_event(&event) {
+                db.add_child(&event);
+            }
+        }
+
+        for validator in self.validators.iter() {
+            let mut validator = validator.lock();
+
+            if validator.node_id() == &node_id {
+                validator.set_last_event(event.clone());
+            }
+        }
+    }
+
+    /// Updates the number of distinct nodes that follow
+    /// the last event of each validator.
+    fn update_followers(&mut self) {
+        let graph = self.causal_graph.read();
+
+        for validator in self.validators.iter() {
+            let mut validator = validator.lock();
+
+            // The followers of a pruned event are
+            // no longer tracked so we keep the count.
+            if let Some(last) = validator.last_event() {
+                if graph.contains(last.clone()) {
+                    validator.set_followers(distinct_followers(&graph, &last, |_| true));
+                }
+            }
+        }
+    }
+
+    /// Updates the validator set in accordance
+    /// with a finalized event.
+    fn apply(&mut self, event: &Arc<Event>) {
+        let node_id = event.node_id();
+        let mut joined = false;
+
+        match **event {
+            // The parent of the event is the previous finalized
+            // event so the proof was checked against the current
+            // difficulty when the event was pushed.
+            Event::Join(ref join) => {
+                if !self.is_validator(&node_id) && pow::verify(join, self.window.difficulty) {
+                    let mut validator = ValidatorState::from_join(join);
+                    validator.set_last_event(event.clone());
+                    self.validators.push(Arc::new(Mutex::new(validator)));
+                    joined = true;
+                }
+            }
+            // The last validator is never removed
+            // since nothing could be finalized then.
+            Event::Leave(_) => {
+                if self.validators.len() > 1 {
+                    self.validators.retain(|v| v.lock().node_id() != &node_id);
+                }
+            }
+            _ => {
+                let node_count = self.validators.len() as u16;
+                let mut must_leave = false;
+
+                for validator in self.validators.iter() {
+                    let mut validator = validator.lock();
+
+                    if validator.node_id() == &node_id {
+                        validator.finalize(event);
+                        must_leave = validator.must_leave(node_count);
+                    }
+                }
+
+                // A validator that has reached the leave limit
+                // is evicted without waiting for its `Leave` event.
+                if must_leave && node_count > 1 {
+                    self.validators.retain(|v| v.lock().node_id() != &node_id);
+                }
+            }
+        }
+
+        // Tune the rate at which validators join
+        self.window.finalize(self.finalized_count, joined);
+    }
+
+    /// Writes the state of the machine to its database along
+    /// with the events received since the last write, in a
+    /// single atomic batch.
+    ///
+    /// Only the hashes of the orphans are part of the state
+    /// since the orphans are written as they are received.
+    fn write_state(&mut self) {
+        if self.db.is_none() {
+            return;
+        }
+
+        let mut header: Vec<u8> = Vec::with_capacity(57);
+
+        // Header structure:
+        // 1) Last finalized hash  - 32 bytes
+        // 2) Finalized count      - 64bits
+        // 3) Difficulty           - 8bits
+        // 4) Window joins         - 64bits
+        // 5) Window start         - 64bits
+        header.extend_from_slice(&self.last_finalized.hash().unwrap().0);
+        header.write_u64::<BigEndian>(self.finalized_count).unwrap();
+        header.write_u8(self.window.difficulty).unwrap();
+        header.write_u64::<BigEndian>(self.window.joins).unwrap();
+        header.write_u64::<BigEndian>(self.window.start).unwrap();
+
+        let validators: Vec<Vec<u8>> = self.validators
+            .iter()
+            .map(|v| v.lock().to_bytes())
+            .collect();
+
+        let orphans: Vec<u8> = self.orphan_index
+            .keys()
+            .flat_map(|hash| hash.0.to_vec())
+            .collect();
+
+        let state = rlp::encode_list::<Vec<u8>, _>(&[
+            header,
+            rlp::encode_list::<Vec<u8>, _>(&validators),
+            orphans,
+        ]);
+
+        self.db.as_mut().unwrap().put_state(&state);
+        self.commit();
+    }
+
+    /// Writes the changes made to the database since
+    /// the last write in a single atomic batch.
+    fn commit(&mut self) {
+        if let Some(db) = self.db.as_mut() {
+            db.commit();
+        }
+    }
+
+    /// Keeps aside an event whose parent is not yet in the
+    /// causal graph.
+    ///
+    /// The oldest orphan of the sender of the event is evicted
+    /// if the sender has too many orphans and the oldest orphan
+    /// overall is evicted if there are too many orphans.
+    fn park_orphan(&mut self, event: Arc<Event>) {
+        let node_id = event.node_id();
+        let hash = event.hash().unwrap();
+        let parent_hash = event.parent_hash().unwrap();
+
+        if self.node_orphans.get(&node_id).cloned().unwrap_or(0) >= MAX_NODE_ORPHANS {
+            self.evict_orphan(Some(&node_id));
+        }
+
+        if self.orphan_index.len() >= MAX_ORPHANS {
+            self.evict_orphan(None);
+        }
+
+        if let Some(db) = self.db.as_mut() {
+            db.put_event(&event);
+        }
+
+        *self.node_orphans.entry(node_id).or_insert(0) += 1;
+        self.orphan_index.insert(hash, (self.orphan_seq, event.clone()));
+        self.orphan_seq += 1;
+        self.orphans
+            .entry(parent_hash)
+            .or_insert_with(Vec::new)
+            .push(event);
+    }
+
+    /// Removes the oldest orphan, issued by the given node
+    /// if there is one, along with the orphans following it.
+    fn evict_orphan(&mut self, node_id: Option<&NodeId>) {
+        let oldest = self.orphan_index
+            .iter()
+            .filter(|(_, (_, orphan))| node_id.map_or(true, |node_id| &orphan.node_id() == node_id))
+            .min_by_key(|(_, (seq, _))| *seq)
+            .map(|(hash, _)| hash.clone());
+
+        if let Some(hash) = oldest {
+            self.remove_orphans(&hash);
+        }
+    }
+
+    /// Forgets about the orphan with the given hash without
+    /// removing the orphans that follow it.
+    fn unlink_orphan(&mut self, hash: &Hash) {
+        let orphan = match self.orphan_index.remove(hash) {
+            Some((_, orphan)) => orphan,
+            None => return,
+        };
+
+        let node_id = orphan.node_id();
+        let parent_hash = orphan.parent_hash().unwrap();
+        let remaining = match self.node_orphans.get_mut(&node_id) {
+            Some(count) => {
+                *count -= 1;
+                *count
+            }
+            None => 0,
+        };
+
+        if remaining == 0 {
+            self.node_orphans.remove(&node_id);
+        }
+
+        let siblings_left = match self.orphans.get_mut(&parent_hash) {
+            Some(siblings) => {
+                siblings.retain(|o| o.hash().as_ref() != Some(hash));
+                siblings.len()
+            }
+            None => 0,
+        };
+
+        if siblings_left == 0 {
+            self.orphans.remove(&parent_hash);
+        }
+    }
+
+    /// Removes the orphans that directly or indirectly
+    /// follow the event with the given hash.
+    fn remove_orphans(&mut self, hash: &Hash) {
+        let mut to_remove = vec![hash.clone()];
+
+        while let Some(hash) = to_remove.pop() {
+            if let Some(db) = self.db.as_mut() {
+                db.remove_event(&hash);
+            }
+
+            self.unlink_orphan(&hash);
+
+            if let Some(orphans) = self.orphans.remove(&hash) {
+                to_remove.extend(orphans.iter().map(|o| o.hash().unwrap()));
+            }
+        }
+    }
+
+    /// Returns `true` if the event with the given hash is in
+    /// the causal graph, has been pruned from it or is waiting
+    /// on its parent.
+    pub fn contains(&self, hash: &Hash) -> bool {
+        let in_graph = self.causal_graph.read().contains_hash(hash);
+        in_graph || self.is_pruned(hash) || self.is_orphan(hash)
+    }
+
+    /// Returns `true` if the event with the given hash has
+    /// been pruned from the causal graph. The finalized events
+    /// are still recognized once the causal graph has forgotten
+    /// about them.
+    fn is_pruned(&self, hash: &Hash) -> bool {
+        let graph = self.causal_graph.read();
+
+        if graph.contains_hash(hash) {
+            return false;
+        }
+
+        graph.is_pruned(hash) || self.finalized_position(hash).is_some()
+    }
+
+    /// Returns the position in the total order of the
+    /// finalized event with the given hash, if it is
+    /// stored in the database.
+    fn finalized_position(&self, hash: &Hash) -> Option<u64> {
+        self.db.as_ref()?.finalized_position(hash)
+    }
+
+    fn is_orphan(&self, hash: &Hash) -> bool {
+        self.orphan_index.contains_key(hash)
+    }
+
+    /// Returns the event with the given hash.
+    ///
+    /// The finalized events are read from the database
+    /// once they have been pruned from the causal graph.
+    pub fn get(&self, hash: &Hash) -> Option<Arc<Event>> {
+        if let Some(event) = self.causal_graph.read().get(hash) {
+            return Some(event);
+        }
+
+        self.finalized_position(hash)?;
+        self.db.as_ref()?.event(hash)
+    }
+
+    /// Returns the hashes of the events that are not in the
+    /// causal graph but are the parents of received events.
+    ///
+    /// These are the events that the causal graph is
+    /// waiting on in order to attach the received ones.
+    pub fn missing_parents(&self) -> Vec<Hash> {
+        let mut missing: HashSet<Hash> = self.causal_graph.read().missing_parents().into_iter().collect();
+
+        for parent_hash in self.orphans.keys() {
+            if !self.is_orphan(parent_hash) {
+                missing.insert(parent_hash.clone());
+            }
+        }
+
+        missing.into_iter().collect()
+    }
+
+    /// Returns at most `count` events that follow the
+    /// event with the given hash in the causal graph.
+    ///
+    /// If the event has been finalized and pruned, the
+    /// finalized events that follow it are read from the
+    /// database and come first, so that a lagging node
+    /// can catch up with the total order.
+    pub fn following(&self, hash: &Hash, count: usize) -> Vec<Arc<Event>> {
+        let graph = self.causal_graph.read();
+
+        if graph.contains_hash(hash) {
+            return graph.following(hash, count);
+        }
+
+        let (db, position) = match (self.db.as_ref(), self.finalized_position(hash)) {
+            (Some(db), Some(position)) => (db, position),
+            _ => return Vec::new(),
+        };
+
+        let mut result = Vec::with_capacity(count);
+
+        for position in position + 1..=self.finalized_count {
+            if result.len() >= count {
+                return result;
+            }
+
+            match db.finalized(position) {
+                Some(event) => result.push(event),
+                None => return result,
+            }
+        }
+
+        let remaining = count - result.len();
+        result.extend(graph.following(&self.last_finalized.hash().unwrap(), remaining));
+        result
+    }
+
+    /// Returns the highest event that is currently
+    /// residing in the causal graph.
+    pub fn highest(&self) -> Arc<Event> {
+        let graph = &(*self.causal_graph).read();
+        graph.highest()
+    }
+
+    /// Returns the highest event in the causal graph
+    /// that **does not** belong to the node with the
+    /// given `NodeId`.
+    pub fn highest_exclusive(&self, node_id: &NodeId) -> Option<Arc<Event>> {
+        let graph = &(*self.causal_graph).read();
+        graph.highest_exclusive(node_id)
+    }
+
+    /// Return the highest event that follows the our latest
+    /// sent event in the causal graph that **does not**
+    /// belong to ourselves.
+    pub fn highest_following(&mut self) -> Option<Arc<Event>> {
+        let graph = &(*self.causal_graph).read();
+        graph.highest_following()
+    }
+
+    /// Return the highest event that follows the given
+    /// given event in the causal graph that **does not**
+    /// belong to the node with the given `NodeId`.
+    pub fn compute_highest_following(&mut self, node_id: &NodeId, event: Arc<Event>) -> Option<Arc<Event>> {
+        let graph = &(*self.causal_graph).read();
+        graph.compute_highest_following(node_id, event)
+    }
+
+    /// Returns true if the second event happened exactly after the first event.
+    pub(crate) fn is_direct_follower(&self, event1: Arc<Event>, event2: Arc<Event>) -> bool {
+        self.causal_graph.read().is_direct_follower(event1, event2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[macro_use]
+    use quickcheck::*;
+    use super::*;
+    use account::NormalAddress;
+    use causality::Stamp;
+    use crypto::{Hash, Identity, SecretKey};
+    use events::{Heartbeat, Join, Leave};
+    use hashdb::HashDB;
+    use kvdb_rocksdb::{Database, DatabaseConfig};
+    use persistence::PersistentDb;
+    use rand::{thread_rng, Rng};
+    use tempfile::tempdir;
+
+    #[test]
+    /// Causal graph structure:
+    ///
+    /// A -> B -> C -> D -> E -> F
+    /// |
+    /// A' -> B' -> C' -> D'
+    ///       |
+    ///       B''
+    ///
+    /// The intended result for calling the function on A should be F
+    /// and the intended result for A' should be D'.
+    fn highest_following() {
+        let i1 = Identity::new();
+        let i2 = Identity::new();
+        let i3 = Identity::new();
+        let i4 = Identity::new();
+        let n1 = NodeId(*i1.pkey());
+        let n2 = NodeId(*i2.pkey());
+        let n3 = NodeId(*i3.pkey());
+        let n4 = NodeId(*i4.pkey());
+        let seed = Stamp::seed();
         let (s_a, s_b) = seed.fork();
         let (s_b, s_c) = s_b.fork();
         let (s_a, s_d) = s_a.fork();
@@ -272,7 +1119,7 @@ mod tests {
         let s_c = s_c.event();
         let B_prime_hash = Hash::random();
         let B_prime = Event::Dummy(
-            n3.clone(),
+            n4.clone(),
             B_prime_hash.clone(),
             Some(A_prime_hash),
             s_c.clone(),
@@ -296,7 +1143,7 @@ mod tests {
         );
 
         let s_c = s_c.event();
-        let D_prime = Event::Dummy(n3, Hash::random(), Some(C_prime_hash.clone()), s_c);
+        let D_prime = Event::Dummy(n4.clone(), Hash::random(), Some(C_prime_hash.clone()), s_c);
 
         let s_b = s_b.join(s_a.peek()).event();
         let B_hash = Hash::random();
@@ -325,8 +1172,9 @@ mod tests {
         let events: Vec<Arc<Event>> = events.iter().map(|e| Arc::new(e.clone())).collect();
 
         let A = events[0].clone();
-        let E = events[4].clone();
         let F = events[5].clone();
+        let A_prime = events[6].clone();
+        let D_prime = events[9].clone();
 
         let (_, events) = events.split_at(1);
         let mut events: Vec<Arc<Event>> = events.iter().cloned().collect();
@@ -335,14 +1183,1030 @@ mod tests {
         // of the order in which the events are pushed.
         thread_rng().shuffle(&mut events);
 
-        let mut machine = ConsensusMachine::new(n1, A.clone());
+        let mut machine = ConsensusMachine::new(n1.clone(), A.clone());
+        machine.add_validator(&n1);
+        machine.add_validator(&n2);
+        machine.add_validator(&n3);
+        machine.add_validator(&n4);
 
         for e in events {
             machine.push(e).unwrap();
         }
 
-        assert_eq!(machine.highest(), F);
-        assert_eq!(machine.highest_exclusive(&n2), Some(E));
+        assert_eq!(machine.highest_following().unwrap(), F.clone());
+        assert_eq!(machine.compute_highest_following(&n1, A.clone()).unwrap(), F);
+        assert_eq!(
+            machine.compute_highest_following(&n2, A_prime.clone()).unwrap(),
+            D_prime
+        );
+    }
+
+    #[test]
+    /// Causal graph structure:
+    ///
+    /// A -> B -> C -> D -> E -> F
+    /// |
+    /// A' -> B' -> C' -> D'
+    ///       |
+    ///       A''
+    ///
+    /// The intended result for calling the function should be F.
+    fn highest() {
+        let i1 = Identity::new();
+        let i2 = Identity::new();
+        let i3 = Identity::new();
+        let i4 = Identity::new();
+        let n1 = NodeId(*i1.pkey());
+        let n2 = NodeId(*i2.pkey());
+        let n3 = NodeId(*i3.pkey());
+        let n4 = NodeId(*i4.pkey());
+        let seed = Stamp::seed();
+        let (s_a, s_b) = seed.fork();
+        let (s_b, s_c) = s_b.fork();
+        let (s_a, s_d) = s_a.fork();
+
+        let s_a = s_a.event();
+        let A_hash = Hash::random();
+        let A = Event::Dummy(n1.clone(), A_hash.clone(), None, s_a.clone());
+
+        let s_c = s_c.join(s_a.peek()).event();
+        let A_prime_hash = Hash::random();
+        let A_prime = Event::Dummy(
+            n3.clone(),
+            A_prime_hash.clone(),
+            Some(A_hash.clone()),
+            s_c.clone(),
+        );
+
+        let s_c = s_c.event();
+        let B_prime_hash = Hash::random();
+        let B_prime = Event::Dummy(
+            n4.clone(),
+            B_prime_hash.clone(),
+            Some(A_prime_hash),
+            s_c.clone(),
+        );
+
+        let s_d = s_d.join(s_c.peek()).event();
+        let B_second = Event::Dummy(
+            n3.clone(),
+            Hash::random(),
+            Some(B_prime_hash.clone()),
+            s_d.clone(),
+        );
+
+        let s_c = s_c.event();
+        let C_prime_hash = Hash::random();
+        let C_prime = Event::Dummy(
+            n3.clone(),
+            C_prime_hash.clone(),
+            Some(B_prime_hash),
+            s_c.clone(),
+        );
+
+        let s_c = s_c.event();
+        let D_prime = Event::Dummy(n4.clone(), Hash::random(), Some(C_prime_hash.clone()), s_c);
+
+        let s_b = s_b.join(s_a.peek()).event();
+        let B_hash = Hash::random();
+        let B = Event::Dummy(n2.clone(), B_hash.clone(), Some(A_hash), s_b.clone());
+
+        let s_a = s_a.join(s_b.peek()).event();
+        let C_hash = Hash::random();
+        let C = Event::Dummy(n1.clone(), C_hash.clone(), Some(B_hash), s_a.clone());
+
+        let s_b = s_b.join(s_a.peek()).event();
+        let D_hash = Hash::random();
+        let D = Event::Dummy(n2.clone(), D_hash.clone(), Some(C_hash), s_b.clone());
+
+        let s_a = s_a.join(s_b.peek()).event();
+        let E_hash = Hash::random();
+        let E = Event::Dummy(n1.clone(), E_hash.clone(), Some(D_hash), s_a.clone());
+
+        let s_b = s_b.join(s_a.peek()).event();
+        let F_hash = Hash::random();
+        let F = Event::Dummy(n2.clone(), F_hash, Some(E_hash), s_b.clone());
+
+        let events = vec![
+            A, B, C, D, E, F, A_prime, B_prime, C_prime, D_prime, B_second,
+        ];
+
+        let events: Vec<Arc<Event>> = events.iter().map(|e| Arc::new(e.clone())).collect();
+
+        let A = events[0].clone();
+        let E = events[4].clone();
+        let F = events[5].clone();
+
+        let (_, events) = events.split_at(1);
+        let mut events: Vec<Arc<Event>> = events.iter().cloned().collect();
+
+        // The causal graph should be the same regardless
+        // of the order in which the events are pushed.
+        thread_rng().shuffle(&mut events);
+
+        let mut machine = ConsensusMachine::new(n1.clone(), A.clone());
+        machine.add_validator(&n1);
+        machine.add_validator(&n2);
+        machine.add_validator(&n3);
+        machine.add_validator(&n4);
+
+        for e in events {
+            machine.push(e).unwrap();
+        }
+
+        assert_eq!(machine.highest(), F);
+        assert_eq!(machine.highest_exclusive(&n2), Some(E));
+    }
+
+    #[test]
+    /// Causal graph structure:
+    ///
+    /// A -> B -> C -> D
+    ///
+    /// The events are received out of order and
+    /// the missing parents should be reported.
+    fn missing_parents() {
+        let i1 = Identity::new();
+        let i2 = Identity::new();
+        let n1 = NodeId(*i1.pkey());
+        let n2 = NodeId(*i2.pkey());
+        let seed = Stamp::seed();
+        let (s_a, s_b) = seed.fork();
+
+        let s_a = s_a.event();
+        let A_hash = Hash::random();
+        let A = Arc::new(Event::Dummy(n1.clone(), A_hash.clone(), None, s_a.clone()));
+
+        let s_b = s_b.join(s_a.peek()).event();
+        let B_hash = Hash::random();
+        let B = Arc::new(Event::Dummy(n2.clone(), B_hash.clone(), Some(A_hash.clone()), s_b.clone()));
+
+        let s_a = s_a.join(s_b.peek()).event();
+        let C_hash = Hash::random();
+        let C = Arc::new(Event::Dummy(n1.clone(), C_hash.clone(), Some(B_hash.clone()), s_a.clone()));
+
+        let s_b = s_b.join(s_a.peek()).event();
+        let D = Arc::new(Event::Dummy(n2.clone(), Hash::random(), Some(C_hash.clone()), s_b));
+
+        let mut machine = ConsensusMachine::new(n1.clone(), A.clone());
+        machine.add_validator(&n1);
+        machine.add_validator(&n2);
+        assert!(machine.missing_parents().is_empty());
+
+        machine.push(D.clone()).unwrap();
+        assert_eq!(machine.missing_parents(), vec![C_hash.clone()]);
+
+        machine.push(B.clone()).unwrap();
+        assert_eq!(machine.missing_parents(), vec![C_hash]);
+        assert_eq!(machine.following(&A_hash, 10), vec![B.clone()]);
+
+        machine.push(C.clone()).unwrap();
+        assert!(machine.missing_parents().is_empty());
+        assert_eq!(machine.following(&A_hash, 10), vec![B.clone(), C.clone(), D]);
+        assert_eq!(machine.following(&A_hash, 2), vec![B, C]);
+        assert!(machine.following(&Hash::random(), 10).is_empty());
+    }
+
+    fn leave(node_id: &NodeId, skey: &SecretKey, parent_hash: Hash, stamp: Stamp) -> Event {
+        let mut leave = Leave {
+            node_id: node_id.clone(),
+            stamp,
+            parent_hash,
+            hash: None,
+            signature: None,
+        };
+
+        leave.sign(skey.clone());
+        leave.hash();
+
+        Event::Leave(leave)
+    }
+
+    fn heartbeat(node_id: &NodeId, skey: &SecretKey, parent_hash: Hash, stamp: Stamp) -> Event {
+        let mut heartbeat = Heartbeat {
+            node_id: node_id.clone(),
+            stamp,
+            root_hash: Some(Hash::random()),
+            hash: None,
+            parent_hash,
+            signature: None,
+            transactions: Vec::new(),
+        };
+
+        heartbeat.sign(skey.clone());
+        heartbeat.hash();
+
+        Event::Heartbeat(heartbeat)
+    }
+
+    #[test]
+    fn it_rejects_invalid_events() {
+        let i1 = Identity::new();
+        let i2 = Identity::new();
+        let i3 = Identity::new();
+        let n1 = NodeId(*i1.pkey());
+        let n2 = NodeId(*i2.pkey());
+        let n3 = NodeId(*i3.pkey());
+        let (s_a, s_b) = Stamp::seed().fork();
+
+        let s_a = s_a.event();
+        let A_hash = Hash::random();
+        let A = Arc::new(Event::Dummy(n1.clone(), A_hash.clone(), None, s_a.clone()));
+        let s_b = s_b.join(s_a.peek()).event();
+
+        let mut machine = ConsensusMachine::new(n1.clone(), A);
+        machine.add_validator(&n1);
+        machine.add_validator(&n2);
+
+        let mut bad_hash = heartbeat(&n2, i2.skey(), A_hash.clone(), s_b.clone());
+
+        if let Event::Heartbeat(ref mut event) = bad_hash {
+            event.hash = Some(Hash::random());
+        }
+
+        let bad_hash = Arc::new(bad_hash);
+        let bad_sig = Arc::new(heartbeat(&n2, i3.skey(), A_hash.clone(), s_b.clone()));
+        let not_validator = Arc::new(heartbeat(&n3, i3.skey(), A_hash.clone(), s_b.clone()));
+        let bad_stamp = Arc::new(heartbeat(&n2, i2.skey(), A_hash.clone(), Stamp::seed()));
+        let follows_own = Arc::new(heartbeat(&n1, i1.skey(), A_hash.clone(), s_b.clone()));
+        let valid = Arc::new(heartbeat(&n2, i2.skey(), A_hash, s_b));
+
+        assert_eq!(machine.push(bad_hash.clone()), Err(CGError::InvalidHash));
+        assert_eq!(machine.push(bad_sig.clone()), Err(CGError::InvalidSignature));
+        assert_eq!(machine.push(not_validator.clone()), Err(CGError::NotValidator));
+        assert_eq!(machine.push(bad_stamp.clone()), Err(CGError::InvalidStamp));
+        assert_eq!(machine.push(follows_own.clone()), Err(CGError::FollowsOwnEvent));
+        assert!(!machine.is_valid(bad_stamp.clone()));
+        assert!(machine.is_valid(valid.clone()));
+        assert_eq!(machine.push(valid.clone()), Ok(()));
+        assert_eq!(machine.push(valid.clone()), Err(CGError::AlreadyInCG));
+
+        for event in vec![bad_hash, bad_sig, not_validator, bad_stamp, follows_own] {
+            assert!(!machine.contains(&event.hash().unwrap()));
+        }
+
+        assert!(machine.contains(&valid.hash().unwrap()));
+    }
+
+    #[test]
+    /// Causal graph structure:
+    ///
+    /// A -> B -> D
+    /// |
+    /// C -> H
+    ///
+    /// The heartbeat H of the second node is only valid
+    /// once its last event B is followed by another node.
+    fn it_rejects_ineligible_heartbeats() {
+        let i1 = Identity::new();
+        let i2 = Identity::new();
+        let i3 = Identity::new();
+        let i4 = Identity::new();
+        let n1 = NodeId(*i1.pkey());
+        let n2 = NodeId(*i2.pkey());
+        let n3 = NodeId(*i3.pkey());
+        let n4 = NodeId(*i4.pkey());
+        let (s_a, s_b) = Stamp::seed().fork();
+        let (s_b, s_c) = s_b.fork();
+        let (s_c, s_d) = s_c.fork();
+
+        let s_a = s_a.event();
+        let A_hash = Hash::random();
+        let A = Arc::new(Event::Dummy(n1.clone(), A_hash.clone(), None, s_a.clone()));
+
+        let s_b = s_b.join(s_a.peek()).event();
+        let B_hash = Hash::random();
+        let B = Arc::new(Event::Dummy(n2.clone(), B_hash.clone(), Some(A_hash.clone()), s_b.clone()));
+
+        let s_c = s_c.join(s_a.peek()).event();
+        let C_hash = Hash::random();
+        let C = Arc::new(Event::Dummy(n3.clone(), C_hash.clone(), Some(A_hash), s_c.clone()));
+
+        let s_d = s_d.join(s_b.peek()).event();
+        let D = Arc::new(Event::Dummy(n4.clone(), Hash::random(), Some(B_hash), s_d));
+
+        let s_b = s_b.join(s_c.peek()).event();
+        let H = Arc::new(heartbeat(&n2, i2.skey(), C_hash, s_b));
+
+        let mut machine = ConsensusMachine::new(n1.clone(), A);
+        machine.add_validator(&n1);
+        machine.add_validator(&n2);
+        machine.add_validator(&n3);
+        machine.add_validator(&n4);
+
+        machine.push(B).unwrap();
+        machine.push(C).unwrap();
+
+        assert_eq!(machine.push(H.clone()), Err(CGError::IneligibleHeartbeat));
+        assert!(!machine.contains(&H.hash().unwrap()));
+
+        machine.push(D).unwrap();
+
+        assert_eq!(machine.push(H.clone()), Ok(()));
+        assert!(machine.contains(&H.hash().unwrap()));
+    }
+
+    #[test]
+    /// Causal graph structure:
+    ///
+    /// A -> B -> C -> D
+    ///
+    /// The stamp of C does not happen after the stamp of B so
+    /// both C and D are dropped once B is received.
+    fn it_validates_orphans_once_their_parent_is_received() {
+        let i1 = Identity::new();
+        let i2 = Identity::new();
+        let n1 = NodeId(*i1.pkey());
+        let n2 = NodeId(*i2.pkey());
+        let (s_a, s_b) = Stamp::seed().fork();
+
+        let s_a = s_a.event();
+        let A_hash = Hash::random();
+        let A = Arc::new(Event::Dummy(n1.clone(), A_hash.clone(), None, s_a.clone()));
+
+        let s_b = s_b.join(s_a.peek()).event();
+        let B_hash = Hash::random();
+        let B = Arc::new(Event::Dummy(n2.clone(), B_hash.clone(), Some(A_hash.clone()), s_b.clone()));
+
+        let C_hash = Hash::random();
+        let C = Arc::new(Event::Dummy(n1.clone(), C_hash.clone(), Some(B_hash.clone()), Stamp::seed()));
+
+        let s_b = s_b.join(s_a.peek()).event();
+        let D_hash = Hash::random();
+        let D = Arc::new(Event::Dummy(n2.clone(), D_hash.clone(), Some(C_hash.clone()), s_b));
+
+        let mut machine = ConsensusMachine::new(n1.clone(), A);
+        machine.add_validator(&n1);
+        machine.add_validator(&n2);
+
+        machine.push(D).unwrap();
+        machine.push(C).unwrap();
+
+        assert!(machine.contains(&C_hash));
+        assert!(machine.contains(&D_hash));
+        assert_eq!(machine.missing_parents(), vec![B_hash]);
+
+        machine.push(B.clone()).unwrap();
+
+        assert!(!machine.contains(&C_hash));
+        assert!(!machine.contains(&D_hash));
+        assert!(machine.missing_parents().is_empty());
+        assert_eq!(machine.following(&A_hash, 10), vec![B]);
+    }
+
+    #[test]
+    fn it_evicts_the_oldest_orphans() {
+        let nodes: Vec<NodeId> = (0..3).map(|_| NodeId(*Identity::new().pkey())).collect();
+        let A = chain(None, &nodes[..1], 1).remove(0);
+        let orphan = |node_id: &NodeId| {
+            Arc::new(Event::Dummy(node_id.clone(), Hash::random(), Some(Hash::random()), Stamp::seed()))
+        };
+
+        let mut machine = ConsensusMachine::new(nodes[0].clone(), A);
+        machine.add_validator(&nodes[0]);
+        machine.add_validator(&nodes[1]);
+
+        // Orphans are only kept if their sender is a validator
+        let stranger = orphan(&nodes[2]);
+
+        assert_eq!(machine.push(stranger.clone()), Err(CGError::NotValidator));
+        assert!(CGError::NotValidator.is_transient());
+        assert!(!machine.contains(&stranger.hash().unwrap()));
+
+        let orphans: Vec<Arc<Event>> = (0..MAX_NODE_ORPHANS).map(|_| orphan(&nodes[0])).collect();
+
+        for e in orphans.iter() {
+            machine.push(e.clone()).unwrap();
+        }
+
+        assert!(orphans.iter().all(|e| machine.contains(&e.hash().unwrap())));
+
+        // The oldest orphan of the sender is evicted
+        let next = orphan(&nodes[0]);
+        machine.push(next.clone()).unwrap();
+
+        assert!(!machine.contains(&orphans[0].hash().unwrap()));
+        assert!(machine.contains(&orphans[1].hash().unwrap()));
+        assert!(machine.contains(&next.hash().unwrap()));
+        assert_eq!(machine.missing_parents().len(), MAX_NODE_ORPHANS);
+
+        // The orphans of other nodes are kept
+        let other = orphan(&nodes[1]);
+        machine.push(other.clone()).unwrap();
+
+        assert!(machine.contains(&orphans[1].hash().unwrap()));
+        assert!(machine.contains(&other.hash().unwrap()));
+        assert_eq!(machine.missing_parents().len(), MAX_NODE_ORPHANS + 1);
+    }
+
+    #[test]
+    fn it_caps_the_total_number_of_orphans() {
+        let node_count = MAX_ORPHANS / MAX_NODE_ORPHANS + 1;
+        let nodes: Vec<NodeId> = (0..node_count).map(|_| NodeId(*Identity::new().pkey())).collect();
+        let A = chain(None, &nodes[..1], 1).remove(0);
+        let mut machine = ConsensusMachine::new(nodes[0].clone(), A);
+        let mut orphans = Vec::with_capacity(MAX_ORPHANS + 1);
+
+        for n in nodes.iter() {
+            machine.add_validator(n);
+        }
+
+        for i in 0..=MAX_ORPHANS {
+            let node_id = nodes[i / MAX_NODE_ORPHANS].clone();
+            let orphan = Arc::new(Event::Dummy(node_id, Hash::random(), Some(Hash::random()), Stamp::seed()));
+
+            machine.push(orphan.clone()).unwrap();
+            orphans.push(orphan);
+        }
+
+        // The oldest orphan overall is evicted
+        assert!(!machine.contains(&orphans[0].hash().unwrap()));
+        assert!(orphans[1..].iter().all(|e| machine.contains(&e.hash().unwrap())));
+        assert_eq!(machine.missing_parents().len(), MAX_ORPHANS);
+    }
+
+    /// Returns a chain of events following the given parent,
+    /// issued in turn by each of the given nodes. The first
+    /// event is a root event if there is no parent.
+    fn chain(parent: Option<&Arc<Event>>, nodes: &[NodeId], len: usize) -> Vec<Arc<Event>> {
+        let mut stamps = vec![Stamp::seed()];
+
+        while stamps.len() < nodes.len() {
+            let (s1, s2) = stamps.pop().unwrap().fork();
+            stamps.push(s1);
+            stamps.push(s2);
+        }
+
+        let mut events: Vec<Arc<Event>> = Vec::with_capacity(len);
+        let mut last = parent.cloned();
+
+        for i in 0..len {
+            let n = i % nodes.len();
+            let parent_hash = last.as_ref().map(|e| e.hash().unwrap());
+            let stamp = match last {
+                Some(ref parent) => stamps[n].join(parent.stamp().peek()).event(),
+                None => stamps[n].event(),
+            };
+
+            let event = Arc::new(Event::Dummy(nodes[n].clone(), Hash::random(), parent_hash, stamp.clone()));
+
+            stamps[n] = stamp;
+            events.push(event.clone());
+            last = Some(event);
+        }
+
+        events
+    }
+
+    fn heartbeats(parent: &Arc<Event>, identities: &[Identity], len: usize) -> Vec<Arc<Event>> {
+        let mut stamps = vec![Stamp::seed()];
+
+        while stamps.len() < identities.len() {
+            let (s1, s2) = stamps.pop().unwrap().fork();
+            stamps.push(s1);
+            stamps.push(s2);
+        }
+
+        let mut events: Vec<Arc<Event>> = Vec::with_capacity(len);
+        let mut last = parent.clone();
+
+        for i in 0..len {
+            let n = i % identities.len();
+            let node_id = NodeId(*identities[n].pkey());
+            let stamp = stamps[n].join(last.stamp().peek()).event();
+            let event = Arc::new(heartbeat(&node_id, identities[n].skey(), last.hash().unwrap(), stamp.clone()));
+
+            stamps[n] = stamp;
+            events.push(event.clone());
+            last = event;
+        }
+
+        events
+    }
+
+    #[test]
+    /// Causal graph structure:
+    ///
+    /// A -> E1 -> E2 -> ... -> E13
+    ///
+    /// An event is finalized once it is followed by
+    /// seven events, issued in turn by all four nodes.
+    fn it_finalizes_events_in_total_order() {
+        let nodes: Vec<NodeId> = (0..4).map(|_| NodeId(*Identity::new().pkey())).collect();
+        let events = chain(None, &nodes, 14);
+        let mut sequences = Vec::new();
+
+        for _ in 0..2 {
+            let mut to_push: Vec<Arc<Event>> = events[1..].to_vec();
+
+            // Every node should finalize the same sequence
+            // regardless of the order of the received events.
+            thread_rng().shuffle(&mut to_push);
+
+            let mut machine = ConsensusMachine::new(nodes[0].clone(), events[0].clone());
+            let receiver = machine.subscribe();
+
+            for n in nodes.iter() {
+                machine.add_validator(n);
+            }
+
+            for e in to_push {
+                machine.push(e).unwrap();
+            }
+
+            let finalized: Vec<Arc<Event>> = receiver.try_iter().collect();
+
+            assert_eq!(machine.finalized_count(), 6);
+            assert_eq!(machine.last_finalized(), events[6]);
+            sequences.push(finalized);
+        }
+
+        assert_eq!(sequences[0], events[1..7].to_vec());
+        assert_eq!(sequences[0], sequences[1]);
+    }
+
+    #[test]
+    fn it_finalizes_events_once_they_are_followed_by_enough_events() {
+        let nodes: Vec<NodeId> = (0..4).map(|_| NodeId(*Identity::new().pkey())).collect();
+        let events = chain(None, &nodes, 10);
+        let mut machine = ConsensusMachine::new(nodes[0].clone(), events[0].clone());
+        let receiver = machine.subscribe();
+
+        for n in nodes.iter() {
+            machine.add_validator(n);
+        }
+
+        for e in events[1..8].iter() {
+            machine.push(e.clone()).unwrap();
+        }
+
+        assert!(receiver.try_recv().is_err());
+        assert_eq!(machine.last_finalized(), events[0]);
+
+        machine.push(events[8].clone()).unwrap();
+
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), vec![events[1].clone()]);
+
+        machine.push(events[9].clone()).unwrap();
+
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), vec![events[2].clone()]);
+        assert_eq!(machine.finalized_count(), 2);
+    }
+
+    #[test]
+    /// Causal graph structure:
+    ///
+    /// A -> X -> E1 -> E2 -> ... -> E20
+    /// |
+    /// Y
+    ///
+    /// The second node issues Y, which competes with X, and
+    /// later switches to the events that follow X. Every node
+    /// should finalize X and the events following it whichever
+    /// sibling it receives first.
+    fn it_finalizes_the_same_sibling_regardless_of_the_order() {
+        let nodes: Vec<NodeId> = (0..4).map(|_| NodeId(*Identity::new().pkey())).collect();
+        let A = Arc::new(Event::Dummy(NodeId::new([0; 32]), Hash::random(), None, Stamp::seed()));
+        let mut stamps = vec![Stamp::seed()];
+
+        while stamps.len() < nodes.len() {
+            let (s1, s2) = stamps.pop().unwrap().fork();
+            stamps.push(s1);
+            stamps.push(s2);
+        }
+
+        let mut issue = |n: usize, parent: &Arc<Event>| {
+            let stamp = stamps[n].join(parent.stamp().peek()).event();
+            stamps[n] = stamp.clone();
+            Arc::new(Event::Dummy(nodes[n].clone(), Hash::random(), parent.hash(), stamp))
+        };
+
+        let X = issue(0, &A);
+        let Y = issue(1, &A);
+        let mut x_branch = vec![X.clone()];
+
+        for n in [2, 3, 0, 2, 3, 0, 1, 2, 3, 0, 1, 2, 3, 0, 1, 2, 3, 0, 1, 2].iter() {
+            let event = issue(*n, x_branch.last().unwrap());
+            x_branch.push(event);
+        }
+
+        let y_branch = vec![Y.clone()];
+        let mut sequences = Vec::new();
+
+        for y_first in [false, true].iter() {
+            let mut machine = ConsensusMachine::new(nodes[0].clone(), A.clone());
+            let receiver = machine.subscribe();
+            let to_push = if *y_first {
+                y_branch.iter().chain(x_branch.iter())
+            } else {
+                x_branch.iter().chain(y_branch.iter())
+            };
+
+            for n in nodes.iter() {
+                machine.add_validator(n);
+            }
+
+            // The events of the losing sibling are
+            // rejected once the winner is finalized.
+            for e in to_push {
+                let _ = machine.push(e.clone());
+            }
+
+            sequences.push(receiver.try_iter().collect::<Vec<_>>());
+        }
+
+        assert!(sequences[0].len() > 1);
+        assert_eq!(sequences[0][0], X);
+        assert!(sequences[0].iter().all(|e| !y_branch.contains(e)));
+        assert_eq!(sequences[0], sequences[1]);
+    }
+
+    #[test]
+    fn it_recovers_its_state_after_a_restart() {
+        let identities: Vec<Identity> = (0..4).map(|_| Identity::new()).collect();
+        let nodes: Vec<NodeId> = identities.iter().map(|i| NodeId(*i.pkey())).collect();
+        let A = Arc::new(Event::Dummy(NodeId::new([0; 32]), Hash::random(), None, Stamp::seed()));
+        let events = heartbeats(&A, &identities, 20);
+        let dir = tempdir().unwrap();
+        let config = DatabaseConfig::with_columns(None);
+        let db = Arc::new(Database::open(&config, dir.path().to_str().unwrap()).unwrap());
+        let mut storage = PersistentDb::new(db, None);
+        let mut machine = ConsensusMachine::with_db(nodes[0].clone(), A.clone(), ConsensusDb::new(storage.clone()));
+        let mut reference = ConsensusMachine::new(nodes[0].clone(), A.clone());
+
+        for n in nodes.iter() {
+            machine.add_validator(n);
+            reference.add_validator(n);
+        }
+
+        for e in events[..14].iter() {
+            machine.push(e.clone()).unwrap();
+        }
+
+        // The last event is waiting on its parent
+        machine.push(events[16].clone()).unwrap();
+
+        let last_finalized = machine.last_finalized();
+        let finalized_count = machine.finalized_count();
+        let heartbeat_counts: Vec<u16> = nodes.iter().map(|n| machine.validator(n).unwrap().heartbeats()).collect();
+
+        assert_ne!(last_finalized, A);
+        drop(machine);
+
+        let mut machine = ConsensusMachine::load(nodes[0].clone(), A.clone(), ConsensusDb::new(storage.clone())).unwrap();
+
+        assert_eq!(machine.last_finalized(), last_finalized);
+        assert_eq!(machine.finalized_count(), finalized_count);
+        assert_eq!(machine.highest(), events[13]);
+        assert!(machine.contains(&events[16].hash().unwrap()));
+        assert_eq!(
+            nodes.iter().map(|n| machine.validator(n).unwrap().heartbeats()).collect::<Vec<_>>(),
+            heartbeat_counts
+        );
+
+        // The restarted machine should finalize the
+        // same events as a machine that never stopped.
+        for e in events[14..].iter().filter(|e| **e != events[16]) {
+            machine.push(e.clone()).unwrap();
+        }
+
+        for e in events.iter() {
+            reference.push(e.clone()).unwrap();
+        }
+
+        assert!(machine.finalized_count() > finalized_count);
+        assert_eq!(machine.finalized_count(), reference.finalized_count());
+        assert_eq!(machine.last_finalized(), reference.last_finalized());
+        assert_eq!(machine.highest(), events[19]);
+        drop(machine);
+
+        // A missing event is an inconsistency
+        storage.remove(&events[19].hash().unwrap());
+
+        assert_eq!(
+            ConsensusMachine::load(nodes[0].clone(), A.clone(), ConsensusDb::new(storage)).err(),
+            Some("Missing event")
+        );
+    }
+
+    #[test]
+    fn it_serves_finalized_events_to_lagging_nodes() {
+        let identities: Vec<Identity> = (0..4).map(|_| Identity::new()).collect();
+        let nodes: Vec<NodeId> = identities.iter().map(|i| NodeId(*i.pkey())).collect();
+        let A = Arc::new(Event::Dummy(NodeId::new([0; 32]), Hash::random(), None, Stamp::seed()));
+        let events = heartbeats(&A, &identities, 14);
+        let dir = tempdir().unwrap();
+        let config = DatabaseConfig::with_columns(None);
+        let db = Arc::new(Database::open(&config, dir.path().to_str().unwrap()).unwrap());
+        let storage = PersistentDb::new(db, None);
+        let mut machine = ConsensusMachine::with_db(nodes[0].clone(), A.clone(), ConsensusDb::new(storage));
+        let mut lagging = ConsensusMachine::new(nodes[1].clone(), A.clone());
+
+        for n in nodes.iter() {
+            machine.add_validator(n);
+            lagging.add_validator(n);
+        }
+
+        for e in events.iter() {
+            machine.push(e.clone()).unwrap();
+        }
+
+        let A_hash = A.hash().unwrap();
+        let first_hash = events[0].hash().unwrap();
+
+        // The finalized events are still served once pruned
+        assert!(machine.finalized_count() > 1);
+        assert!(!machine.causal_graph.read().contains_hash(&first_hash));
+        assert_eq!(machine.get(&first_hash), Some(events[0].clone()));
+        assert_eq!(machine.following(&A_hash, 2), events[..2].to_vec());
+        assert_eq!(machine.following(&first_hash, 20), events[1..].to_vec());
+
+        // A lagging node catches up from the root event
+        for e in machine.following(&A_hash, 20) {
+            lagging.push(e).unwrap();
+        }
+
+        assert_eq!(lagging.last_finalized(), machine.last_finalized());
+        assert_eq!(lagging.finalized_count(), machine.finalized_count());
+    }
+
+    #[test]
+    fn it_prunes_finalized_events() {
+        let nodes: Vec<NodeId> = (0..4).map(|_| NodeId(*Identity::new().pkey())).collect();
+        let events = chain(None, &nodes, 14);
+        let mut machine = ConsensusMachine::new(nodes[0].clone(), events[0].clone());
+
+        for n in nodes.iter() {
+            machine.add_validator(n);
+        }
+
+        for e in events[1..].iter() {
+            machine.push(e.clone()).unwrap();
+        }
+
+        assert_eq!(machine.last_finalized(), events[6]);
+
+        // The finalized events are still recognized
+        for e in events[..6].iter() {
+            let hash = e.hash().unwrap();
+
+            assert!(machine.get(&hash).is_none());
+            assert!(machine.contains(&hash));
+        }
+
+        assert_eq!(machine.get(&events[6].hash().unwrap()), Some(events[6].clone()));
+        assert_eq!(machine.push(events[2].clone()), Err(CGError::AlreadyInCG));
+
+        // An event cannot follow a pruned event
+        let late = chain(Some(&events[2]), &nodes[3..], 1);
+
+        assert_eq!(machine.push(late[0].clone()), Err(CGError::PrunedParent));
+        assert!(!machine.contains(&late[0].hash().unwrap()));
+        assert_eq!(machine.highest(), events[13]);
+    }
+
+    #[test]
+    /// Causal graph structure:
+    ///
+    /// A -> J -> E1 -> ... -> E12 -> L -> F1 -> ... -> F12
+    ///
+    /// The `Join` event J admits a fifth validator and the
+    /// `Leave` event L removes the second validator once
+    /// they are finalized.
+    fn it_updates_the_validator_set_on_finalized_events() {
+        let identities: Vec<Identity> = (0..5).map(|_| Identity::new()).collect();
+        let nodes: Vec<NodeId> = identities.iter().map(|i| NodeId(*i.pkey())).collect();
+        let A = chain(None, &nodes[..1], 1).remove(0);
+        let collector_address = NormalAddress::from_pkey(*identities[4].pkey());
+
+        let mut join = Join {
+            node_id: nodes[4].clone(),
+            stamp: Stamp::seed().join(A.stamp().peek()).event(),
+            collector_address: collector_address.clone(),
+            proof: Vec::new(),
+            parent_cg_hash: A.hash(),
+            parent_join_hash: None,
+            hash: None,
+            signature: None,
+        };
+
+        let mut bad_proof = join.clone();
+        bad_proof.proof = vec![0, 0];
+
+        while pow::verify(&bad_proof, 8) {
+            bad_proof.proof[1] += 1;
+        }
+
+        bad_proof.sign(identities[4].skey().clone());
+        bad_proof.hash();
+
+        join.proof = pow::mine(&join, 8);
+        join.sign(identities[4].skey().clone());
+        join.hash();
+
+        let bad_proof = Arc::new(Event::Join(bad_proof));
+        let J = Arc::new(Event::Join(join));
+        let E = chain(Some(&J), &[nodes[1].clone(), nodes[2].clone(), nodes[3].clone(), nodes[0].clone()], 12);
+        let last = E[11].clone();
+
+        let mut machine = ConsensusMachine::new(nodes[0].clone(), A);
+        let receiver = machine.subscribe();
+
+        machine.set_difficulty(8);
+
+        for n in nodes[..4].iter() {
+            machine.add_validator(n);
+        }
+
+        assert_eq!(machine.push(bad_proof), Err(CGError::InvalidProof));
+        machine.push(J.clone()).unwrap();
+
+        for e in E.iter() {
+            machine.push(e.clone()).unwrap();
+        }
+
+        let validator = machine.validator(&nodes[4]).unwrap();
+
+        assert_eq!(machine.last_finalized(), E[1]);
+        assert_eq!(validator.join_hash(), J.hash().as_ref());
+        assert_eq!(validator.collector_address(), Some(&collector_address));
+
+        let stamp = Stamp::seed().join(last.stamp().peek()).event();
+        let L = Arc::new(leave(&nodes[1], identities[1].skey(), last.hash().unwrap(), stamp.clone()));
+        let H = Arc::new(heartbeat(&nodes[2], identities[2].skey(), last.hash().unwrap(), stamp));
+
+        // The second node has not issued enough heartbeats in order to leave
+        assert_eq!(machine.push(L.clone()), Err(CGError::EarlyLeave));
+
+        let finalize_heartbeats = |machine: &ConsensusMachine, node_id: &NodeId, count: u16| {
+            for v in machine.validators.iter() {
+                let mut v = v.lock();
+
+                if v.node_id() == node_id {
+                    for _ in 0..count {
+                        v.finalize(&H);
+                    }
+                }
+            }
+        };
+
+        finalize_heartbeats(&machine, &nodes[1], leave_requirement(5));
+
+        assert_eq!(machine.push(L.clone()), Ok(()));
+
+        // The second node no longer issues events once it has left
+        let F = chain(Some(&L), &[nodes[2].clone(), nodes[3].clone(), nodes[0].clone(), nodes[4].clone()], 12);
+
+        for e in F.iter() {
+            machine.push(e.clone()).unwrap();
+        }
+
+        let mut expected = vec![J];
+        expected.extend_from_slice(&E);
+        expected.push(L);
+        expected.extend_from_slice(&F[..5]);
+
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), expected);
+        assert!(!machine.is_validator(&nodes[1]));
+        assert!(machine.is_validator(&nodes[4]));
+    }
+
+    /// Returns a `Join` event following the given parent whose
+    /// proof satisfies exactly the given difficulty.
+    fn join_event(identity: &Identity, parent: &Arc<Event>, difficulty: u8) -> Arc<Event> {
+        let mut join = Join {
+            node_id: NodeId(*identity.pkey()),
+            stamp: Stamp::seed().join(parent.stamp().peek()).event(),
+            collector_address: NormalAddress::from_pkey(*identity.pkey()),
+            proof: vec![0, 0],
+            parent_cg_hash: parent.hash(),
+            parent_join_hash: None,
+            hash: None,
+            signature: None,
+        };
+
+        while !pow::verify(&join, difficulty) || pow::verify(&join, difficulty + 1) {
+            join.proof[1] += 1;
+        }
+
+        join.sign(identity.skey().clone());
+        join.hash();
+
+        Arc::new(Event::Join(join))
+    }
+
+    #[test]
+    /// Causal graph structure:
+    ///
+    /// A -> J1 -> J2
+    /// |
+    /// J3
+    ///
+    /// J1 closes the retarget window once it is finalized so
+    /// the proof of J2 must satisfy the next difficulty while
+    /// the proof of J3 must satisfy the current one.
+    fn it_verifies_joins_against_the_difficulty_at_their_parent() {
+        let identities: Vec<Identity> = (0..6).map(|_| Identity::new()).collect();
+        let nodes: Vec<NodeId> = identities.iter().map(|i| NodeId(*i.pkey())).collect();
+        let A = Arc::new(Event::Dummy(NodeId::new([0; 32]), Hash::random(), None, Stamp::seed()));
+        let mut machine = ConsensusMachine::new(nodes[0].clone(), A.clone());
+
+        for n in nodes[..4].iter() {
+            machine.add_validator(n);
+        }
+
+        machine.set_difficulty(8);
+        machine.window.joins = pow::RETARGET_WINDOW - 1;
+
+        let J1 = join_event(&identities[4], &A, 8);
+        machine.push(J1.clone()).unwrap();
+
+        assert_eq!(machine.difficulty_at(&A.hash().unwrap()), Some(8));
+        assert_eq!(machine.difficulty_at(&J1.hash().unwrap()), Some(9));
+
+        assert_eq!(machine.push(join_event(&identities[5], &J1, 8)), Err(CGError::InvalidProof));
+        assert_eq!(machine.push(join_event(&identities[5], &J1, 9)), Ok(()));
+        assert_eq!(machine.push(join_event(&identities[5], &A, 8)), Ok(()));
+
+        // The current difficulty is retargeted once J1 is finalized
+        assert_eq!(machine.difficulty(), 8);
+    }
+
+    #[test]
+    fn it_evicts_validators_that_reach_the_leave_limit() {
+        let identities: Vec<Identity> = (0..4).map(|_| Identity::new()).collect();
+        let nodes: Vec<NodeId> = identities.iter().map(|i| NodeId(*i.pkey())).collect();
+        let A = Arc::new(Event::Dummy(NodeId::new([0; 32]), Hash::random(), None, Stamp::seed()));
+        let stamp = Stamp::seed().join(A.stamp().peek()).event();
+        let H = Arc::new(heartbeat(&nodes[1], identities[1].skey(), A.hash().unwrap(), stamp));
+        let mut machine = ConsensusMachine::new(nodes[0].clone(), A);
+
+        for n in nodes.iter() {
+            machine.add_validator(n);
+        }
+
+        for _ in 1..leave_limit(4) {
+            machine.apply(&H);
+        }
+
+        assert!(machine.is_validator(&nodes[1]));
+        assert_eq!(machine.validator(&nodes[1]).unwrap().heartbeats(), leave_limit(4) - 1);
+        assert!(machine.is_valid(H.clone()));
+
+        // A validator that has issued the maximum number
+        // of heartbeats cannot issue any more of them.
+        machine.validators[1].lock().finalize(&H);
+        assert_eq!(machine.push(H.clone()), Err(CGError::LeaveLimitReached));
+
+        // The next finalized event evicts the validator
+        machine.apply(&H);
+
+        assert!(!machine.is_validator(&nodes[1]));
+        assert_eq!(machine.validators.len(), 3);
+        assert_eq!(machine.push(H), Err(CGError::NotValidator));
+    }
+
+    #[test]
+    /// Causal graph structure:
+    ///
+    /// A -> H1 -> J1 -> H2 -> J2 -> H3
+    ///
+    /// H1, H2 and H3 are issued by the only validator
+    /// while J1 and J2 are issued by joining nodes.
+    fn it_keeps_finalizing_with_a_single_validator() {
+        let identities: Vec<Identity> = (0..3).map(|_| Identity::new()).collect();
+        let nodes: Vec<NodeId> = identities.iter().map(|i| NodeId(*i.pkey())).collect();
+        let A = Arc::new(Event::Dummy(NodeId::new([0; 32]), Hash::random(), None, Stamp::seed()));
+        let mut machine = ConsensusMachine::new(nodes[0].clone(), A.clone());
+        let receiver = machine.subscribe();
+
+        machine.add_validator(&nodes[0]);
+        machine.set_difficulty(1);
+
+        let stamp = Stamp::seed().join(A.stamp().peek()).event();
+        let H1 = Arc::new(heartbeat(&nodes[0], identities[0].skey(), A.hash().unwrap(), stamp));
+        let J1 = join_event(&identities[1], &H1, 1);
+        let stamp = Stamp::seed().join(J1.stamp().peek()).event();
+        let H2 = Arc::new(heartbeat(&nodes[0], identities[0].skey(), J1.hash().unwrap(), stamp));
+        let J2 = join_event(&identities[2], &H2, 1);
+        let stamp = Stamp::seed().join(J2.stamp().peek()).event();
+        let H3 = Arc::new(heartbeat(&nodes[0], identities[0].skey(), J2.hash().unwrap(), stamp));
+
+        for e in [&H1, &J1, &H2, &J2].iter() {
+            machine.push((*e).clone()).unwrap();
+        }
+
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), vec![H1.clone()]);
+
+        // The validator is not evicted after its first heartbeat
+        assert!(machine.is_validator(&nodes[0]));
+        assert_eq!(machine.validator(&nodes[0]).unwrap().heartbeats(), 1);
+
+        machine.push(H3).unwrap();
+
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), vec![J1]);
+        assert!(machine.is_validator(&nodes[0]));
+        assert!(machine.is_validator(&nodes[1]));
     }
 
     quickcheck! {
@@ -357,9 +2221,11 @@ mod tests {
             let i1 = Identity::new();
             let i2 = Identity::new();
             let i3 = Identity::new();
+            let i4 = Identity::new();
             let n1 = NodeId(*i1.pkey());
             let n2 = NodeId(*i2.pkey());
             let n3 = NodeId(*i3.pkey());
+            let n4 = NodeId(*i4.pkey());
             let seed = Stamp::seed();
             let (s_a, s_b) = seed.fork();
             let (s_b, s_c) = s_b.fork();
@@ -375,7 +2241,7 @@ mod tests {
 
             let s_c = s_c.event();
             let B_prime_hash = Hash::random();
-            let B_prime = Event::Dummy(n3.clone(), B_prime_hash.clone(), Some(A_prime_hash), s_c.clone());
+            let B_prime = Event::Dummy(n4.clone(), B_prime_hash.clone(), Some(A_prime_hash), s_c.clone());
 
             let s_d = s_d.join(s_c.peek()).event();
             let B_second = Event::Dummy(n3.clone(), Hash::random(), Some(B_prime_hash.clone()), s_d.clone());
@@ -385,7 +2251,7 @@ mod tests {
             let C_prime = Event::Dummy(n3.clone(), C_prime_hash.clone(), Some(B_prime_hash), s_c.clone());
 
             let s_c = s_c.event();
-            let D_prime = Event::Dummy(n3, Hash::random(), Some(C_prime_hash.clone()), s_c);
+            let D_prime = Event::Dummy(n4.clone(), Hash::random(), Some(C_prime_hash.clone()), s_c);
 
             let s_b = s_b.join(s_a.peek()).event();
             let B_hash = Hash::random();
@@ -456,7 +2322,11 @@ mod tests {
             // of the order in which the events are pushed.
             thread_rng().shuffle(&mut events);
 
-            let mut machine = ConsensusMachine::new(n1, A.clone());
+            let mut machine = ConsensusMachine::new(n1.clone(), A.clone());
+            machine.add_validator(&n1);
+            machine.add_validator(&n2);
+            machine.add_validator(&n3);
+            machine.add_validator(&n4);
 
             for e in events {
                 machine.push(e).unwrap();
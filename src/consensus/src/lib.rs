@@ -20,10 +20,13 @@
 
 mod candidate_set;
 mod causal_graph;
+mod consensus_db;
 mod consensus_machine;
 pub mod parameters;
+pub mod pow;
 mod validator_state;
 
 pub use candidate_set::*;
+pub use consensus_db::*;
 pub use consensus_machine::*;
 pub use validator_state::*;
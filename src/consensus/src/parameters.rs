@@ -37,11 +37,20 @@ pub fn proposal_requirement(node_count: u16) -> u16 {
     ((node_count as f32 + 1.0) / 2.0).trunc() as u16
 }
 
-/// The number of required proposals for a `CandidateSet`
-/// in order to be considered valid for inclusion into
-/// the total order.
+/// The number of distinct nodes that are required to
+/// propose for a `CandidateSet` in order for it to be
+/// considered valid for inclusion into the total order.
+///
+/// This is more than two thirds of the nodes. A node is
+/// counted for at most one of the competing sets, so two
+/// of them can only be valid in the views of two nodes if
+/// more than a third of the nodes are counted for both,
+/// which requires them to fork their history. A majority
+/// would allow two halves of the nodes to finalize two
+/// competing sets and `node_count + 1` distinct nodes
+/// can never be reached.
 pub fn required_proposal(node_count: u16) -> u16 {
-    node_count + 1
+    2 * node_count / 3 + 1
 }
 
 /// The number if `Heartbeat` events that a node is required
@@ -0,0 +1,310 @@
+/*
+  Copyright 2018 The Purple Library Authors
+  This file is part of the Purple Library.
+
+  The Purple Library is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  The Purple Library is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with the Purple Library. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use crypto::Hash;
+use events::Join;
+
+/// The number of words of a proof. A proof is
+/// a 64 bit nonce split into two 32 bit words.
+pub const PROOF_LEN: usize = 2;
+
+/// The difficulty of the first retarget window.
+pub const DEFAULT_DIFFICULTY: u8 = 24;
+
+/// The lowest difficulty that can be retargeted to.
+pub const MIN_DIFFICULTY: u8 = 1;
+
+/// The highest difficulty that can be retargeted to.
+pub const MAX_DIFFICULTY: u8 = 64;
+
+/// The number of finalized events that should
+/// elapse on average between two `Join` events.
+pub const TARGET_JOIN_INTERVAL: u64 = 1000;
+
+/// The number of finalized `Join` events after
+/// which the difficulty is retargeted.
+pub const RETARGET_WINDOW: u64 = 16;
+
+/// The number of finalized events after which the
+/// difficulty is retargeted even if fewer than
+/// `RETARGET_WINDOW` validators have joined.
+pub const MAX_WINDOW_SPAN: u64 = 2 * TARGET_JOIN_INTERVAL * RETARGET_WINDOW;
+
+/// The window of finalized events over which
+/// the rate at which validators join is measured.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RetargetWindow {
+    /// The difficulty in force during the window.
+    pub difficulty: u8,
+
+    /// The number of finalized `Join` events
+    /// that admitted a validator in the window.
+    pub joins: u64,
+
+    /// The number of finalized events at
+    /// the start of the window.
+    pub start: u64,
+}
+
+impl RetargetWindow {
+    pub fn new(difficulty: u8) -> RetargetWindow {
+        RetargetWindow {
+            difficulty,
+            joins: 0,
+            start: 0,
+        }
+    }
+
+    /// Records a finalized event, given the number of events
+    /// finalized before it and whether it admitted a validator.
+    ///
+    /// The difficulty is retargeted once `RETARGET_WINDOW`
+    /// validators have joined or once `MAX_WINDOW_SPAN` events
+    /// have been finalized, so that it is lowered when the
+    /// difficulty is too high for validators to join at all.
+    pub fn finalize(&mut self, finalized_count: u64, joined: bool) {
+        if joined {
+            self.joins += 1;
+        }
+
+        let elapsed = finalized_count - self.start;
+
+        if self.joins >= RETARGET_WINDOW || elapsed > MAX_WINDOW_SPAN {
+            self.difficulty = retarget(self.difficulty, elapsed);
+            self.joins = 0;
+            self.start = finalized_count;
+        }
+    }
+}
+
+/// Returns `true` if the proof of the `Join` event
+/// satisfies the given difficulty.
+///
+/// The proof is valid if the hash of the node id, the
+/// parent hashes and the proof of the event starts with
+/// at least `difficulty` zero bits.
+pub fn verify(join: &Join, difficulty: u8) -> bool {
+    if join.proof.len() != PROOF_LEN {
+        return false;
+    }
+
+    let message = assemble_message(join);
+    leading_zeros(&proof_hash(&message, &join.proof)) >= difficulty as u32
+}
+
+/// Searches for a proof of the `Join` event that
+/// satisfies the given difficulty.
+///
+/// The expected number of attempts is `2^difficulty`.
+pub fn mine(join: &Join, difficulty: u8) -> Vec<u32> {
+    let message = assemble_message(join);
+    let mut nonce: u64 = 0;
+
+    loop {
+        let proof = vec![(nonce >> 32) as u32, nonce as u32];
+
+        if leading_zeros(&proof_hash(&message, &proof)) >= difficulty as u32 {
+            return proof;
+        }
+
+        nonce = nonce.wrapping_add(1);
+    }
+}
+
+/// Returns the difficulty of the next retarget window based
+/// on the number of events that were finalized while the last
+/// `RETARGET_WINDOW` validators joined.
+///
+/// The difficulty is raised by one bit, which doubles the
+/// required work, when validators join more than twice as
+/// fast as targeted and it is lowered by one bit when they
+/// join more than twice as slow.
+pub fn retarget(difficulty: u8, elapsed: u64) -> u8 {
+    let target = TARGET_JOIN_INTERVAL * RETARGET_WINDOW;
+
+    if elapsed < target / 2 && difficulty < MAX_DIFFICULTY {
+        difficulty + 1
+    } else if elapsed > target * 2 && difficulty > MIN_DIFFICULTY {
+        difficulty - 1
+    } else {
+        difficulty
+    }
+}
+
+fn proof_hash(message: &[u8], proof: &[u32]) -> Hash {
+    let mut buf: Vec<u8> = Vec::with_capacity(message.len() + 4 * proof.len());
+
+    buf.extend_from_slice(message);
+
+    for word in proof.iter() {
+        buf.push((word >> 24) as u8);
+        buf.push((word >> 16) as u8);
+        buf.push((word >> 8) as u8);
+        buf.push(*word as u8);
+    }
+
+    crypto::hash_slice(&buf)
+}
+
+fn leading_zeros(hash: &Hash) -> u32 {
+    let mut zeros = 0;
+
+    for byte in hash.0.iter() {
+        zeros += byte.leading_zeros();
+
+        if *byte != 0 {
+            break;
+        }
+    }
+
+    zeros
+}
+
+fn assemble_message(join: &Join) -> Vec<u8> {
+    let mut buf: Vec<u8> = Vec::new();
+    let node_id = (join.node_id.0).0;
+    let parent_cg_hash = join.parent_cg_hash.unwrap_or(Hash([0; 32]));
+    let parent_join_hash = join.parent_join_hash.unwrap_or(Hash([0; 32]));
+
+    buf.extend_from_slice(&node_id);
+    buf.extend_from_slice(&parent_cg_hash.0);
+    buf.extend_from_slice(&parent_join_hash.0);
+
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use account::NormalAddress;
+    use causality::Stamp;
+    use crypto::Identity;
+    use network::NodeId;
+
+    fn join() -> Join {
+        let identity = Identity::new();
+
+        Join {
+            node_id: NodeId(*identity.pkey()),
+            stamp: Stamp::seed(),
+            collector_address: NormalAddress::from_pkey(*identity.pkey()),
+            proof: Vec::new(),
+            parent_cg_hash: Some(Hash::random()),
+            parent_join_hash: Some(Hash::random()),
+            hash: None,
+            signature: None,
+        }
+    }
+
+    #[test]
+    fn it_mines_and_verifies_proofs() {
+        let mut join = join();
+
+        assert!(!verify(&join, 0));
+
+        join.proof = mine(&join, 8);
+
+        assert!(verify(&join, 0));
+        assert!(verify(&join, 8));
+        assert_eq!(join.proof.len(), PROOF_LEN);
+
+        let mut other_node = join.clone();
+        let mut other_cg_parent = join.clone();
+        let mut other_join_parent = join.clone();
+
+        other_node.node_id = NodeId(*Identity::new().pkey());
+        other_cg_parent.parent_cg_hash = Some(Hash::random());
+        other_join_parent.parent_join_hash = None;
+
+        // The proof is bound to the node id and
+        // to the parent hashes of the event.
+        let hash = proof_hash(&assemble_message(&join), &join.proof);
+
+        for changed in vec![other_node, other_cg_parent, other_join_parent] {
+            assert!(proof_hash(&assemble_message(&changed), &join.proof) != hash);
+        }
+    }
+
+    #[test]
+    fn it_rejects_proofs_below_the_difficulty() {
+        let mut join = join();
+        join.proof = vec![0, 0];
+
+        // Find a proof that has exactly 4 leading zero bits
+        while leading_zeros(&proof_hash(&assemble_message(&join), &join.proof)) != 4 {
+            join.proof[1] += 1;
+        }
+
+        assert!(verify(&join, 4));
+        assert!(!verify(&join, 5));
+    }
+
+    #[test]
+    fn it_retargets_the_difficulty() {
+        let target = TARGET_JOIN_INTERVAL * RETARGET_WINDOW;
+
+        assert_eq!(retarget(10, target), 10);
+        assert_eq!(retarget(10, target / 2), 10);
+        assert_eq!(retarget(10, target * 2), 10);
+        assert_eq!(retarget(10, target / 4), 11);
+        assert_eq!(retarget(10, target * 4), 9);
+        assert_eq!(retarget(MAX_DIFFICULTY, 0), MAX_DIFFICULTY);
+        assert_eq!(retarget(MIN_DIFFICULTY, target * 4), MIN_DIFFICULTY);
+    }
+
+    #[test]
+    fn it_closes_the_window_on_joins_or_elapsed_events() {
+        let mut window = RetargetWindow::new(10);
+
+        for i in 0..RETARGET_WINDOW - 1 {
+            window.finalize(i, true);
+        }
+
+        assert_eq!(window.joins, RETARGET_WINDOW - 1);
+        assert_eq!(window.difficulty, 10);
+
+        // Validators join too fast
+        window.finalize(RETARGET_WINDOW, true);
+
+        assert_eq!(window.difficulty, 11);
+        assert_eq!(window.joins, 0);
+        assert_eq!(window.start, RETARGET_WINDOW);
+
+        // No validator joins at all
+        window.finalize(RETARGET_WINDOW + MAX_WINDOW_SPAN, false);
+        assert_eq!(window.difficulty, 11);
+
+        window.finalize(RETARGET_WINDOW + MAX_WINDOW_SPAN + 1, false);
+
+        assert_eq!(window.difficulty, 10);
+        assert_eq!(window.start, RETARGET_WINDOW + MAX_WINDOW_SPAN + 1);
+    }
+
+    #[test]
+    fn leading_zeros_counts_bits() {
+        let mut hash = Hash([0; 32]);
+        assert_eq!(leading_zeros(&hash), 256);
+
+        hash.0[0] = 1;
+        assert_eq!(leading_zeros(&hash), 7);
+
+        hash.0[0] = 0;
+        hash.0[1] = 0x10;
+        assert_eq!(leading_zeros(&hash), 11);
+    }
+}
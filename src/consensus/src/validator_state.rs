@@ -16,18 +16,322 @@
   along with the Purple Library. If not, see <http://www.gnu.org/licenses/>.
 */
 
+use crate::parameters::*;
+use account::NormalAddress;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use crypto::Hash;
+use events::{Event, Join};
 use network::NodeId;
+use std::io::Cursor;
+use std::sync::Arc;
 
 #[derive(Clone, Debug)]
 pub struct ValidatorState {
     /// The node id of the validator
-    node_id: NodeId, // TODO: Add more relevant fields
+    node_id: NodeId,
+
+    /// The hash of the `Join` event that admitted the
+    /// validator. This is `None` for the validators
+    /// that are part of the initial validator set.
+    join_hash: Option<Hash>,
+
+    /// The address which collects the funds
+    /// accumulated by the validator.
+    collector_address: Option<NormalAddress>,
+
+    /// The number of finalized `Heartbeat` events
+    /// issued by the validator.
+    heartbeats: u16,
+
+    /// The last event issued by the validator
+    last_event: Option<Arc<Event>>,
+
+    /// The number of distinct nodes, other than the
+    /// validator, that follow its last event.
+    followers: u16,
 }
 
 impl ValidatorState {
     pub fn new(node_id: &NodeId) -> ValidatorState {
         ValidatorState {
             node_id: node_id.clone(),
+            join_hash: None,
+            collector_address: None,
+            heartbeats: 0,
+            last_event: None,
+            followers: 0,
+        }
+    }
+
+    /// Creates the state of a validator that
+    /// is admitted by the given `Join` event.
+    pub fn from_join(join: &Join) -> ValidatorState {
+        ValidatorState {
+            node_id: join.node_id.clone(),
+            join_hash: join.hash.clone(),
+            collector_address: Some(join.collector_address.clone()),
+            heartbeats: 0,
+            last_event: None,
+            followers: 0,
+        }
+    }
+
+    /// Returns the node id of the validator.
+    pub fn node_id(&self) -> &NodeId {
+        &self.node_id
+    }
+
+    /// Returns the hash of the `Join` event
+    /// that admitted the validator.
+    pub fn join_hash(&self) -> Option<&Hash> {
+        self.join_hash.as_ref()
+    }
+
+    /// Returns the address which collects the funds
+    /// accumulated by the validator.
+    pub fn collector_address(&self) -> Option<&NormalAddress> {
+        self.collector_address.as_ref()
+    }
+
+    /// Returns the number of finalized `Heartbeat`
+    /// events issued by the validator.
+    pub fn heartbeats(&self) -> u16 {
+        self.heartbeats
+    }
+
+    /// Returns the last event issued by the validator.
+    pub fn last_event(&self) -> Option<Arc<Event>> {
+        self.last_event.clone()
+    }
+
+    /// Returns the number of distinct nodes that
+    /// follow the last event of the validator.
+    pub fn followers(&self) -> u16 {
+        self.followers
+    }
+
+    /// Records an event issued by the validator
+    /// that has been pushed to the causal graph.
+    pub fn set_last_event(&mut self, event: Arc<Event>) {
+        self.last_event = Some(event);
+        self.followers = 0;
+    }
+
+    /// Sets the number of distinct nodes that follow
+    /// the last event of the validator.
+    pub fn set_followers(&mut self, followers: u16) {
+        self.followers = followers;
+    }
+
+    /// Records an event issued by the validator
+    /// that has been finalized.
+    pub fn finalize(&mut self, event: &Event) {
+        if let Event::Heartbeat(_) = event {
+            self.heartbeats = self.heartbeats.saturating_add(1);
+        }
+    }
+
+    /// Returns `true` if the validator has issued enough
+    /// `Heartbeat` events in order to send a `Leave` event.
+    ///
+    /// A single validator can never leave since the
+    /// validator set would be left empty.
+    pub fn can_leave(&self, node_count: u16) -> bool {
+        node_count > 1 && self.heartbeats >= leave_requirement(node_count)
+    }
+
+    /// Returns `true` if the validator has issued the maximum
+    /// number of `Heartbeat` events and **must** send a `Leave`
+    /// event.
+    ///
+    /// A single validator is never required to leave
+    /// since the validator set would be left empty.
+    pub fn must_leave(&self, node_count: u16) -> bool {
+        node_count > 1 && self.heartbeats >= leave_limit(node_count)
+    }
+
+    /// Returns `true` if the validator is eligible
+    /// to send a `Heartbeat` event.
+    pub fn can_send_heartbeat(&self, node_count: u16) -> bool {
+        if self.must_leave(node_count) {
+            return false;
+        }
+
+        match self.last_event {
+            Some(_) => self.followers >= heartbeat_requirement(node_count),
+            None => true,
+        }
+    }
+
+    /// Serializes the validator state.
+    ///
+    /// The last event is omitted if it cannot be serialized.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut header: Vec<u8> = Vec::with_capacity(36);
+
+        // Header structure:
+        // 1) Node id     - 32 bytes
+        // 2) Heartbeats  - 16bits
+        // 3) Followers   - 16bits
+        header.extend_from_slice(&(self.node_id.0).0);
+        header.write_u16::<BigEndian>(self.heartbeats).unwrap();
+        header.write_u16::<BigEndian>(self.followers).unwrap();
+
+        let join_hash = match self.join_hash {
+            Some(ref hash) => hash.0.to_vec(),
+            None => vec![],
+        };
+
+        let collector_address = match self.collector_address {
+            Some(ref address) => address.to_bytes(),
+            None => vec![],
+        };
+
+        let last_event = match self.last_event {
+            Some(ref event) => event.to_bytes().unwrap_or_default(),
+            None => vec![],
+        };
+
+        rlp::encode_list::<Vec<u8>, _>(&[header, join_hash, collector_address, last_event])
+    }
+
+    /// Deserializes a validator state.
+    pub fn from_bytes(bin: &[u8]) -> Result<ValidatorState, &'static str> {
+        let fields: Vec<Vec<u8>> = match rlp::Rlp::new(bin).as_list() {
+            Ok(fields) => fields,
+            Err(_) => return Err("Bad validator state"),
+        };
+
+        if fields.len() != 4 {
+            return Err("Bad validator state");
+        }
+
+        if fields[0].len() != 36 {
+            return Err("Bad header length");
         }
+
+        let mut node_id = [0; 32];
+        node_id.copy_from_slice(&fields[0][..32]);
+
+        let mut rdr = Cursor::new(&fields[0][32..]);
+        let heartbeats = rdr.read_u16::<BigEndian>().unwrap();
+        let followers = rdr.read_u16::<BigEndian>().unwrap();
+
+        let join_hash = match fields[1].len() {
+            0 => None,
+            32 => {
+                let mut hash = [0; 32];
+                hash.copy_from_slice(&fields[1]);
+                Some(Hash(hash))
+            }
+            _ => return Err("Bad join hash length"),
+        };
+
+        let collector_address = if fields[2].is_empty() {
+            None
+        } else {
+            Some(NormalAddress::from_bytes(&fields[2])?)
+        };
+
+        let last_event = if fields[3].is_empty() {
+            None
+        } else {
+            Some(Arc::new(Event::from_bytes(&fields[3])?))
+        };
+
+        Ok(ValidatorState {
+            node_id: NodeId::new(node_id),
+            join_hash,
+            collector_address,
+            heartbeats,
+            last_event,
+            followers,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use causality::Stamp;
+    use crypto::Identity;
+
+    #[test]
+    fn it_enforces_the_leave_requirement_and_limit() {
+        let node_id = NodeId(*Identity::new().pkey());
+        let heartbeat = Event::Dummy(node_id.clone(), Hash::random(), None, Stamp::seed());
+        let mut state = ValidatorState::new(&node_id);
+
+        // Dummy events are not heartbeats
+        state.finalize(&heartbeat);
+        assert_eq!(state.heartbeats(), 0);
+
+        state.heartbeats = leave_requirement(4) - 1;
+        assert!(!state.can_leave(4));
+        assert!(!state.must_leave(4));
+        assert!(state.can_send_heartbeat(4));
+
+        state.heartbeats = leave_requirement(4);
+        assert!(state.can_leave(4));
+        assert!(!state.must_leave(4));
+
+        state.heartbeats = leave_limit(4);
+        assert!(state.can_leave(4));
+        assert!(state.must_leave(4));
+        assert!(!state.can_send_heartbeat(4));
+
+        // A single validator can neither leave nor be evicted
+        state.heartbeats = 0;
+        assert!(!state.can_leave(1));
+        assert!(!state.must_leave(1));
+        assert!(state.can_send_heartbeat(1));
+
+        state.heartbeats = u16::max_value();
+        assert!(!state.can_leave(1));
+        assert!(!state.must_leave(1));
+        assert!(state.can_send_heartbeat(1));
+    }
+
+    #[test]
+    fn it_requires_followers_of_the_last_event_for_heartbeats() {
+        let node_id = NodeId(*Identity::new().pkey());
+        let event = Arc::new(Event::Dummy(node_id.clone(), Hash::random(), None, Stamp::seed()));
+        let mut state = ValidatorState::new(&node_id);
+
+        assert!(state.can_send_heartbeat(4));
+
+        state.set_last_event(event.clone());
+        assert_eq!(state.last_event(), Some(event.clone()));
+        assert!(!state.can_send_heartbeat(4));
+
+        state.set_followers(heartbeat_requirement(4));
+        assert!(state.can_send_heartbeat(4));
+
+        // A new event resets the followers
+        state.set_last_event(event);
+        assert!(!state.can_send_heartbeat(4));
+    }
+
+    #[test]
+    fn serialize_deserialize() {
+        let node_id = NodeId(*Identity::new().pkey());
+        let event = Arc::new(Event::Dummy(node_id.clone(), Hash::random(), None, Stamp::seed()));
+        let mut state = ValidatorState::new(&node_id);
+
+        state.heartbeats = 12;
+        state.join_hash = Some(Hash::random());
+        state.set_last_event(event);
+        state.set_followers(3);
+
+        let deserialized = ValidatorState::from_bytes(&state.to_bytes()).unwrap();
+
+        assert_eq!(deserialized.node_id(), state.node_id());
+        assert_eq!(deserialized.join_hash(), state.join_hash());
+        assert_eq!(deserialized.heartbeats(), 12);
+        assert_eq!(deserialized.followers(), 3);
+
+        // Dummy events cannot be serialized
+        assert!(deserialized.last_event().is_none());
+        assert!(ValidatorState::from_bytes(&[1, 2, 3]).is_err());
     }
 }
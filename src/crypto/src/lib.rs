@@ -33,8 +33,10 @@ pub use blake_hasher::*;
 pub use hash::*;
 pub use rust_base58::base58::*;
 pub use rust_sodium::crypto::kx::{
-    gen_keypair as gen_kx_keypair, PublicKey as KxPublicKey, SecretKey as KxSecretKey, SessionKey,
+    client_session_keys, gen_keypair as gen_kx_keypair, server_session_keys,
+    PublicKey as KxPublicKey, SecretKey as KxSecretKey, SessionKey,
 };
+pub use rust_sodium::crypto::secretbox;
 pub use rust_sodium::crypto::sign::{gen_keypair, PublicKey, SecretKey};
 pub use signature::*;
 
@@ -96,6 +96,19 @@ impl Heartbeat {
         self.signature = Some(signature);
     }
 
+    /// Verifies the signature of the event against
+    /// the node id of its sender.
+    ///
+    /// Returns `false` if the signature field is missing.
+    pub fn verify_sig(&mut self) -> bool {
+        let message = assemble_sign_message(&self);
+
+        match self.signature {
+            Some(ref sig) => crypto::verify(&message, sig.clone(), self.node_id.0),
+            None => false,
+        }
+    }
+
     /// Serializes a heartbeat struct.
     ///
     /// All fields are written in big endian.
@@ -268,13 +281,26 @@ impl Heartbeat {
         };
 
         let transactions = if buf.len() == txs_len as usize {
-            let ser_txs: Vec<Vec<u8>> = rlp::decode_list(&buf);
+            let ser_txs: Vec<Vec<u8>> = match rlp::Rlp::new(&buf).as_list() {
+                Ok(ser_txs) => ser_txs,
+                Err(_) => return Err("Bad transactions"),
+            };
+
+            // Decoding stops at the first malformed item so the
+            // transactions must encode back to the same bytes.
+            if rlp::encode_list::<Vec<u8>, _>(&ser_txs)[..] != buf[..] {
+                return Err("Bad transactions");
+            }
+
             let txs: Result<Vec<Box<Tx>>, _> = ser_txs
                 .par_iter()
                 .map(|tx| {
-                    let tx_type = &tx[0];
+                    let tx_type = match tx.first() {
+                        Some(tx_type) => *tx_type,
+                        None => return Err("Bad transaction type"),
+                    };
 
-                    match *tx_type {
+                    match tx_type {
                         1 => {
                             let deserialized = match Call::from_bytes(&tx) {
                                 Ok(result) => result,
@@ -393,11 +419,39 @@ impl Heartbeat {
 }
 
 fn assemble_hash_message(obj: &Heartbeat) -> Vec<u8> {
-    unimplemented!();
+    let mut signature = if let Some(ref sig) = obj.signature {
+        sig.to_bytes()
+    } else {
+        panic!("Signature field is missing!");
+    };
+
+    let mut buf: Vec<u8> = assemble_sign_message(obj);
+
+    // Compose data to hash
+    buf.append(&mut signature);
+
+    buf
 }
 
 fn assemble_sign_message(obj: &Heartbeat) -> Vec<u8> {
-    unimplemented!();
+    let root_hash = if let Some(ref root_hash) = obj.root_hash {
+        root_hash.0
+    } else {
+        panic!("Root hash field is missing!");
+    };
+
+    let mut buf: Vec<u8> = Vec::new();
+    let node_id = (obj.node_id.0).0;
+    let parent_hash = obj.parent_hash.0;
+    let mut stamp = obj.stamp.to_bytes();
+
+    // Compose data to sign
+    buf.append(&mut node_id.to_vec());
+    buf.append(&mut parent_hash.to_vec());
+    buf.append(&mut root_hash.to_vec());
+    buf.append(&mut stamp);
+
+    buf
 }
 
 #[cfg(test)]
@@ -427,10 +481,56 @@ impl Arbitrary for Heartbeat {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crypto::Identity;
 
     quickcheck! {
         fn serialize_deserialize(tx: Heartbeat) -> bool {
             tx == Heartbeat::from_bytes(&Heartbeat::to_bytes(&tx).unwrap()).unwrap()
         }
+
+        fn verify_signature(heartbeat: Heartbeat) -> bool {
+            let id = Identity::new();
+            let mut heartbeat = heartbeat;
+
+            heartbeat.node_id = NodeId(*id.pkey());
+            heartbeat.sign(id.skey().clone());
+            heartbeat.hash();
+
+            let mut forged = heartbeat.clone();
+            forged.parent_hash = Hash::random();
+
+            heartbeat.verify_sig() && heartbeat.verify_hash() && !forged.verify_sig() && !forged.verify_hash()
+        }
+
+        fn it_rejects_truncated_bytes(heartbeat: Heartbeat, len: usize) -> bool {
+            let bin = Heartbeat::to_bytes(&heartbeat).unwrap();
+            Heartbeat::from_bytes(&bin[..len % bin.len()]).is_err()
+        }
+
+        fn it_rejects_malformed_transactions(heartbeat: Heartbeat) -> bool {
+            let bin = Heartbeat::to_bytes(&heartbeat).unwrap();
+            let txs_len = Cursor::new(&bin[3..7]).read_u32::<BigEndian>().unwrap() as usize;
+            let header = &bin[..bin.len() - txs_len];
+
+            // An empty transaction, a truncated list, trailing
+            // bytes and a string instead of a list.
+            let malformed: Vec<Vec<u8>> = vec![
+                vec![0xc1, 0x80],
+                vec![0xc3, 0x01],
+                vec![0xc0, 0x00],
+                vec![0x83, 0x01, 0x02, 0x03],
+            ];
+
+            malformed.iter().all(|txs| {
+                let mut len = Vec::new();
+                len.write_u32::<BigEndian>(txs.len() as u32).unwrap();
+
+                let mut bin = header.to_vec();
+                bin[3..7].copy_from_slice(&len);
+                bin.extend_from_slice(txs);
+
+                Heartbeat::from_bytes(&bin).is_err()
+            })
+        }
     }
 }
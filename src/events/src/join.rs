@@ -20,7 +20,7 @@ use account::NormalAddress;
 use bitvec::Bits;
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use causality::Stamp;
-use crypto::{Hash, PublicKey, Signature};
+use crypto::{Hash, PublicKey, SecretKey as Sk, Signature};
 use network::NodeId;
 use std::io::Cursor;
 
@@ -56,6 +56,30 @@ pub struct Join {
 impl Join {
     pub const EVENT_TYPE: u8 = 1;
 
+    /// Signs the event with the given secret key.
+    pub fn sign(&mut self, skey: Sk) {
+        // Assemble data
+        let message = assemble_sign_message(&self);
+
+        // Sign data
+        let signature = crypto::sign(&message, skey);
+
+        self.signature = Some(signature);
+    }
+
+    /// Verifies the signature of the event against
+    /// the node id of its sender.
+    ///
+    /// Returns `false` if the signature field is missing.
+    pub fn verify_sig(&mut self) -> bool {
+        let message = assemble_sign_message(&self);
+
+        match self.signature {
+            Some(ref sig) => crypto::verify(&message, sig.clone(), self.node_id.0),
+            None => false,
+        }
+    }
+
     /// Serializes a heartbeat struct.
     ///
     /// All fields are written in big endian.
@@ -270,7 +294,16 @@ impl Join {
         };
 
         let proof = if buf.len() == proof_len as usize {
-            let proof: Vec<u32> = rlp::decode_list(&buf);
+            let proof: Vec<u32> = match rlp::Rlp::new(&buf).as_list() {
+                Ok(proof) => proof,
+                Err(_) => return Err("Bad proof"),
+            };
+
+            // Reject truncated and non-canonical proofs
+            if rlp::encode_list::<u32, _>(&proof)[..] != buf[..] {
+                return Err("Bad proof");
+            }
+
             proof
         } else {
             return Err("Incorrect packet structure! Buffer size is smaller than the proof length");
@@ -289,6 +322,50 @@ impl Join {
 
         Ok(join)
     }
+
+    impl_hash!();
+}
+
+fn assemble_hash_message(obj: &Join) -> Vec<u8> {
+    let mut signature = if let Some(ref sig) = obj.signature {
+        sig.to_bytes()
+    } else {
+        panic!("Signature field is missing!");
+    };
+
+    let mut buf: Vec<u8> = assemble_sign_message(obj);
+
+    // Compose data to hash
+    buf.append(&mut signature);
+
+    buf
+}
+
+fn assemble_sign_message(obj: &Join) -> Vec<u8> {
+    let mut buf: Vec<u8> = Vec::new();
+    let node_id = (obj.node_id.0).0;
+    let mut collector_address = obj.collector_address.to_bytes();
+    let mut stamp = obj.stamp.to_bytes();
+
+    // Compose data to sign
+    buf.append(&mut node_id.to_vec());
+    buf.append(&mut collector_address);
+
+    if let Some(ref parent_cg_hash) = obj.parent_cg_hash {
+        buf.append(&mut parent_cg_hash.0.to_vec());
+    }
+
+    if let Some(ref parent_join_hash) = obj.parent_join_hash {
+        buf.append(&mut parent_join_hash.0.to_vec());
+    }
+
+    buf.append(&mut stamp);
+
+    for nonce in obj.proof.iter() {
+        buf.write_u32::<BigEndian>(*nonce).unwrap();
+    }
+
+    buf
 }
 
 #[cfg(test)]
@@ -313,10 +390,56 @@ impl Arbitrary for Join {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crypto::Identity;
 
     quickcheck! {
         fn serialize_deserialize(tx: Join) -> bool {
             tx == Join::from_bytes(&Join::to_bytes(&tx).unwrap()).unwrap()
         }
+
+        fn verify_signature(join: Join) -> bool {
+            let id = Identity::new();
+            let mut join = join;
+
+            join.node_id = NodeId(*id.pkey());
+            join.sign(id.skey().clone());
+            join.hash();
+
+            let mut forged = join.clone();
+            forged.parent_cg_hash = Some(Hash::random());
+
+            join.verify_sig() && join.verify_hash() && !forged.verify_sig() && !forged.verify_hash()
+        }
+
+        fn it_rejects_truncated_bytes(join: Join, len: usize) -> bool {
+            let bin = Join::to_bytes(&join).unwrap();
+            Join::from_bytes(&bin[..len % bin.len()]).is_err()
+        }
+
+        fn it_rejects_malformed_proofs(join: Join) -> bool {
+            let bin = Join::to_bytes(&join).unwrap();
+            let proof_len = Cursor::new(&bin[4..6]).read_u16::<BigEndian>().unwrap() as usize;
+            let header = &bin[..bin.len() - proof_len];
+
+            // A nested list, a truncated list, trailing
+            // bytes and a string instead of a list.
+            let malformed: Vec<Vec<u8>> = vec![
+                vec![0xc1, 0xc0],
+                vec![0xc3, 0x01],
+                vec![0xc0, 0x00],
+                vec![0x83, 0x01, 0x02, 0x03],
+            ];
+
+            malformed.iter().all(|proof| {
+                let mut len = Vec::new();
+                len.write_u16::<BigEndian>(proof.len() as u16).unwrap();
+
+                let mut bin = header.to_vec();
+                bin[4..6].copy_from_slice(&len);
+                bin.extend_from_slice(proof);
+
+                Join::from_bytes(&bin).is_err()
+            })
+        }
     }
 }
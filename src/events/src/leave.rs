@@ -18,7 +18,7 @@
 
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use causality::Stamp;
-use crypto::{Hash, PublicKey, Signature};
+use crypto::{Hash, PublicKey, SecretKey as Sk, Signature};
 use network::NodeId;
 use std::io::Cursor;
 
@@ -43,6 +43,30 @@ pub struct Leave {
 impl Leave {
     pub const EVENT_TYPE: u8 = 2;
 
+    /// Signs the event with the given secret key.
+    pub fn sign(&mut self, skey: Sk) {
+        // Assemble data
+        let message = assemble_sign_message(&self);
+
+        // Sign data
+        let signature = crypto::sign(&message, skey);
+
+        self.signature = Some(signature);
+    }
+
+    /// Verifies the signature of the event against
+    /// the node id of its sender.
+    ///
+    /// Returns `false` if the signature field is missing.
+    pub fn verify_sig(&mut self) -> bool {
+        let message = assemble_sign_message(&self);
+
+        match self.signature {
+            Some(ref sig) => crypto::verify(&message, sig.clone(), self.node_id.0),
+            None => false,
+        }
+    }
+
     /// Serializes a heartbeat struct.
     ///
     /// All fields are written in big endian.
@@ -175,6 +199,37 @@ impl Leave {
 
         Ok(leave)
     }
+
+    impl_hash!();
+}
+
+fn assemble_hash_message(obj: &Leave) -> Vec<u8> {
+    let mut signature = if let Some(ref sig) = obj.signature {
+        sig.to_bytes()
+    } else {
+        panic!("Signature field is missing!");
+    };
+
+    let mut buf: Vec<u8> = assemble_sign_message(obj);
+
+    // Compose data to hash
+    buf.append(&mut signature);
+
+    buf
+}
+
+fn assemble_sign_message(obj: &Leave) -> Vec<u8> {
+    let mut buf: Vec<u8> = Vec::new();
+    let node_id = (obj.node_id.0).0;
+    let parent_hash = obj.parent_hash.0;
+    let mut stamp = obj.stamp.to_bytes();
+
+    // Compose data to sign
+    buf.append(&mut node_id.to_vec());
+    buf.append(&mut parent_hash.to_vec());
+    buf.append(&mut stamp);
+
+    buf
 }
 
 #[cfg(test)]
@@ -196,10 +251,25 @@ impl Arbitrary for Leave {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crypto::Identity;
 
     quickcheck! {
         fn serialize_deserialize(tx: Leave) -> bool {
             tx == Leave::from_bytes(&Leave::to_bytes(&tx).unwrap()).unwrap()
         }
+
+        fn verify_signature(leave: Leave) -> bool {
+            let id = Identity::new();
+            let mut leave = leave;
+
+            leave.node_id = NodeId(*id.pkey());
+            leave.sign(id.skey().clone());
+            leave.hash();
+
+            let mut forged = leave.clone();
+            forged.parent_hash = Hash::random();
+
+            leave.verify_sig() && leave.verify_hash() && !forged.verify_sig() && !forged.verify_hash()
+        }
     }
 }
@@ -110,6 +110,64 @@ impl Event {
         }
     }
 
+    /// Verifies the correctness of the hash of the event.
+    ///
+    /// Returns `false` if the hash or the signature field
+    /// is missing. `Dummy` events are always valid.
+    pub fn verify_hash(&mut self) -> bool {
+        match *self {
+            Event::Heartbeat(ref mut event) => {
+                event.hash.is_some()
+                    && event.signature.is_some()
+                    && event.root_hash.is_some()
+                    && event.verify_hash()
+            }
+            Event::Join(ref mut event) => {
+                event.hash.is_some() && event.signature.is_some() && event.verify_hash()
+            }
+            Event::Leave(ref mut event) => {
+                event.hash.is_some() && event.signature.is_some() && event.verify_hash()
+            }
+            Event::Dummy(_, _, _, _) => true,
+        }
+    }
+
+    /// Verifies the signature of the event against the
+    /// node id of its sender.
+    ///
+    /// Returns `false` if the signature field is missing.
+    /// `Dummy` events are always valid.
+    pub fn verify_sig(&mut self) -> bool {
+        match *self {
+            Event::Heartbeat(ref mut event) => event.root_hash.is_some() && event.verify_sig(),
+            Event::Join(ref mut event) => event.verify_sig(),
+            Event::Leave(ref mut event) => event.verify_sig(),
+            Event::Dummy(_, _, _, _) => true,
+        }
+    }
+
+    /// Serializes the event.
+    ///
+    /// The first byte of the result is the type of the event.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, &'static str> {
+        match *self {
+            Event::Heartbeat(ref event) => event.to_bytes(),
+            Event::Join(ref event) => event.to_bytes(),
+            Event::Leave(ref event) => event.to_bytes(),
+            Event::Dummy(_, _, _, _) => Err("Dummy events cannot be serialized"),
+        }
+    }
+
+    /// Deserializes an event by dispatching on its type byte.
+    pub fn from_bytes(bin: &[u8]) -> Result<Event, &'static str> {
+        match bin.first() {
+            Some(&Heartbeat::EVENT_TYPE) => Ok(Event::Heartbeat(Heartbeat::from_bytes(bin)?)),
+            Some(&Join::EVENT_TYPE) => Ok(Event::Join(Join::from_bytes(bin)?)),
+            Some(&Leave::EVENT_TYPE) => Ok(Event::Leave(Leave::from_bytes(bin)?)),
+            _ => Err("Bad event type"),
+        }
+    }
+
     pub fn parent_hash(&self) -> Option<Hash> {
         match *self {
             Event::Heartbeat(ref event) => Some(event.parent_hash.clone()),
@@ -119,3 +177,20 @@ impl Event {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    quickcheck! {
+        fn serialize_deserialize(join: Join, leave: Leave) -> bool {
+            let join_bytes = Event::Join(join.clone()).to_bytes().unwrap();
+            let leave_bytes = Event::Leave(leave.clone()).to_bytes().unwrap();
+
+            match (Event::from_bytes(&join_bytes), Event::from_bytes(&leave_bytes)) {
+                (Ok(Event::Join(j)), Ok(Event::Leave(l))) => j == join && l == leave,
+                _ => false
+            }
+        }
+    }
+}
@@ -0,0 +1,450 @@
+/*
+  Copyright 2018 The Purple Library Authors
+  This file is part of the Purple Library.
+
+  The Purple Library is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  The Purple Library is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with the Purple Library. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use account::{Address, Balance};
+use crypto::Hash;
+use events::Heartbeat;
+use hashbrown::HashMap;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use transactions::Tx;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum MempoolErr {
+    /// The transaction is already in the mempool.
+    AlreadyInMempool,
+
+    /// The transaction does not have a hash.
+    NoHash,
+
+    /// The transaction cannot be serialized.
+    BadTransaction,
+
+    /// The mempool is full and the transaction does not pay
+    /// a greater fee than any of the evictable transactions.
+    Full,
+}
+
+#[derive(Clone, Debug)]
+struct MempoolEntry {
+    /// The stored transaction
+    tx: Arc<Tx>,
+
+    /// The address of the transaction's creator
+    creator: Address,
+
+    /// The nonce that the creator account will have
+    /// after the transaction is applied.
+    nonce: u64,
+
+    /// The serialized size of the transaction in bytes
+    size: usize,
+}
+
+#[derive(Debug)]
+pub struct Mempool {
+    /// Mapping between transaction hashes and entries
+    tx_lookup: HashMap<Hash, MempoolEntry>,
+
+    /// The hashes of the pending transactions of each
+    /// account ordered by nonce.
+    address_mappings: HashMap<Address, BTreeMap<u64, Hash>>,
+
+    /// The current size of the stored transactions in bytes
+    size: usize,
+
+    /// The maximum size of the stored transactions in bytes
+    max_size: usize,
+}
+
+impl Mempool {
+    /// Creates a new mempool that will hold at
+    /// most `max_size` bytes of transactions.
+    pub fn new(max_size: usize) -> Mempool {
+        Mempool {
+            tx_lookup: HashMap::new(),
+            address_mappings: HashMap::new(),
+            size: 0,
+            max_size,
+        }
+    }
+
+    /// Returns the number of stored transactions.
+    pub fn len(&self) -> usize {
+        self.tx_lookup.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tx_lookup.is_empty()
+    }
+
+    /// Returns the size of the stored transactions in bytes.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Returns `true` if there is a transaction with the given hash.
+    pub fn contains(&self, hash: &Hash) -> bool {
+        self.tx_lookup.contains_key(hash)
+    }
+
+    /// Returns the transaction with the given hash.
+    pub fn get(&self, hash: &Hash) -> Option<Arc<Tx>> {
+        self.tx_lookup.get(hash).map(|entry| entry.tx.clone())
+    }
+
+    /// Returns the nonce that the next transaction of the
+    /// given account will have, given that the account
+    /// currently has the provided ledger nonce.
+    pub fn next_nonce(&self, address: &Address, account_nonce: u64) -> u64 {
+        match self.address_mappings.get(address) {
+            Some(mapping) => match mapping.keys().next_back() {
+                Some(nonce) => nonce + 1,
+                None => account_nonce + 1,
+            },
+            None => account_nonce + 1,
+        }
+    }
+
+    /// Attempts to append a validated transaction to the mempool.
+    ///
+    /// The transaction is ordered after all other pending
+    /// transactions of its creator. `account_nonce` is the
+    /// nonce of the creator as it is currently stored in
+    /// the ledger.
+    ///
+    /// If the mempool is full, the transactions with the
+    /// smallest fee are evicted in order to make room for
+    /// the new transaction. Only transactions that pay their
+    /// fee in the same currency as the new transaction are
+    /// considered and only the last transaction of each account
+    /// can be evicted so that the remaining transactions never
+    /// have nonce gaps.
+    pub fn append(&mut self, tx: Arc<Tx>, account_nonce: u64) -> Result<(), MempoolErr> {
+        let hash = match tx.hash() {
+            Some(hash) => hash,
+            None => return Err(MempoolErr::NoHash),
+        };
+
+        if self.tx_lookup.contains_key(&hash) {
+            return Err(MempoolErr::AlreadyInMempool);
+        }
+
+        let size = match tx.to_bytes() {
+            Ok(bin) => bin.len(),
+            Err(_) => return Err(MempoolErr::BadTransaction),
+        };
+
+        if size > self.max_size {
+            return Err(MempoolErr::Full);
+        }
+
+        let creator = tx.creator();
+        let fee = tx.fee();
+        let fee_hash = tx.fee_hash();
+
+        // Evict transactions until there is room for the new one
+        while self.size + size > self.max_size {
+            match self.lowest_fee_tail(&creator, &fee_hash) {
+                Some((tail_hash, tail_fee)) => {
+                    if tail_fee >= fee {
+                        return Err(MempoolErr::Full);
+                    }
+
+                    self.remove(&tail_hash);
+                }
+                None => return Err(MempoolErr::Full),
+            }
+        }
+
+        let nonce = self.next_nonce(&creator, account_nonce);
+        let entry = MempoolEntry {
+            tx,
+            creator: creator.clone(),
+            nonce,
+            size,
+        };
+
+        self.address_mappings
+            .entry(creator)
+            .or_insert_with(BTreeMap::new)
+            .insert(nonce, hash.clone());
+
+        self.tx_lookup.insert(hash, entry);
+        self.size += size;
+
+        Ok(())
+    }
+
+    /// Removes the transaction with the given hash
+    /// from the mempool and returns it.
+    pub fn remove(&mut self, hash: &Hash) -> Option<Arc<Tx>> {
+        let entry = self.tx_lookup.remove(hash)?;
+        let mut remove_mapping = false;
+
+        if let Some(mapping) = self.address_mappings.get_mut(&entry.creator) {
+            mapping.remove(&entry.nonce);
+            remove_mapping = mapping.is_empty();
+        }
+
+        if remove_mapping {
+            self.address_mappings.remove(&entry.creator);
+        }
+
+        self.size -= entry.size;
+
+        Some(entry.tx)
+    }
+
+    /// Removes all the transactions that are included
+    /// in the given applied `Heartbeat` event.
+    pub fn remove_included(&mut self, heartbeat: &Heartbeat) {
+        for tx in heartbeat.transactions.iter() {
+            if let Some(hash) = tx.hash() {
+                self.remove(&hash);
+            }
+        }
+    }
+
+    /// Returns at most `count` pending transactions.
+    ///
+    /// The transactions of each account are returned in
+    /// nonce order. Between accounts, the transaction with
+    /// the greatest fee is picked first. Fees paid in different
+    /// currencies are not comparable so in this case the
+    /// transaction paying in the currency with the lowest
+    /// hash is picked first.
+    pub fn pending(&self, count: usize) -> Vec<Arc<Tx>> {
+        let mut result = Vec::with_capacity(count);
+        let mut queues: Vec<Vec<&Hash>> = self
+            .address_mappings
+            .values()
+            .map(|mapping| mapping.values().rev().collect())
+            .collect();
+
+        while result.len() < count {
+            let mut best: Option<(usize, Hash, Balance)> = None;
+
+            for (i, queue) in queues.iter().enumerate() {
+                if let Some(hash) = queue.last() {
+                    let tx = &self.tx_lookup.get(*hash).unwrap().tx;
+                    let fee_hash = tx.fee_hash();
+                    let fee = tx.fee();
+
+                    match best {
+                        Some((_, ref best_fee_hash, _)) if *best_fee_hash < fee_hash => {}
+                        Some((_, ref best_fee_hash, ref best_fee))
+                            if *best_fee_hash == fee_hash && *best_fee >= fee => {}
+                        _ => best = Some((i, fee_hash, fee)),
+                    }
+                }
+            }
+
+            match best {
+                Some((i, _, _)) => {
+                    let hash = queues[i].pop().unwrap();
+                    result.push(self.tx_lookup.get(hash).unwrap().tx.clone());
+                }
+                None => break,
+            }
+        }
+
+        result
+    }
+
+    /// Returns the hash and the fee of the last transaction
+    /// of an account, other than the given one, that pays
+    /// the smallest fee in the currency with the given hash.
+    fn lowest_fee_tail(&self, exclude: &Address, fee_hash: &Hash) -> Option<(Hash, Balance)> {
+        let mut result: Option<(Hash, Balance)> = None;
+
+        for (address, mapping) in self.address_mappings.iter() {
+            if address == exclude {
+                continue;
+            }
+
+            if let Some(hash) = mapping.values().next_back() {
+                let tx = &self.tx_lookup.get(hash).unwrap().tx;
+
+                if tx.fee_hash() != *fee_hash {
+                    continue;
+                }
+
+                let fee = tx.fee();
+
+                match result {
+                    Some((_, ref lowest)) if *lowest <= fee => {}
+                    _ => result = Some((hash.clone(), fee)),
+                }
+            }
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use account::NormalAddress;
+    use causality::Stamp;
+    use crypto::{hash_slice, Identity};
+    use network::NodeId;
+    use transactions::CreateCurrency;
+
+    fn create_currency(id: &Identity, fee: &[u8]) -> Arc<Tx> {
+        create_currency_with_fee_hash(id, fee, hash_slice(b"purple"))
+    }
+
+    fn create_currency_with_fee_hash(id: &Identity, fee: &[u8], fee_hash: Hash) -> Arc<Tx> {
+        let creator = NormalAddress::from_pkey(*id.pkey());
+        let mut tx = CreateCurrency {
+            creator,
+            receiver: Address::Normal(creator),
+            asset_hash: Hash::random(),
+            coin_supply: 10000,
+            precision: 18,
+            fee_hash,
+            fee: Balance::from_bytes(fee).unwrap(),
+            hash: None,
+            signature: None,
+        };
+
+        tx.sign(id.skey().clone());
+        tx.hash();
+
+        Arc::new(Tx::CreateCurrency(tx))
+    }
+
+    fn tx_size(tx: &Arc<Tx>) -> usize {
+        tx.to_bytes().unwrap().len()
+    }
+
+    #[test]
+    fn it_orders_transactions_by_nonce() {
+        let id = Identity::new();
+        let creator = NormalAddress::from_pkey(*id.pkey());
+        let tx1 = create_currency(&id, b"1.0");
+        let tx2 = create_currency(&id, b"5.0");
+        let tx3 = create_currency(&id, b"3.0");
+        let mut mempool = Mempool::new(1024 * 1024);
+
+        mempool.append(tx1.clone(), 4).unwrap();
+        mempool.append(tx2.clone(), 4).unwrap();
+        mempool.append(tx3.clone(), 4).unwrap();
+
+        assert_eq!(mempool.len(), 3);
+        assert_eq!(mempool.next_nonce(&Address::Normal(creator), 4), 8);
+        assert_eq!(mempool.pending(3), vec![tx1, tx2, tx3]);
+    }
+
+    #[test]
+    fn it_rejects_duplicates() {
+        let id = Identity::new();
+        let tx = create_currency(&id, b"1.0");
+        let mut mempool = Mempool::new(1024 * 1024);
+
+        mempool.append(tx.clone(), 0).unwrap();
+
+        assert_eq!(mempool.append(tx, 0), Err(MempoolErr::AlreadyInMempool));
+    }
+
+    #[test]
+    fn it_evicts_by_fee() {
+        let i1 = Identity::new();
+        let i2 = Identity::new();
+        let i3 = Identity::new();
+        let low = create_currency(&i1, b"1.0");
+        let high = create_currency(&i2, b"10.0");
+        let higher = create_currency(&i3, b"20.0");
+        let lowest = create_currency(&i3, b"0.1");
+        let mut mempool = Mempool::new(tx_size(&low) + tx_size(&high));
+
+        mempool.append(low.clone(), 0).unwrap();
+        mempool.append(high.clone(), 0).unwrap();
+        mempool.append(higher.clone(), 0).unwrap();
+
+        assert!(!mempool.contains(&low.hash().unwrap()));
+        assert!(mempool.contains(&high.hash().unwrap()));
+        assert!(mempool.contains(&higher.hash().unwrap()));
+        assert!(mempool.size() <= tx_size(&low) + tx_size(&high));
+        assert_eq!(mempool.append(lowest, 0), Err(MempoolErr::Full));
+    }
+
+    #[test]
+    fn it_evicts_only_transactions_paying_in_the_same_currency() {
+        let i1 = Identity::new();
+        let i2 = Identity::new();
+        let i3 = Identity::new();
+        let i4 = Identity::new();
+        let other_currency = hash_slice(b"other");
+        let low = create_currency(&i1, b"1.0");
+        let other = create_currency_with_fee_hash(&i2, b"5.0", other_currency);
+        let higher_other = create_currency_with_fee_hash(&i3, b"9.0", other_currency);
+        let high = create_currency(&i4, b"3.0");
+        let mut mempool = Mempool::new(tx_size(&low) + tx_size(&other));
+
+        mempool.append(low.clone(), 0).unwrap();
+        mempool.append(other.clone(), 0).unwrap();
+
+        // Only `other` pays in the same currency so it is evicted
+        // even though `low` pays a smaller amount.
+        mempool.append(higher_other.clone(), 0).unwrap();
+
+        assert!(mempool.contains(&low.hash().unwrap()));
+        assert!(!mempool.contains(&other.hash().unwrap()));
+        assert!(mempool.contains(&higher_other.hash().unwrap()));
+
+        // `high` only has to outbid the transactions paying in its currency.
+        mempool.append(high.clone(), 0).unwrap();
+
+        assert!(!mempool.contains(&low.hash().unwrap()));
+        assert!(mempool.contains(&higher_other.hash().unwrap()));
+        assert!(mempool.contains(&high.hash().unwrap()));
+    }
+
+    #[test]
+    fn it_removes_included_transactions() {
+        let i1 = Identity::new();
+        let i2 = Identity::new();
+        let tx1 = create_currency(&i1, b"1.0");
+        let tx2 = create_currency(&i1, b"1.0");
+        let tx3 = create_currency(&i2, b"1.0");
+        let mut mempool = Mempool::new(1024 * 1024);
+
+        mempool.append(tx1.clone(), 0).unwrap();
+        mempool.append(tx2.clone(), 0).unwrap();
+        mempool.append(tx3.clone(), 0).unwrap();
+
+        let heartbeat = Heartbeat {
+            node_id: NodeId(*i1.pkey()),
+            stamp: Stamp::seed(),
+            root_hash: None,
+            hash: None,
+            parent_hash: Hash::random(),
+            signature: None,
+            transactions: vec![Box::new((*tx1).clone()), Box::new((*tx3).clone())],
+        };
+
+        mempool.remove_included(&heartbeat);
+
+        assert_eq!(mempool.len(), 1);
+        assert_eq!(mempool.size(), tx_size(&tx2));
+        assert_eq!(mempool.pending(10), vec![tx2]);
+    }
+}
@@ -0,0 +1,418 @@
+/*
+  Copyright 2018 The Purple Library Authors
+  This file is part of the Purple Library.
+
+  The Purple Library is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  The Purple Library is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with the Purple Library. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The maximum number of stored addresses
+pub const MAX_ADDRESSES: usize = 1000;
+
+/// Addresses that fail this many consecutive
+/// connection attempts are removed.
+pub const MAX_FAILURES: u32 = 10;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AddressEntry {
+    /// Unix timestamp in seconds of the last time we
+    /// have successfully connected to the address.
+    ///
+    /// Equal to 0 if we have never connected to it.
+    pub last_seen: u64,
+
+    /// The number of consecutive failed connection attempts
+    pub failures: u32,
+}
+
+/// Book of known peer addresses that is persisted
+/// in the bootstrap cache.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct AddressBook {
+    entries: HashMap<SocketAddr, AddressEntry>,
+}
+
+impl AddressBook {
+    pub fn new() -> AddressBook {
+        AddressBook {
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Returns the number of stored addresses.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the entry of the given address.
+    pub fn get(&self, addr: &SocketAddr) -> Option<&AddressEntry> {
+        self.entries.get(addr)
+    }
+
+    /// Adds an address that we have not yet connected to.
+    ///
+    /// Returns `false` if the address is already stored
+    /// or if it cannot be dialed.
+    pub fn insert(&mut self, addr: SocketAddr) -> bool {
+        if self.entries.contains_key(&addr) || !is_dialable(&addr) {
+            return false;
+        }
+
+        if self.entries.len() >= MAX_ADDRESSES {
+            self.evict_worst();
+        }
+
+        self.entries.insert(
+            addr,
+            AddressEntry {
+                last_seen: 0,
+                failures: 0,
+            },
+        );
+
+        true
+    }
+
+    /// Records a successful connection to the given address.
+    pub fn mark_seen(&mut self, addr: SocketAddr) {
+        if !self.entries.contains_key(&addr) && self.entries.len() >= MAX_ADDRESSES {
+            self.evict_worst();
+        }
+
+        let entry = self.entries.entry(addr).or_insert(AddressEntry {
+            last_seen: 0,
+            failures: 0,
+        });
+
+        entry.last_seen = unix_timestamp();
+        entry.failures = 0;
+    }
+
+    /// Records a failed connection attempt to the given address.
+    ///
+    /// The address is removed after `MAX_FAILURES`
+    /// consecutive failures.
+    pub fn mark_failed(&mut self, addr: &SocketAddr) {
+        let remove = match self.entries.get_mut(addr) {
+            Some(entry) => {
+                entry.failures += 1;
+                entry.failures >= MAX_FAILURES
+            }
+            None => false,
+        };
+
+        if remove {
+            self.entries.remove(addr);
+        }
+    }
+
+    /// Returns at most `count` addresses, other than the excluded
+    /// ones, ordered by the least amount of failures and then
+    /// by the most recently seen.
+    pub fn best(&self, count: usize, exclude: &[SocketAddr]) -> Vec<SocketAddr> {
+        let mut entries: Vec<(&SocketAddr, &AddressEntry)> = self
+            .entries
+            .iter()
+            .filter(|(addr, _)| !exclude.contains(*addr))
+            .collect();
+
+        entries.sort_by(|(a_addr, a), (b_addr, b)| {
+            a.failures
+                .cmp(&b.failures)
+                .then(b.last_seen.cmp(&a.last_seen))
+                .then(a_addr.cmp(b_addr))
+        });
+
+        entries.iter().take(count).map(|(addr, _)| **addr).collect()
+    }
+
+    /// Serializes the address book in the format
+    /// stored under the `bootstrap_cache` key.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let entries: Vec<Vec<u8>> = self
+            .best(self.entries.len(), &[])
+            .iter()
+            .map(|addr| {
+                let entry = &self.entries[addr];
+                let mut buf: Vec<u8> = Vec::with_capacity(31);
+
+                // Address entry structure:
+                // 1) Last seen        - 64bits
+                // 2) Failures         - 32bits
+                // 3) Address          - Binary of address length
+                buf.write_u64::<BigEndian>(entry.last_seen).unwrap();
+                buf.write_u32::<BigEndian>(entry.failures).unwrap();
+                write_addr(&mut buf, addr);
+
+                buf
+            })
+            .collect();
+
+        rlp::encode_list::<Vec<u8>, _>(&entries)
+    }
+
+    pub fn from_bytes(bin: &[u8]) -> Result<AddressBook, &'static str> {
+        let entries: Vec<Vec<u8>> = match rlp::Rlp::new(bin).as_list() {
+            Ok(entries) => entries,
+            Err(_) => return Err("Bad address book"),
+        };
+
+        // Malformed entries are skipped when decoding so we make
+        // sure that the whole input has been consumed.
+        if rlp::encode_list::<Vec<u8>, _>(&entries)[..] != bin[..] {
+            return Err("Bad address book");
+        }
+
+        let mut address_book = AddressBook::new();
+
+        for entry in entries {
+            let mut rdr = Cursor::new(entry);
+
+            let last_seen = if let Ok(result) = rdr.read_u64::<BigEndian>() {
+                result
+            } else {
+                return Err("Bad last seen timestamp");
+            };
+
+            let failures = if let Ok(result) = rdr.read_u32::<BigEndian>() {
+                result
+            } else {
+                return Err("Bad failures count");
+            };
+
+            // Consume cursor
+            let mut buf: Vec<u8> = rdr.into_inner();
+            let _: Vec<u8> = buf.drain(..12).collect();
+
+            let addr = read_addr(&mut buf)?;
+
+            if !buf.is_empty() {
+                return Err("Incorrect address entry structure");
+            }
+
+            address_book.entries.insert(
+                addr,
+                AddressEntry {
+                    last_seen: last_seen,
+                    failures: failures,
+                },
+            );
+        }
+
+        Ok(address_book)
+    }
+
+    /// Removes the entry with the most failures that
+    /// has been seen the least recently.
+    fn evict_worst(&mut self) {
+        let worst = self
+            .entries
+            .iter()
+            .max_by(|(_, a), (_, b)| {
+                a.failures
+                    .cmp(&b.failures)
+                    .then(b.last_seen.cmp(&a.last_seen))
+            })
+            .map(|(addr, _)| *addr);
+
+        if let Some(addr) = worst {
+            self.entries.remove(&addr);
+        }
+    }
+}
+
+/// Writes a socket address to the given buffer.
+///
+/// Address structure:
+/// 1) IP version       - 8bits
+/// 2) IP address       - 4 or 16 byte binary
+/// 3) Port             - 16bits
+pub(crate) fn write_addr(buf: &mut Vec<u8>, addr: &SocketAddr) {
+    match addr.ip() {
+        IpAddr::V4(ip) => {
+            buf.write_u8(4).unwrap();
+            buf.extend_from_slice(&ip.octets());
+        }
+        IpAddr::V6(ip) => {
+            buf.write_u8(6).unwrap();
+            buf.extend_from_slice(&ip.octets());
+        }
+    }
+
+    buf.write_u16::<BigEndian>(addr.port()).unwrap();
+}
+
+/// Reads a socket address written with `write_addr`
+/// from the start of the given buffer.
+pub(crate) fn read_addr(buf: &mut Vec<u8>) -> Result<SocketAddr, &'static str> {
+    let ip_len = match buf.first().cloned() {
+        Some(4) => 4,
+        Some(6) => 16,
+        _ => return Err("Bad ip version"),
+    };
+
+    if buf.len() < 1 + ip_len + 2 {
+        return Err("Incorrect address structure");
+    }
+
+    let _: Vec<u8> = buf.drain(..1).collect();
+    let ip_vec: Vec<u8> = buf.drain(..ip_len).collect();
+    let port_vec: Vec<u8> = buf.drain(..2).collect();
+
+    let ip = if ip_len == 4 {
+        let mut b = [0; 4];
+        b.copy_from_slice(&ip_vec);
+
+        IpAddr::V4(Ipv4Addr::from(b))
+    } else {
+        let mut b = [0; 16];
+        b.copy_from_slice(&ip_vec);
+
+        IpAddr::V6(Ipv6Addr::from(b))
+    };
+
+    let port = Cursor::new(port_vec).read_u16::<BigEndian>().unwrap();
+
+    Ok(SocketAddr::new(ip, port))
+}
+
+/// Returns `false` for addresses that cannot be connected to.
+fn is_dialable(addr: &SocketAddr) -> bool {
+    addr.port() != 0 && !addr.ip().is_unspecified() && !addr.ip().is_multicast()
+}
+
+pub(crate) fn unix_timestamp() -> u64 {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs(),
+        Err(_) => 0,
+    }
+}
+
+#[cfg(test)]
+pub(crate) fn arbitrary_addr<G: quickcheck::Gen>(g: &mut G) -> SocketAddr {
+    use quickcheck::Arbitrary;
+
+    let ip = if bool::arbitrary(g) {
+        IpAddr::V4(Ipv4Addr::from(u32::arbitrary(g)))
+    } else {
+        let mut b = [0; 16];
+
+        for byte in b.iter_mut() {
+            *byte = u8::arbitrary(g);
+        }
+
+        IpAddr::V6(Ipv6Addr::from(b))
+    };
+
+    SocketAddr::new(ip, u16::arbitrary(g))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck::{Arbitrary, Gen};
+
+    #[derive(Debug, Clone)]
+    struct Addresses(Vec<SocketAddr>);
+
+    impl Arbitrary for Addresses {
+        fn arbitrary<G: Gen>(g: &mut G) -> Addresses {
+            let len = usize::arbitrary(g) % 50;
+            Addresses((0..len).map(|_| arbitrary_addr(g)).collect())
+        }
+    }
+
+    quickcheck! {
+        fn serialize_deserialize(addresses: Addresses) -> bool {
+            let mut address_book = AddressBook::new();
+
+            for (i, addr) in addresses.0.iter().enumerate() {
+                if i % 2 == 0 {
+                    address_book.mark_seen(*addr);
+                } else {
+                    address_book.insert(*addr);
+                    address_book.mark_failed(addr);
+                }
+            }
+
+            address_book == AddressBook::from_bytes(&address_book.to_bytes()).unwrap()
+        }
+    }
+
+    #[test]
+    fn it_rejects_malformed_bytes() {
+        let addr: SocketAddr = "10.0.0.1:44034".parse().unwrap();
+        let mut address_book = AddressBook::new();
+
+        address_book.mark_seen(addr);
+
+        let bin = address_book.to_bytes();
+
+        assert!(AddressBook::from_bytes(&bin[..bin.len() - 1]).is_err());
+        assert!(AddressBook::from_bytes(&[0xff, 0x01, 0x02]).is_err());
+        assert!(AddressBook::from_bytes(&[0x83, 0x01, 0x02, 0x03]).is_err());
+    }
+
+    #[test]
+    fn it_orders_by_failures_and_last_seen() {
+        let a1: SocketAddr = "10.0.0.1:44034".parse().unwrap();
+        let a2: SocketAddr = "10.0.0.2:44034".parse().unwrap();
+        let a3: SocketAddr = "[2001:db8::1]:44034".parse().unwrap();
+        let mut address_book = AddressBook::new();
+
+        address_book.insert(a1);
+        address_book.insert(a2);
+        address_book.mark_seen(a3);
+        address_book.mark_failed(&a1);
+
+        assert_eq!(address_book.best(3, &[]), vec![a3, a2, a1]);
+        assert_eq!(address_book.best(3, &[a3]), vec![a2, a1]);
+        assert_eq!(address_book.best(1, &[]), vec![a3]);
+    }
+
+    #[test]
+    fn it_removes_failing_addresses() {
+        let addr: SocketAddr = "10.0.0.1:44034".parse().unwrap();
+        let mut address_book = AddressBook::new();
+
+        address_book.insert(addr);
+
+        for _ in 0..(MAX_FAILURES - 1) {
+            address_book.mark_failed(&addr);
+        }
+
+        assert_eq!(address_book.get(&addr).unwrap().failures, MAX_FAILURES - 1);
+        address_book.mark_failed(&addr);
+        assert!(address_book.get(&addr).is_none());
+    }
+
+    #[test]
+    fn it_rejects_undialable_addresses() {
+        let mut address_book = AddressBook::new();
+
+        assert!(!address_book.insert("0.0.0.0:44034".parse().unwrap()));
+        assert!(!address_book.insert("10.0.0.1:0".parse().unwrap()));
+        assert!(address_book.insert("10.0.0.1:44034".parse().unwrap()));
+        assert!(!address_book.insert("10.0.0.1:44034".parse().unwrap()));
+    }
+}
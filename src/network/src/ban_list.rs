@@ -0,0 +1,312 @@
+/*
+  Copyright 2018 The Purple Library Authors
+  This file is part of the Purple Library.
+
+  The Purple Library is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  The Purple Library is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with the Purple Library. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use NodeId;
+
+/// Temporarily banned node ids and ip addresses
+/// that is persisted in the node storage.
+///
+/// Each ban is stored along with the unix timestamp
+/// in seconds at which it expires.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct BanList {
+    node_ids: HashMap<NodeId, u64>,
+    ips: HashMap<IpAddr, u64>,
+}
+
+impl BanList {
+    const NODE_ID_ENTRY: u8 = 1;
+    const IP_ENTRY: u8 = 2;
+
+    pub fn new() -> BanList {
+        BanList {
+            node_ids: HashMap::new(),
+            ips: HashMap::new(),
+        }
+    }
+
+    /// Returns the number of bans.
+    pub fn len(&self) -> usize {
+        self.node_ids.len() + self.ips.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.node_ids.is_empty() && self.ips.is_empty()
+    }
+
+    /// Bans the given node id until the given timestamp.
+    ///
+    /// An existing ban is only ever extended.
+    pub fn ban_node_id(&mut self, node_id: NodeId, until: u64) {
+        let entry = self.node_ids.entry(node_id).or_insert(until);
+
+        if *entry < until {
+            *entry = until;
+        }
+    }
+
+    /// Bans the given ip address until the given timestamp.
+    ///
+    /// An existing ban is only ever extended.
+    pub fn ban_ip(&mut self, ip: IpAddr, until: u64) {
+        let entry = self.ips.entry(ip).or_insert(until);
+
+        if *entry < until {
+            *entry = until;
+        }
+    }
+
+    /// Lifts the ban of the given node id. Returns
+    /// `false` if the node id was not banned.
+    pub fn unban_node_id(&mut self, node_id: &NodeId) -> bool {
+        self.node_ids.remove(node_id).is_some()
+    }
+
+    /// Lifts the ban of the given ip address. Returns
+    /// `false` if the ip address was not banned.
+    pub fn unban_ip(&mut self, ip: &IpAddr) -> bool {
+        self.ips.remove(ip).is_some()
+    }
+
+    /// Returns `true` if the given node id is banned at the given timestamp.
+    pub fn is_node_id_banned(&self, node_id: &NodeId, now: u64) -> bool {
+        match self.node_ids.get(node_id) {
+            Some(until) => now < *until,
+            None => false,
+        }
+    }
+
+    /// Returns `true` if the given ip address is banned at the given timestamp.
+    pub fn is_ip_banned(&self, ip: &IpAddr, now: u64) -> bool {
+        match self.ips.get(ip) {
+            Some(until) => now < *until,
+            None => false,
+        }
+    }
+
+    /// Removes the bans that have expired at the given timestamp.
+    pub fn remove_expired(&mut self, now: u64) {
+        self.node_ids.retain(|_, until| now < *until);
+        self.ips.retain(|_, until| now < *until);
+    }
+
+    /// Returns an iterator over the banned node ids
+    /// and the timestamps at which the bans expire.
+    pub fn node_ids(&self) -> impl Iterator<Item = (&NodeId, &u64)> {
+        self.node_ids.iter()
+    }
+
+    /// Returns an iterator over the banned ip addresses
+    /// and the timestamps at which the bans expire.
+    pub fn ips(&self) -> impl Iterator<Item = (&IpAddr, &u64)> {
+        self.ips.iter()
+    }
+
+    /// Serializes the ban list in the format
+    /// stored under the `ban_list` key.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut entries: Vec<Vec<u8>> = Vec::with_capacity(self.len());
+
+        // Ban entry structure:
+        // 1) Entry type       - 8bits
+        // 2) Expiry           - 64bits
+        // 3) Node id or ip    - 32 bytes or binary of ip length
+        for (node_id, until) in self.node_ids.iter() {
+            let mut buf: Vec<u8> = Vec::with_capacity(41);
+
+            buf.write_u8(Self::NODE_ID_ENTRY).unwrap();
+            buf.write_u64::<BigEndian>(*until).unwrap();
+            buf.extend_from_slice(&(node_id.0).0);
+            entries.push(buf);
+        }
+
+        for (ip, until) in self.ips.iter() {
+            let mut buf: Vec<u8> = Vec::with_capacity(26);
+
+            buf.write_u8(Self::IP_ENTRY).unwrap();
+            buf.write_u64::<BigEndian>(*until).unwrap();
+            write_ip(&mut buf, ip);
+            entries.push(buf);
+        }
+
+        rlp::encode_list::<Vec<u8>, _>(&entries)
+    }
+
+    pub fn from_bytes(bin: &[u8]) -> Result<BanList, &'static str> {
+        let entries: Vec<Vec<u8>> = match rlp::Rlp::new(bin).as_list() {
+            Ok(entries) => entries,
+            Err(_) => return Err("Bad ban list"),
+        };
+
+        // Decoding stops at the first malformed entry
+        // so trailing garbage has to be checked for.
+        if rlp::encode_list::<Vec<u8>, _>(&entries)[..] != bin[..] {
+            return Err("Bad ban list");
+        }
+
+        let mut ban_list = BanList::new();
+
+        for entry in entries {
+            let mut rdr = Cursor::new(entry);
+
+            let entry_type = if let Ok(result) = rdr.read_u8() {
+                result
+            } else {
+                return Err("Bad entry type");
+            };
+
+            let until = if let Ok(result) = rdr.read_u64::<BigEndian>() {
+                result
+            } else {
+                return Err("Bad expiry timestamp");
+            };
+
+            // Consume cursor
+            let mut buf: Vec<u8> = rdr.into_inner();
+            let _: Vec<u8> = buf.drain(..9).collect();
+
+            match entry_type {
+                Self::NODE_ID_ENTRY => {
+                    if buf.len() != 32 {
+                        return Err("Incorrect ban entry structure");
+                    }
+
+                    let mut b = [0; 32];
+                    b.copy_from_slice(&buf);
+
+                    ban_list.node_ids.insert(NodeId::new(b), until);
+                }
+                Self::IP_ENTRY => {
+                    let ip = read_ip(&buf)?;
+                    ban_list.ips.insert(ip, until);
+                }
+                _ => return Err("Bad entry type"),
+            }
+        }
+
+        Ok(ban_list)
+    }
+}
+
+/// Writes an ip address to the given buffer.
+///
+/// Ip structure:
+/// 1) IP version       - 8bits
+/// 2) IP address       - 4 or 16 byte binary
+fn write_ip(buf: &mut Vec<u8>, ip: &IpAddr) {
+    match ip {
+        IpAddr::V4(ip) => {
+            buf.write_u8(4).unwrap();
+            buf.extend_from_slice(&ip.octets());
+        }
+        IpAddr::V6(ip) => {
+            buf.write_u8(6).unwrap();
+            buf.extend_from_slice(&ip.octets());
+        }
+    }
+}
+
+fn read_ip(buf: &[u8]) -> Result<IpAddr, &'static str> {
+    match (buf.first().cloned(), buf.len()) {
+        (Some(4), 5) => {
+            let mut b = [0; 4];
+            b.copy_from_slice(&buf[1..]);
+
+            Ok(IpAddr::V4(Ipv4Addr::from(b)))
+        }
+        (Some(6), 17) => {
+            let mut b = [0; 16];
+            b.copy_from_slice(&buf[1..]);
+
+            Ok(IpAddr::V6(Ipv6Addr::from(b)))
+        }
+        _ => Err("Bad ip address"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use address_book::arbitrary_addr;
+    use quickcheck::{Arbitrary, Gen};
+
+    impl Arbitrary for BanList {
+        fn arbitrary<G: Gen>(g: &mut G) -> BanList {
+            let mut ban_list = BanList::new();
+
+            for _ in 0..(usize::arbitrary(g) % 20) {
+                ban_list.ban_node_id(NodeId::arbitrary(g), u64::arbitrary(g));
+                ban_list.ban_ip(arbitrary_addr(g).ip(), u64::arbitrary(g));
+            }
+
+            ban_list
+        }
+    }
+
+    quickcheck! {
+        fn serialize_deserialize(ban_list: BanList) -> bool {
+            ban_list == BanList::from_bytes(&ban_list.to_bytes()).unwrap()
+        }
+    }
+
+    #[test]
+    fn it_expires_bans() {
+        let mut ban_list = BanList::new();
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let node_id = NodeId::new([1; 32]);
+
+        ban_list.ban_ip(ip, 100);
+        ban_list.ban_node_id(node_id.clone(), 100);
+        ban_list.ban_node_id(node_id.clone(), 50);
+
+        assert!(ban_list.is_ip_banned(&ip, 99));
+        assert!(ban_list.is_node_id_banned(&node_id, 99));
+        assert!(!ban_list.is_ip_banned(&ip, 100));
+
+        ban_list.remove_expired(100);
+        assert!(ban_list.is_empty());
+    }
+
+    #[test]
+    fn it_rejects_malformed_bytes() {
+        let mut ban_list = BanList::new();
+
+        ban_list.ban_ip("10.0.0.1".parse().unwrap(), 100);
+
+        let bin = ban_list.to_bytes();
+
+        assert!(BanList::from_bytes(&bin[..bin.len() - 1]).is_err());
+        assert!(BanList::from_bytes(&[0xff, 0x01, 0x02]).is_err());
+        assert!(BanList::from_bytes(&[0x83, 0x01, 0x02, 0x03]).is_err());
+    }
+
+    #[test]
+    fn it_lifts_bans() {
+        let mut ban_list = BanList::new();
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+
+        ban_list.ban_ip(ip, 100);
+        assert!(ban_list.unban_ip(&ip));
+        assert!(!ban_list.unban_ip(&ip));
+        assert!(!ban_list.is_ip_banned(&ip, 0));
+    }
+}
@@ -16,9 +16,10 @@
   along with the Purple Library. If not, see <http://www.gnu.org/licenses/>.
 */
 
-use connection::connect_to_peer;
-use futures::stream;
-use futures::Future;
+use address_book::{unix_timestamp, AddressBook};
+use ban_list::BanList;
+use connection_manager::{start_connection_manager, ConnectionManager};
+use elastic_array::ElasticArray128;
 use futures::Stream;
 use hashdb::HashDB;
 use network::Network;
@@ -27,96 +28,101 @@ use persistence::PersistentDb;
 use std::net::SocketAddr;
 use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::executor::Spawn;
+use tokio::timer::Interval;
 
 const BOOTNODES: &'static [&'static str] = &["139.162.133.241:44034"];
 
+/// Interval in milliseconds at which the
+/// bootstrap cache is written to disk.
+const BOOTSTRAP_CACHE_INTERVAL: u64 = 60000;
+
+/// Loads the bootstrap cache and starts the connection
+/// manager which keeps `outbound_peers` outbound peers.
+///
+/// The bootstrap nodes are only dialed when there are
+/// not enough reachable addresses in the bootstrap cache.
 pub fn bootstrap(
     network: Arc<Mutex<Network>>,
     accept_connections: Arc<AtomicBool>,
     db: PersistentDb,
     max_peers: usize,
+    outbound_peers: usize,
 ) -> Spawn {
     info!("Starting bootstrap");
 
     let bootstrap_cache_key = crypto::hash_slice(b"bootstrap_cache");
 
-    // Try to first connect to the nodes in the bootstrap cache.
+    // Load the addresses stored in the bootstrap cache
     if let Some(cache) = db.get(&bootstrap_cache_key) {
-        let cache: Vec<String> = rlp::decode_list(&cache);
-        let cache: Vec<SocketAddr> = cache.iter().map(|addr| addr.parse().unwrap()).collect();
-
-        let peers_to_connect = if cache.len() > max_peers {
-            cache[..max_peers].to_vec()
-        } else {
-            cache
-        };
-
-        let network = network.clone();
-        let network_clone = network.clone();
-        let accept_connections = accept_connections.clone();
-        let accept_connections_clone = accept_connections.clone();
-
-        let fut = stream::iter_ok(peers_to_connect)
-            .for_each(move |addr| {
-                connect_to_peer(
-                    network.clone(),
-                    accept_connections.clone(),
-                    max_peers,
-                    &addr,
-                )
-            })
-            .and_then(move |_| {
-                // Connect to bootstrap nodes if we haven't
-                // yet reached the maximum amount of peers.
-                if network_clone.lock().peer_count() < max_peers {
-                    let bootnodes: Vec<SocketAddr> =
-                        BOOTNODES.iter().map(|addr| addr.parse().unwrap()).collect();
-
-                    let accept_connections = accept_connections_clone.clone();
-                    let network = network_clone.clone();
-
-                    let fut = stream::iter_ok(bootnodes).for_each(move |addr| {
-                        connect_to_peer(
-                            network.clone(),
-                            accept_connections.clone(),
-                            max_peers,
-                            &addr,
-                        )
-                    });
-
-                    tokio::spawn(fut);
-                    info!("Finished bootstrap");
-                    Ok(())
-                } else {
-                    info!("Finished bootstrap");
-                    Ok(())
-                }
-            });
-
-        tokio::spawn(fut)
-    } else {
-        let bootnodes: Vec<SocketAddr> =
-            BOOTNODES.iter().map(|addr| addr.parse().unwrap()).collect();
-
-        let mut peers_to_connect: Vec<SocketAddr> = Vec::with_capacity(bootnodes.len());
-
-        for addr in bootnodes.iter().take(max_peers) {
-            peers_to_connect.push(*addr);
+        match AddressBook::from_bytes(&cache) {
+            Ok(address_book) => *network.lock().address_book_mut() = address_book,
+            Err(err) => warn!("Invalid bootstrap cache: {}", err),
         }
+    }
+
+    info!(
+        "Loaded {} addresses from the bootstrap cache",
+        network.lock().address_book().len()
+    );
+
+    let bootnodes: Vec<SocketAddr> = BOOTNODES.iter().map(|addr| addr.parse().unwrap()).collect();
+    let manager = ConnectionManager::new(outbound_peers, bootnodes);
 
-        let accept_connections = accept_connections.clone();
-        let network = network.clone();
+    start_connection_manager(network, accept_connections, max_peers, manager)
+}
 
-        let fut = stream::iter_ok(peers_to_connect).for_each(move |addr| {
-            connect_to_peer(
-                network.clone(),
-                accept_connections.clone(),
-                max_peers,
-                &addr,
-            )
+/// Periodically writes the address book of the network
+/// to the `bootstrap_cache` key and its bans to the
+/// `ban_list` key.
+pub fn start_bootstrap_cache_writer(network: Arc<Mutex<Network>>, db: PersistentDb) -> Spawn {
+    let interval = Duration::from_millis(BOOTSTRAP_CACHE_INTERVAL);
+    let mut db = db;
+
+    let fut = Interval::new(Instant::now() + interval, interval)
+        .map_err(|e| warn!("bootstrap cache interval errored = {:?}", e))
+        .for_each(move |_| {
+            write_bootstrap_cache(&network, &mut db);
+            write_ban_list(&network, &mut db);
+            Ok(())
         });
 
-        tokio::spawn(fut)
+    tokio::spawn(fut)
+}
+
+/// Writes the address book of the network to the `bootstrap_cache` key.
+pub fn write_bootstrap_cache(network: &Arc<Mutex<Network>>, db: &mut PersistentDb) {
+    let bootstrap_cache_key = crypto::hash_slice(b"bootstrap_cache");
+    let cache = network.lock().address_book().to_bytes();
+
+    debug!("Writing bootstrap cache");
+    db.emplace(
+        bootstrap_cache_key,
+        ElasticArray128::<u8>::from_slice(&cache),
+    );
+}
+
+/// Loads the bans stored under the `ban_list` key into the network.
+pub fn load_ban_list(network: &Arc<Mutex<Network>>, db: &PersistentDb) {
+    let ban_list_key = crypto::hash_slice(b"ban_list");
+
+    if let Some(bin) = db.get(&ban_list_key) {
+        match BanList::from_bytes(&bin) {
+            Ok(mut ban_list) => {
+                ban_list.remove_expired(unix_timestamp());
+                network.lock().set_ban_list(ban_list);
+            }
+            Err(err) => warn!("Invalid ban list: {}", err),
+        }
     }
 }
+
+/// Writes the bans of the network to the `ban_list` key.
+pub fn write_ban_list(network: &Arc<Mutex<Network>>, db: &mut PersistentDb) {
+    let ban_list_key = crypto::hash_slice(b"ban_list");
+    let ban_list = network.lock().ban_list().to_bytes();
+
+    debug!("Writing ban list");
+    db.emplace(ban_list_key, ElasticArray128::<u8>::from_slice(&ban_list));
+}
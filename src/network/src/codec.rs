@@ -0,0 +1,265 @@
+/*
+  Copyright 2018 The Purple Library Authors
+  This file is part of the Purple Library.
+
+  The Purple Library is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  The Purple Library is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with the Purple Library. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use byteorder::{BigEndian, ByteOrder};
+use bytes::{BufMut, BytesMut};
+use tokio::codec::{Decoder, Encoder};
+use tokio::io;
+
+/// The maximum size in bytes of a packet
+pub const MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+
+/// The maximum size in bytes of the first packet of a
+/// connection, which is the connect packet of the handshake
+pub const MAX_HANDSHAKE_FRAME_SIZE: usize = 1024;
+
+/// The size in bytes of the length prefix of a frame
+const LENGTH_PREFIX_SIZE: usize = 4;
+
+/// Codec for length-prefixed packet frames.
+///
+/// Frame structure:
+/// 1) Length           - 32bits
+/// 2) Packet type      - 8bits
+/// 3) Packet payload   - Binary of packet length - 1
+///
+/// The decoded item is the raw packet including its type byte
+/// so that it can be passed to the `from_bytes` function of the
+/// corresponding packet.
+///
+/// Peers must start with the connect packet of the handshake
+/// so the first decoded frame is limited to the size of the
+/// handshake frame. The buffer is only grown as the bytes
+/// of a frame are received.
+#[derive(Debug, Clone)]
+pub struct PacketCodec {
+    max_frame_size: usize,
+
+    /// Whether the first frame has been decoded
+    handshake_decoded: bool,
+}
+
+impl PacketCodec {
+    pub fn new(max_frame_size: usize) -> PacketCodec {
+        PacketCodec {
+            max_frame_size: max_frame_size,
+            handshake_decoded: false,
+        }
+    }
+
+    /// Returns the maximum size of the next decoded frame.
+    fn frame_limit(&self) -> usize {
+        if self.handshake_decoded {
+            self.max_frame_size
+        } else {
+            self.max_frame_size.min(MAX_HANDSHAKE_FRAME_SIZE)
+        }
+    }
+}
+
+impl Default for PacketCodec {
+    fn default() -> PacketCodec {
+        PacketCodec::new(MAX_FRAME_SIZE)
+    }
+}
+
+impl Decoder for PacketCodec {
+    type Item = Vec<u8>;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Vec<u8>>, io::Error> {
+        if src.len() < LENGTH_PREFIX_SIZE {
+            return Ok(None);
+        }
+
+        let len = BigEndian::read_u32(&src[..LENGTH_PREFIX_SIZE]) as usize;
+
+        // A frame must at least contain the packet type
+        if len == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Empty frame"));
+        }
+
+        if len > self.frame_limit() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Frame size exceeds the maximum frame size",
+            ));
+        }
+
+        // The declared length is not reserved upfront so that
+        // idle connections do not hold large buffers.
+        if src.len() < LENGTH_PREFIX_SIZE + len {
+            return Ok(None);
+        }
+
+        src.advance(LENGTH_PREFIX_SIZE);
+        self.handshake_decoded = true;
+
+        Ok(Some(src.split_to(len).to_vec()))
+    }
+}
+
+impl Encoder for PacketCodec {
+    type Item = Vec<u8>;
+    type Error = io::Error;
+
+    fn encode(&mut self, packet: Vec<u8>, dst: &mut BytesMut) -> Result<(), io::Error> {
+        if packet.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Empty packet"));
+        }
+
+        if packet.len() > self.max_frame_size {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Packet size exceeds the maximum frame size",
+            ));
+        }
+
+        dst.reserve(LENGTH_PREFIX_SIZE + packet.len());
+        dst.put_u32_be(packet.len() as u32);
+        dst.put_slice(&packet);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    quickcheck! {
+        fn encode_decode(packets: Vec<Vec<u8>>) -> bool {
+            let packets: Vec<Vec<u8>> = packets
+                .into_iter()
+                .filter(|p| !p.is_empty())
+                .collect();
+
+            let mut codec = PacketCodec::default();
+            let mut buf = BytesMut::new();
+
+            for packet in packets.iter() {
+                codec.encode(packet.clone(), &mut buf).unwrap();
+            }
+
+            let mut decoded = Vec::new();
+
+            while let Some(packet) = codec.decode(&mut buf).unwrap() {
+                decoded.push(packet);
+            }
+
+            decoded == packets && buf.is_empty()
+        }
+
+        fn decode_partial(packet: Vec<u8>) -> bool {
+            if packet.is_empty() {
+                return true;
+            }
+
+            let mut codec = PacketCodec::default();
+            let mut encoded = BytesMut::new();
+            codec.encode(packet.clone(), &mut encoded).unwrap();
+
+            let mut buf = BytesMut::new();
+            let mut result = None;
+
+            // Feed the frame one byte at a time
+            for byte in encoded.iter() {
+                assert!(result.is_none());
+                buf.reserve(1);
+                buf.put_u8(*byte);
+                result = codec.decode(&mut buf).unwrap();
+            }
+
+            result == Some(packet)
+        }
+    }
+
+    #[test]
+    fn it_decodes_newline_bytes() {
+        let packet = vec![1, b'\n', 2, b'\n', b'\n'];
+        let mut codec = PacketCodec::default();
+        let mut buf = BytesMut::new();
+
+        codec.encode(packet.clone(), &mut buf).unwrap();
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(packet));
+    }
+
+    #[test]
+    fn it_rejects_oversized_frames() {
+        let mut codec = PacketCodec::new(8);
+        let mut buf = BytesMut::with_capacity(64);
+
+        assert!(codec.encode(vec![1; 9], &mut buf).is_err());
+
+        buf.put_u32_be(9);
+        buf.put_slice(&[1; 9]);
+
+        assert!(codec.decode(&mut buf).is_err());
+    }
+
+    #[test]
+    fn it_rejects_empty_frames() {
+        let mut codec = PacketCodec::default();
+        let mut buf = BytesMut::with_capacity(64);
+
+        assert!(codec.encode(vec![], &mut buf).is_err());
+
+        buf.put_u32_be(0);
+
+        assert!(codec.decode(&mut buf).is_err());
+    }
+
+    #[test]
+    fn it_limits_the_size_of_the_first_frame() {
+        let mut codec = PacketCodec::default();
+        let mut buf = BytesMut::with_capacity(64);
+
+        buf.put_u32_be(MAX_HANDSHAKE_FRAME_SIZE as u32 + 1);
+        assert!(codec.decode(&mut buf).is_err());
+
+        let mut codec = PacketCodec::default();
+        let mut buf = BytesMut::new();
+        let packet = vec![1; MAX_HANDSHAKE_FRAME_SIZE + 1];
+
+        codec
+            .encode(vec![1; MAX_HANDSHAKE_FRAME_SIZE], &mut buf)
+            .unwrap();
+        codec.encode(packet.clone(), &mut buf).unwrap();
+
+        assert_eq!(
+            codec.decode(&mut buf).unwrap().unwrap().len(),
+            MAX_HANDSHAKE_FRAME_SIZE
+        );
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(packet));
+    }
+
+    #[test]
+    fn it_does_not_reserve_the_declared_frame_size() {
+        let mut codec = PacketCodec::default();
+        let mut buf = BytesMut::with_capacity(64);
+
+        codec.encode(vec![1], &mut buf).unwrap();
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(vec![1]));
+
+        buf.put_u32_be(MAX_FRAME_SIZE as u32);
+        buf.put_u8(1);
+
+        assert!(codec.decode(&mut buf).unwrap().is_none());
+        assert!(buf.capacity() < MAX_FRAME_SIZE);
+    }
+}
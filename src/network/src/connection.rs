@@ -16,43 +16,57 @@
   along with the Purple Library. If not, see <http://www.gnu.org/licenses/>.
 */
 
-use network::Network;
-use packets::connect::Connect;
+use error::NetworkErr;
+use futures::future::{self, Either};
+use futures::sync::mpsc;
+use handlers::handle_packet;
+use network::{Network, MIN_PROTOCOL_VERSION, NODE_CAPABILITIES, PROTOCOL_VERSION};
+use node_id::NodeId;
+use packets::{Connect, RequestEventRange, RequestPeers, SendEvents};
 use parking_lot::Mutex;
 use peer::Peer;
-use std::io::BufReader;
-use std::iter;
+use reputation::Misbehavior;
 use std::net::SocketAddr;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::executor::Spawn;
 use tokio::io;
-use tokio::net::{TcpListener, TcpStream};
 use tokio::prelude::future::ok;
 use tokio::prelude::*;
-use tokio_io_timeout::TimeoutStream;
+use tokio::timer::Delay;
+use transport::Socket;
 
-/// Purple network port
+/// Default Purple network port
 pub const PORT: u16 = 44034;
-const PEER_TIMEOUT: u64 = 3000;
 
-/// Initializes the listener for the given network
+const HANDSHAKE_TIMEOUT: u64 = 5000;
+
+/// Time in milliseconds after which an outbound
+/// connection attempt is abandoned.
+pub const CONNECT_TIMEOUT: u64 = 5000;
+const REQUESTED_PEERS: u8 = 32;
+
+/// Initializes a listener on the given address for the given network
 pub fn start_listener(
     network: Arc<Mutex<Network>>,
     accept_connections: Arc<AtomicBool>,
     max_peers: usize,
+    addr: &SocketAddr,
 ) -> Spawn {
-    info!("Starting TCP listener on port {}", PORT);
+    info!("Starting listener on {}", addr);
 
     // Bind the server's socket.
-    let addr = format!("127.0.0.1:{}", PORT).parse().unwrap();
-    let listener = TcpListener::bind(&addr).expect("unable to bind TCP listener");
+    let transport = {
+        let mut network = network.lock();
+        network.set_listen_port(addr.port());
+        network.transport()
+    };
+    let listener = transport.listen(addr).expect("unable to bind listener");
     let accept_connections_clone = accept_connections.clone();
 
     // Pull out a stream of sockets for incoming connections
     let server = listener
-        .incoming()
         .map_err(|e| warn!("accept failed = {:?}", e))
         .filter(move |_| accept_connections_clone.load(Ordering::Relaxed))
         .for_each(move |s| {
@@ -74,8 +88,17 @@ pub fn connect_to_peer(
     max_peers: usize,
     addr: &SocketAddr,
 ) -> Spawn {
-    let connect = TcpStream::connect(addr)
-        .map_err(|e| warn!("connect failed = {:?}", e))
+    let network_clone = network.clone();
+    let addr = *addr;
+    let transport = network.lock().transport();
+
+    let connect = transport
+        .connect(&addr)
+        .timeout(Duration::from_millis(CONNECT_TIMEOUT))
+        .map_err(move |e| {
+            warn!("connect failed = {:?}", e);
+            network_clone.lock().address_book_mut().mark_failed(&addr);
+        })
         .and_then(move |sock| {
             process_connection(
                 network,
@@ -91,19 +114,18 @@ pub fn connect_to_peer(
 
 fn process_connection(
     network: Arc<Mutex<Network>>,
-    sock: TcpStream,
+    sock: Socket,
     max_peers: usize,
     accept_connections: Arc<AtomicBool>,
     client_or_server: ConnectionType,
 ) -> Spawn {
-    let mut sock = TimeoutStream::new(sock);
+    let addr = sock.addr;
 
-    // Set timeout
-    sock.set_read_timeout(Some(Duration::from_millis(PEER_TIMEOUT)));
-    sock.set_write_timeout(Some(Duration::from_millis(PEER_TIMEOUT)));
-
-    let refuse_connection = Arc::new(AtomicBool::new(false));
-    let addr = sock.get_ref().peer_addr().unwrap();
+    if network.lock().is_ip_banned(&addr.ip()) {
+        // Dropping the socket closes the connection
+        debug!("Refusing connection with banned peer {}", addr);
+        return tokio::spawn(ok(()));
+    }
 
     match client_or_server {
         ConnectionType::Client => info!("Connecting to {}", addr),
@@ -112,73 +134,188 @@ fn process_connection(
 
     let network = network.clone();
 
+    let limits = network.lock().rate_limits().clone();
+    let (outbound_sender, outbound_receiver) = mpsc::channel(limits.outbound_queue);
+    let (inbound_sender, inbound_receiver) = mpsc::channel(limits.inbound_queue);
+
     // Create new peer and add it to the peer table
-    let peer = Peer::new(None, addr);
-    network.lock().add_peer(peer);
+    let mut peer = Peer::new(None, addr, client_or_server, outbound_sender);
+    peer.set_rate_limits(&limits);
 
-    let peer_count = network.lock().peer_count();
+    if let Err(err) = network.lock().add_peer(peer) {
+        // Dropping the socket closes the connection
+        debug!("Could not add peer {}: {:?}", addr, err);
+        return tokio::spawn(ok(()));
+    }
 
-    if peer_count >= max_peers {
+    // Outbound peers are managed by the connection
+    // manager and do not take inbound slots.
+    let inbound_count = network.lock().count_by_type(ConnectionType::Server);
+
+    if inbound_count >= max_peers {
         // Stop accepting peers
         accept_connections.store(false, Ordering::Relaxed);
     }
 
-    // Split up the reading and writing parts of the
-    // socket.
-    let (reader, _writer) = sock.split();
-    let reader = BufReader::new(reader);
+    // The reading and writing parts of the socket
+    let Socket { reader, writer, .. } = sock;
+
+    // Forward the packets queued for the peer to the
+    // write half of the socket.
+    let socket_writer = outbound_receiver
+        .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "outbound queue closed"))
+        .forward(writer)
+        .map(|_| ());
+
+    // Both sides start the handshake by sending their connect packet
+    if let Err(err) = send_connect(&network, &addr) {
+        warn!("Could not send connect packet to {}: {}", addr, err);
+    }
+
+    // Queue the decoded frames for processing. The socket is
+    // not read while the inbound queue is full. This "loop"
+    // is terminated once we hit EOF on the socket.
+    let socket_reader =
+        reader
+            .forward(inbound_sender.sink_map_err(|_| {
+                io::Error::new(io::ErrorKind::BrokenPipe, "inbound queue closed")
+            }))
+            .map(|_| ());
 
-    // Model the read portion of this socket by mapping an infinite
-    // iterator to each line off the socket. This "loop" is then
-    // terminated with an error once we hit EOF on the socket.
-    let iter = stream::iter_ok::<_, io::Error>(iter::repeat(()));
     let network_clone = network.clone();
-    let refuse_connection_clone = refuse_connection.clone();
 
-    let socket_reader = iter
-        .take_while(move |_| ok(!refuse_connection_clone.load(Ordering::Relaxed)))
-        .fold(reader, move |reader, _| {
+    // Process each queued frame. This "loop" is terminated
+    // with an error when receiving a malformed frame or
+    // when the handshake fails.
+    let packet_processor = inbound_receiver
+        .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "inbound queue closed"))
+        .for_each(move |packet| {
             let network = network_clone.clone();
+            let is_none_id = {
+                let mut network = network.lock();
 
-            // Read a line off the socket, failing if we're at EOF.
-            let line = io::read_until(reader, b'\n', Vec::new());
-            let line = line.and_then(move |(reader, vec)| {
-                if vec.len() == 0 {
-                    Err(io::Error::new(io::ErrorKind::BrokenPipe, "broken pipe"))
-                } else {
-                    Ok((reader, vec))
-                }
-            });
-
-            let refuse_connection = refuse_connection.clone();
+                // Account for the length prefix of the frame
+                network.record_bytes_in(&addr, packet.len() as u64 + 4);
+                network.is_none_id(&addr)
+            };
 
-            line.map(move |(reader, message)| {
+            match is_none_id {
                 // We should receive a connect packet
                 // if the peer's id is non-existent.
-                if network.lock().is_none_id(&addr) {
-                    match Connect::from_bytes(&message) {
-                        Ok(connect_packet) => {
-                            debug!(
-                                "Received connect packet from {}: {:?}",
-                                addr, connect_packet
-                            );
-                            reader
+                Ok(true) => match handle_connect(&network, &addr, &packet, &client_or_server) {
+                    Ok(()) => {
+                        info!("Handshake with {} completed", addr);
+
+                        let mut network = network.lock();
+
+                        // Only the addresses we have dialed are
+                        // known to accept connections.
+                        if client_or_server == ConnectionType::Client {
+                            network.address_book_mut().mark_seen(addr);
+                        }
+
+                        // Ask the peer for the addresses it knows
+                        let request = RequestPeers::new(REQUESTED_PEERS);
+
+                        if let Err(err) = network.send_to_peer(&addr, request.to_bytes()) {
+                            warn!("Could not request peers from {}: {}", addr, err);
+                        }
+
+                        // Ask the peer for the events that follow our highest one
+                        let highest = network.event_store().map(|store| store.highest());
+
+                        if let Some(from) = highest {
+                            let request =
+                                RequestEventRange::new(from, SendEvents::MAX_EVENTS as u16);
+
+                            if let Err(err) = network.send_to_peer(&addr, request.to_bytes()) {
+                                warn!("Could not request events from {}: {}", addr, err);
+                            }
                         }
-                        _ => {
-                            // Invalid packet, remove peer
-                            debug!("Invalid connect packet from {}", addr);
 
-                            // Flag socket for connection refusal
-                            refuse_connection.store(true, Ordering::Relaxed);
+                        Ok(())
+                    }
+                    Err(err) => {
+                        debug!("Handshake with {} failed: {}", addr, err);
+
+                        if client_or_server == ConnectionType::Client {
+                            network.lock().address_book_mut().mark_failed(&addr);
+                        }
+
+                        Err(io::Error::new(io::ErrorKind::InvalidData, err))
+                    }
+                },
+                Ok(false) => {
+                    // All packets after the handshake are encrypted. Frames
+                    // that fail authentication close the connection while
+                    // packets over the rate limits are dropped.
+                    let packet = {
+                        let mut network = network.lock();
+                        let decrypted = network.peer_mut(&addr).map(|peer| {
+                            peer.decrypt_packet(&packet).map(|packet| {
+                                let packet_type = packet.first().cloned().unwrap_or(0);
+                                let allowed =
+                                    peer.allow_packet(packet_type, packet.len(), Instant::now());
+
+                                (packet, allowed, peer.is_flooding())
+                            })
+                        });
+
+                        // Occasional bursts over the rate limits are
+                        // dropped and only sustained ones are penalized.
+                        match decrypted {
+                            Some(Ok((packet, true, _))) => Ok(Some(packet)),
+                            Some(Ok((_, false, flooding))) => {
+                                if flooding {
+                                    network.penalize(&addr, Misbehavior::Flooding);
+                                }
+
+                                Ok(None)
+                            }
+                            Some(Err(err)) => {
+                                network.penalize(&addr, Misbehavior::BadSignature);
+                                Err(err)
+                            }
+                            None => Err("There is no listed peer with the given address"),
+                        }
+                    };
 
-                            reader
+                    let result = match packet {
+                        Ok(Some(packet)) => handle_packet(&network, &addr, &packet),
+                        Ok(None) => {
+                            debug!("Dropping rate limited packet from {}", addr);
+                            Ok(())
+                        }
+                        Err(err) => Err(err),
+                    };
+
+                    match result {
+                        Ok(()) => Ok(()),
+                        Err(err) => {
+                            debug!("Invalid packet from {}: {}", addr, err);
+                            Err(io::Error::new(io::ErrorKind::InvalidData, err))
                         }
                     }
-                } else {
-                    info!("{}: {}", addr, hex::encode(message));
-                    reader
                 }
-            })
+                Err(err) => Err(io::Error::new(
+                    io::ErrorKind::BrokenPipe,
+                    format!("{:?}", err),
+                )),
+            }
+        });
+
+    // Disconnect peers that do not complete
+    // the handshake in the allowed time.
+    let network_clone = network.clone();
+    let handshake_timeout = Delay::new(Instant::now() + Duration::from_millis(HANDSHAKE_TIMEOUT))
+        .map_err(|_| ())
+        .and_then(move |_| {
+            if network_clone.lock().is_none_id(&addr) != Ok(false) {
+                debug!("Handshake with {} timed out", addr);
+                Either::A(ok(()))
+            } else {
+                Either::B(future::empty())
+            }
         });
 
     // Now that we've got futures representing each half of the socket, we
@@ -186,15 +323,25 @@ fn process_connection(
     // tear down the other. Then we spawn off the result.
     let network = network.clone();
     let socket_reader = socket_reader.map_err(|_| ());
+    let socket_writer = socket_writer.map_err(|_| ());
+    let packet_processor = packet_processor.map_err(|_| ());
+    let connection = socket_reader
+        .select(socket_writer)
+        .map(|_| ())
+        .map_err(|_| ())
+        .select(packet_processor)
+        .map(|_| ())
+        .map_err(|_| ())
+        .select(handshake_timeout);
 
     let accept_connections = accept_connections.clone();
 
     // Spawn a task to process the connection
-    tokio::spawn(socket_reader.then(move |_| {
+    tokio::spawn(connection.then(move |_| {
         network.lock().remove_peer_with_addr(&addr);
 
         // Re-enable connections
-        if network.lock().peer_count() < max_peers {
+        if network.lock().count_by_type(ConnectionType::Server) < max_peers {
             accept_connections.store(true, Ordering::Relaxed);
         }
 
@@ -203,8 +350,104 @@ fn process_connection(
     }))
 }
 
-enum ConnectionType {
+/// Sends our signed connect packet to the peer with the given address.
+fn send_connect(network: &Arc<Mutex<Network>>, addr: &SocketAddr) -> Result<(), &'static str> {
+    let mut network = network.lock();
+    let kx_key = match network.peer(addr) {
+        Some(peer) => peer.kx_public_key().clone(),
+        None => return Err("There is no listed peer with the given address"),
+    };
+
+    let mut connect = Connect::new(
+        network.node_id().0,
+        kx_key,
+        *network.network_id(),
+        PROTOCOL_VERSION,
+        NODE_CAPABILITIES,
+        network.listen_port(),
+    );
+    connect.sign(network.secret_key().clone());
+
+    network.send_to_peer(addr, connect.to_bytes())
+}
+
+/// Validates the connect packet received from the peer with the
+/// given address, binds its node id to the peer entry and derives
+/// the session keys.
+///
+/// Peers that are on a different network or that speak an
+/// incompatible protocol version are rejected.
+fn handle_connect(
+    network: &Arc<Mutex<Network>>,
+    addr: &SocketAddr,
+    packet: &[u8],
+    client_or_server: &ConnectionType,
+) -> Result<(), &'static str> {
+    let mut network = network.lock();
+    let mut connect = match Connect::from_bytes(packet) {
+        Ok(connect) => connect,
+        Err(err) => {
+            network.penalize(addr, Misbehavior::MalformedPacket);
+            return Err(err);
+        }
+    };
+
+    if !connect.verify_sig() {
+        network.penalize(addr, Misbehavior::BadSignature);
+        return Err("Invalid connect packet signature");
+    }
+
+    let node_id = NodeId(*connect.node_id());
+
+    if network.is_node_id_banned(&node_id) {
+        return Err("Peer is banned");
+    }
+
+    if node_id == network.node_id() {
+        return Err("Cannot connect to ourselves");
+    }
+
+    if connect.network_id() != network.network_id() {
+        return Err("Peer is on a different network");
+    }
+
+    if connect.protocol_version() < MIN_PROTOCOL_VERSION {
+        return Err("Incompatible protocol version");
+    }
+
+    match network.set_node_id(addr, node_id) {
+        Ok(()) => {}
+        Err(NetworkErr::DuplicateNodeId) => {
+            return Err("Already connected to a peer with the same node id")
+        }
+        Err(_) => return Err("There is no listed peer with the given address"),
+    }
+
+    match network.peer_mut(addr) {
+        Some(peer) => {
+            peer.protocol_version = Some(connect.protocol_version());
+            peer.capabilities = Some(connect.capabilities());
+            peer.derive_session_keys(connect.kx_key(), client_or_server)?;
+        }
+        None => return Err("There is no listed peer with the given address"),
+    }
+
+    // Inbound peers connect from an ephemeral port so we
+    // record the port they advertise as listening on.
+    if *client_or_server == ConnectionType::Server && connect.listen_port() != 0 {
+        let listen_addr = SocketAddr::new(addr.ip(), connect.listen_port());
+        network.address_book_mut().insert(listen_addr);
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConnectionType {
+    /// We have initiated the connection
     Client,
+
+    /// The peer has initiated the connection
     Server,
 }
 
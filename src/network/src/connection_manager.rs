@@ -0,0 +1,418 @@
+/*
+  Copyright 2018 The Purple Library Authors
+  This file is part of the Purple Library.
+
+  The Purple Library is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  The Purple Library is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with the Purple Library. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use connection::{connect_to_peer, ConnectionType, CONNECT_TIMEOUT};
+use futures::Stream;
+use network::Network;
+use parking_lot::Mutex;
+use rand::Rng;
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::executor::Spawn;
+use tokio::timer::Interval;
+
+/// Interval in milliseconds at which the
+/// outbound connections are checked.
+const CONNECTION_MANAGER_INTERVAL: u64 = 2000;
+
+/// The delay in milliseconds before retrying
+/// an address for the first time.
+const BASE_BACKOFF: u64 = 1000;
+
+/// The maximum delay in milliseconds between
+/// two attempts to connect to an address.
+const MAX_BACKOFF: u64 = 600000;
+
+#[derive(Debug, Clone)]
+struct Backoff {
+    /// The number of consecutive connection attempts
+    attempts: u32,
+
+    /// The time of the last connection attempt
+    last_attempt: Instant,
+
+    /// The time before which the address is not retried.
+    ///
+    /// The backoff delay starts after the connection
+    /// attempt has timed out.
+    next_attempt: Instant,
+}
+
+/// Keeps a target number of outbound peers by
+/// dialing addresses from the address book.
+#[derive(Debug)]
+pub struct ConnectionManager {
+    /// The number of outbound peers to keep
+    target_outbound: usize,
+
+    /// Addresses that are always used as a last resort
+    fallback: Vec<SocketAddr>,
+
+    /// The backoff state of each dialed address
+    backoffs: HashMap<SocketAddr, Backoff>,
+}
+
+impl ConnectionManager {
+    pub fn new(target_outbound: usize, fallback: Vec<SocketAddr>) -> ConnectionManager {
+        ConnectionManager {
+            target_outbound: target_outbound,
+            fallback: fallback,
+            backoffs: HashMap::new(),
+        }
+    }
+
+    /// Returns the addresses that should be dialed at the
+    /// given time in order to reach the target number of
+    /// outbound peers and records the connection attempts.
+    ///
+    /// Candidates from subnets that we are not yet
+    /// connected to are picked first.
+    pub fn next_candidates<R: Rng>(
+        &mut self,
+        network: &Network,
+        now: Instant,
+        rng: &mut R,
+    ) -> Vec<SocketAddr> {
+        let connect_timeout = Duration::from_millis(CONNECT_TIMEOUT);
+        let fallback = &self.fallback;
+
+        // Forget the backoff of the addresses that we have successfully
+        // connected to and of the addresses that have been evicted from
+        // the address book. A successful connection resets the failures
+        // of an address.
+        self.backoffs.retain(|addr, backoff| {
+            if let Some(peer) = network.peer(addr) {
+                return peer.id.is_none();
+            }
+
+            if now < backoff.last_attempt + connect_timeout {
+                return true;
+            }
+
+            match network.address_book().get(addr) {
+                Some(entry) => entry.failures > 0,
+                None => fallback.contains(addr),
+            }
+        });
+
+        let outbound = network.addrs_by_type(ConnectionType::Client);
+        let in_flight: Vec<SocketAddr> = self
+            .backoffs
+            .iter()
+            .filter(|(addr, backoff)| {
+                now < backoff.last_attempt + connect_timeout && network.peer(addr).is_none()
+            })
+            .map(|(addr, _)| *addr)
+            .collect();
+
+        if outbound.len() + in_flight.len() >= self.target_outbound {
+            return Vec::new();
+        }
+
+        let needed = self.target_outbound - outbound.len() - in_flight.len();
+        let mut used_subnets: HashSet<Vec<u8>> = outbound
+            .iter()
+            .chain(in_flight.iter())
+            .map(subnet)
+            .collect();
+
+        let address_book = network.address_book();
+        let candidates: Vec<SocketAddr> = address_book
+            .best(address_book.len(), &[])
+            .into_iter()
+            .chain(self.fallback.iter().cloned())
+            .filter(|addr| network.peer(addr).is_none() && !in_flight.contains(addr))
+            .filter(|addr| !network.is_ip_banned(&addr.ip()))
+            .filter(|addr| match self.backoffs.get(addr) {
+                Some(backoff) => now >= backoff.next_attempt,
+                None => true,
+            })
+            .collect();
+
+        let mut selected: Vec<SocketAddr> = Vec::with_capacity(needed);
+
+        // First pick candidates from distinct subnets
+        for addr in candidates.iter() {
+            if selected.len() >= needed {
+                break;
+            }
+
+            if used_subnets.insert(subnet(addr)) && !selected.contains(addr) {
+                selected.push(*addr);
+            }
+        }
+
+        // Then fill the remaining slots with any candidate
+        for addr in candidates.iter() {
+            if selected.len() >= needed {
+                break;
+            }
+
+            if !selected.contains(addr) {
+                selected.push(*addr);
+            }
+        }
+
+        for addr in selected.iter() {
+            let attempts = match self.backoffs.get(addr) {
+                Some(backoff) => backoff.attempts + 1,
+                None => 1,
+            };
+
+            self.backoffs.insert(
+                *addr,
+                Backoff {
+                    attempts: attempts,
+                    last_attempt: now,
+                    next_attempt: now + connect_timeout + backoff_delay(attempts, rng),
+                },
+            );
+        }
+
+        selected
+    }
+}
+
+/// Starts the task that keeps the target number of outbound peers.
+pub fn start_connection_manager(
+    network: Arc<Mutex<Network>>,
+    accept_connections: Arc<AtomicBool>,
+    max_peers: usize,
+    manager: ConnectionManager,
+) -> Spawn {
+    let interval = Duration::from_millis(CONNECTION_MANAGER_INTERVAL);
+    let mut manager = manager;
+
+    let fut = Interval::new(Instant::now(), interval)
+        .map_err(|e| warn!("connection manager interval errored = {:?}", e))
+        .for_each(move |now| {
+            let candidates = manager.next_candidates(&network.lock(), now, &mut rand::thread_rng());
+
+            for addr in candidates.iter() {
+                debug!("Dialing {}", addr);
+                connect_to_peer(network.clone(), accept_connections.clone(), max_peers, addr);
+            }
+
+            Ok(())
+        });
+
+    tokio::spawn(fut)
+}
+
+/// Returns the delay before the next connection attempt after
+/// the given number of consecutive attempts.
+///
+/// The delay doubles with each attempt up to `MAX_BACKOFF` and
+/// a random jitter of up to half of the delay is added so that
+/// nodes do not retry in lockstep.
+fn backoff_delay<R: Rng>(attempts: u32, rng: &mut R) -> Duration {
+    let exponent = if attempts > 0 { attempts - 1 } else { 0 };
+    let delay = if exponent >= 20 {
+        MAX_BACKOFF
+    } else {
+        (BASE_BACKOFF << exponent).min(MAX_BACKOFF)
+    };
+
+    let jitter = rng.gen_range(0, delay / 2 + 1);
+
+    Duration::from_millis(delay + jitter)
+}
+
+/// Returns the subnet of an address. This is the /16
+/// prefix for IPv4 and the /32 prefix for IPv6.
+fn subnet(addr: &SocketAddr) -> Vec<u8> {
+    match addr.ip() {
+        IpAddr::V4(ip) => {
+            let octets = ip.octets();
+            vec![4, octets[0], octets[1]]
+        }
+        IpAddr::V6(ip) => {
+            let octets = ip.octets();
+            vec![6, octets[0], octets[1], octets[2], octets[3]]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use address_book::MAX_FAILURES;
+    use crypto::Identity;
+    use futures::sync::mpsc;
+    use rand::rngs::mock::StepRng;
+    use NodeId;
+    use Peer;
+
+    fn network() -> Network {
+        let identity = Identity::new();
+        Network::new(
+            NodeId(*identity.pkey()),
+            identity.skey().clone(),
+            "test".to_owned(),
+        )
+    }
+
+    fn add_peer(network: &mut Network, addr: &str) -> SocketAddr {
+        let (sender, _receiver) = mpsc::channel(8);
+        let addr: SocketAddr = addr.parse().unwrap();
+
+        network
+            .add_peer(Peer::new(
+                Some(NodeId(*Identity::new().pkey())),
+                addr,
+                ConnectionType::Client,
+                sender,
+            ))
+            .unwrap();
+
+        addr
+    }
+
+    #[test]
+    fn it_keeps_the_target_number_of_peers() {
+        let mut network = network();
+        let mut rng = StepRng::new(0, 0);
+        let mut manager = ConnectionManager::new(2, vec![]);
+        let now = Instant::now();
+
+        for i in 0..5 {
+            let addr = format!("10.{}.0.1:44034", i).parse().unwrap();
+            network.address_book_mut().insert(addr);
+        }
+
+        add_peer(&mut network, "10.0.0.1:44034");
+
+        let candidates = manager.next_candidates(&network, now, &mut rng);
+        assert_eq!(candidates.len(), 1);
+
+        // The connection attempt is still in flight
+        assert!(manager.next_candidates(&network, now, &mut rng).is_empty());
+
+        add_peer(&mut network, &candidates[0].to_string());
+        assert!(manager.next_candidates(&network, now, &mut rng).is_empty());
+    }
+
+    #[test]
+    fn it_prefers_diverse_subnets() {
+        let mut network = network();
+        let mut rng = StepRng::new(0, 0);
+        let mut manager = ConnectionManager::new(3, vec![]);
+
+        add_peer(&mut network, "10.0.0.1:44034");
+
+        let same_subnet: Vec<SocketAddr> = (2..6)
+            .map(|i| format!("10.0.0.{}:44034", i).parse().unwrap())
+            .collect();
+        let other_subnet: SocketAddr = "10.1.0.1:44034".parse().unwrap();
+
+        for addr in same_subnet.iter() {
+            network.address_book_mut().mark_seen(*addr);
+        }
+
+        network.address_book_mut().insert(other_subnet);
+
+        let candidates = manager.next_candidates(&network, Instant::now(), &mut rng);
+
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(candidates[0], other_subnet);
+        assert!(same_subnet.contains(&candidates[1]));
+    }
+
+    #[test]
+    fn it_backs_off_failing_addresses() {
+        let network = network();
+        let mut rng = StepRng::new(0, 0);
+        let bootnode: SocketAddr = "10.0.0.1:44034".parse().unwrap();
+        let mut manager = ConnectionManager::new(1, vec![bootnode]);
+        let now = Instant::now();
+
+        assert_eq!(
+            manager.next_candidates(&network, now, &mut rng),
+            vec![bootnode]
+        );
+
+        // The connection attempt has timed out but the
+        // backoff delay has not yet passed.
+        let now = now + Duration::from_millis(CONNECT_TIMEOUT);
+        assert!(manager.next_candidates(&network, now, &mut rng).is_empty());
+
+        let now = now + Duration::from_millis(BASE_BACKOFF * 2);
+        assert_eq!(
+            manager.next_candidates(&network, now, &mut rng),
+            vec![bootnode]
+        );
+        assert_eq!(manager.backoffs[&bootnode].attempts, 2);
+    }
+
+    #[test]
+    fn it_forgets_the_backoff_of_connected_and_evicted_addresses() {
+        let mut network = network();
+        let mut rng = StepRng::new(0, 0);
+        let mut manager = ConnectionManager::new(2, vec![]);
+        let now = Instant::now();
+        let seen: SocketAddr = "10.0.0.1:44034".parse().unwrap();
+        let evicted: SocketAddr = "10.1.0.1:44034".parse().unwrap();
+
+        network.address_book_mut().insert(seen);
+        network.address_book_mut().insert(evicted);
+
+        assert_eq!(manager.next_candidates(&network, now, &mut rng).len(), 2);
+        assert_eq!(manager.backoffs.len(), 2);
+
+        // The first address accepts the connection and then
+        // disconnects while the second one keeps failing.
+        network.address_book_mut().mark_seen(seen);
+
+        for _ in 0..MAX_FAILURES {
+            network.address_book_mut().mark_failed(&evicted);
+        }
+
+        let now = now + Duration::from_millis(CONNECT_TIMEOUT);
+        assert_eq!(manager.next_candidates(&network, now, &mut rng), vec![seen]);
+        assert_eq!(manager.backoffs.len(), 1);
+        assert_eq!(manager.backoffs[&seen].attempts, 1);
+    }
+
+    #[test]
+    fn backoff_delay_grows_exponentially() {
+        let mut rng = StepRng::new(0, 0);
+
+        assert_eq!(
+            backoff_delay(1, &mut rng),
+            Duration::from_millis(BASE_BACKOFF)
+        );
+        assert_eq!(
+            backoff_delay(3, &mut rng),
+            Duration::from_millis(BASE_BACKOFF * 4)
+        );
+        assert_eq!(
+            backoff_delay(100, &mut rng),
+            Duration::from_millis(MAX_BACKOFF)
+        );
+
+        let mut rng = rand::thread_rng();
+
+        for attempts in 1..30 {
+            let delay = backoff_delay(attempts, &mut rng);
+            assert!(delay <= Duration::from_millis(MAX_BACKOFF + MAX_BACKOFF / 2));
+        }
+    }
+}
@@ -0,0 +1,68 @@
+/*
+  Copyright 2018 The Purple Library Authors
+  This file is part of the Purple Library.
+
+  The Purple Library is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  The Purple Library is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with the Purple Library. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use crypto::Hash;
+use std::fmt::Debug;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum EventStoreErr {
+    /// The event with the given hash is already stored
+    AlreadyStored(Hash),
+
+    /// The event with the given hash is well formed but cannot
+    /// be stored in our current state e.g. because its parent
+    /// has been pruned or because the validator set it was
+    /// issued against has changed since.
+    Rejected(Hash),
+
+    /// The event could not be decoded or is invalid
+    InvalidEvent(&'static str),
+}
+
+/// Storage of the events that are gossiped between peers.
+///
+/// The network layer only handles serialized events so
+/// that it does not depend on the consensus layer which
+/// is in charge of validating and storing them.
+///
+/// Implementations synchronize their own state so that
+/// events are validated without holding the network lock.
+pub trait EventStore: Debug + Send + Sync {
+    /// Returns `true` if the event with the given hash is stored.
+    fn contains(&self, hash: &Hash) -> bool;
+
+    /// Returns the serialized event with the given hash.
+    fn get(&self, hash: &Hash) -> Option<Vec<u8>>;
+
+    /// Validates and stores a serialized event.
+    ///
+    /// Returns the hash of the stored event.
+    fn push(&self, event: &[u8]) -> Result<Hash, EventStoreErr>;
+
+    /// Returns the hash of the highest stored event.
+    fn highest(&self) -> Hash;
+
+    /// Returns the hashes of the events that are not stored
+    /// but are required in order to process stored ones.
+    fn missing(&self) -> Vec<Hash>;
+
+    /// Returns at most `count` serialized events that follow
+    /// the event with the given hash. Parents always come
+    /// before their children.
+    fn following(&self, hash: &Hash, count: usize) -> Vec<Vec<u8>>;
+}
@@ -0,0 +1,430 @@
+/*
+  Copyright 2018 The Purple Library Authors
+  This file is part of the Purple Library.
+
+  The Purple Library is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  The Purple Library is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with the Purple Library. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use event_store::EventStoreErr;
+use network::Network;
+use packets::{
+    AnnounceEvents, AnnounceTxs, Ping, Pong, RequestEventRange, RequestEvents, RequestPeers,
+    RequestTxs, SendEvents, SendPeers, SendTxs, MAX_HASHES,
+};
+use parking_lot::Mutex;
+use reputation::Misbehavior;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Instant;
+use tx_store::TxStoreErr;
+
+/// Handles a decrypted packet received from the
+/// peer with the given address.
+pub fn handle_packet(
+    network: &Arc<Mutex<Network>>,
+    addr: &SocketAddr,
+    packet: &[u8],
+) -> Result<(), &'static str> {
+    match packet.first() {
+        Some(&Ping::PACKET_TYPE) => handle_ping(network, addr, packet),
+        Some(&Pong::PACKET_TYPE) => handle_pong(network, addr, packet),
+        Some(&RequestPeers::PACKET_TYPE) => handle_request_peers(network, addr, packet),
+        Some(&SendPeers::PACKET_TYPE) => handle_send_peers(network, addr, packet),
+        Some(&AnnounceEvents::PACKET_TYPE) => handle_announce_events(network, addr, packet),
+        Some(&RequestEvents::PACKET_TYPE) => handle_request_events(network, addr, packet),
+        Some(&SendEvents::PACKET_TYPE) => handle_send_events(network, addr, packet),
+        Some(&RequestEventRange::PACKET_TYPE) => handle_request_event_range(network, addr, packet),
+        Some(&AnnounceTxs::PACKET_TYPE) => handle_announce_txs(network, addr, packet),
+        Some(&RequestTxs::PACKET_TYPE) => handle_request_txs(network, addr, packet),
+        Some(&SendTxs::PACKET_TYPE) => handle_send_txs(network, addr, packet),
+        Some(packet_type) => {
+            debug!("Unknown packet type {} from {}", packet_type, addr);
+            Ok(())
+        }
+        None => Err("Empty packet"),
+    }
+}
+
+/// Penalizes the peer with the given address
+/// if its packet could not be decoded.
+fn decode<T>(
+    network: &Arc<Mutex<Network>>,
+    addr: &SocketAddr,
+    result: Result<T, &'static str>,
+) -> Result<T, &'static str> {
+    if result.is_err() {
+        network.lock().penalize(addr, Misbehavior::MalformedPacket);
+    }
+
+    result
+}
+
+/// Returns `true` if a packet of the given type received from
+/// the peer with the given address answers one of our requests.
+fn is_reply(network: &Arc<Mutex<Network>>, addr: &SocketAddr, packet_type: u8) -> bool {
+    match network.lock().peer_mut(addr) {
+        Some(peer) => peer.reply_received(packet_type, Instant::now()),
+        None => false,
+    }
+}
+
+fn handle_ping(
+    network: &Arc<Mutex<Network>>,
+    addr: &SocketAddr,
+    packet: &[u8],
+) -> Result<(), &'static str> {
+    let packet = decode(network, addr, Ping::from_bytes(packet))?;
+    let mut network = network.lock();
+
+    network.send_to_peer(addr, Pong::new(packet.nonce).to_bytes())
+}
+
+fn handle_pong(
+    network: &Arc<Mutex<Network>>,
+    addr: &SocketAddr,
+    packet: &[u8],
+) -> Result<(), &'static str> {
+    let packet = decode(network, addr, Pong::from_bytes(packet))?;
+    let mut network = network.lock();
+
+    let peer = match network.peer_mut(addr) {
+        Some(peer) => peer,
+        None => return Err("There is no listed peer with the given address"),
+    };
+
+    if peer.pong_received(packet.nonce, Instant::now()) {
+        debug!("Latency to {} is {:?}", addr, peer.latency);
+    } else {
+        debug!("Received pong with unexpected nonce from {}", addr);
+    }
+
+    Ok(())
+}
+
+fn handle_request_peers(
+    network: &Arc<Mutex<Network>>,
+    addr: &SocketAddr,
+    packet: &[u8],
+) -> Result<(), &'static str> {
+    let packet = decode(network, addr, RequestPeers::from_bytes(packet))?;
+    let mut network = network.lock();
+
+    debug!(
+        "Received request for {} peers from {}",
+        packet.requested_peers, addr
+    );
+
+    let peers = network
+        .address_book()
+        .best(packet.requested_peers as usize, &[*addr]);
+
+    network.send_to_peer(addr, SendPeers::new(peers).to_bytes())
+}
+
+fn handle_send_peers(
+    network: &Arc<Mutex<Network>>,
+    addr: &SocketAddr,
+    packet: &[u8],
+) -> Result<(), &'static str> {
+    let packet = decode(network, addr, SendPeers::from_bytes(packet))?;
+    let mut network = network.lock();
+    let address_book = network.address_book_mut();
+    let mut added = 0;
+
+    for peer in packet.peers {
+        if address_book.insert(peer) {
+            added += 1;
+        }
+    }
+
+    debug!("Received {} new peer addresses from {}", added, addr);
+
+    Ok(())
+}
+
+fn handle_announce_events(
+    network: &Arc<Mutex<Network>>,
+    addr: &SocketAddr,
+    packet: &[u8],
+) -> Result<(), &'static str> {
+    let packet = decode(network, addr, AnnounceEvents::from_bytes(packet))?;
+    let mut network = network.lock();
+
+    match network.peer_mut(addr) {
+        Some(peer) => {
+            for hash in packet.hashes.iter() {
+                peer.known_events.insert(*hash);
+            }
+        }
+        None => return Err("There is no listed peer with the given address"),
+    }
+
+    let missing = network.request_events(addr, &packet.hashes, Instant::now());
+
+    if missing.is_empty() {
+        return Ok(());
+    }
+
+    debug!("Requesting {} events from {}", missing.len(), addr);
+    network.send_to_peer(addr, RequestEvents::new(missing).to_bytes())
+}
+
+fn handle_request_events(
+    network: &Arc<Mutex<Network>>,
+    addr: &SocketAddr,
+    packet: &[u8],
+) -> Result<(), &'static str> {
+    let packet = decode(network, addr, RequestEvents::from_bytes(packet))?;
+    let mut network = network.lock();
+
+    let events: Vec<Vec<u8>> = match network.event_store() {
+        Some(event_store) => packet
+            .hashes
+            .iter()
+            .filter_map(|hash| event_store.get(hash))
+            .take(SendEvents::MAX_EVENTS)
+            .collect(),
+        None => return Ok(()),
+    };
+
+    debug!(
+        "Sending {} of {} requested events to {}",
+        events.len(),
+        packet.hashes.len(),
+        addr
+    );
+
+    if events.is_empty() {
+        return Ok(());
+    }
+
+    network.send_to_peer(addr, SendEvents::new(events).to_bytes())
+}
+
+fn handle_send_events(
+    network: &Arc<Mutex<Network>>,
+    addr: &SocketAddr,
+    packet: &[u8],
+) -> Result<(), &'static str> {
+    let packet = decode(network, addr, SendEvents::from_bytes(packet))?;
+    let solicited = is_reply(network, addr, SendEvents::PACKET_TYPE);
+    let event_store = match network.lock().event_store() {
+        Some(event_store) => event_store,
+        None => return Ok(()),
+    };
+
+    let mut last_new = None;
+    let mut stored = 0;
+
+    for event in packet.events.iter() {
+        // Validating an event is expensive so the network
+        // lock is only taken once the event is stored.
+        let result = event_store.push(event);
+        let mut network = network.lock();
+
+        let hash = match result {
+            Ok(hash) => {
+                debug!("Received new event {:?} from {}", hash, addr);
+                network.announce_event(hash, Some(addr));
+                last_new = Some(hash);
+                hash
+            }
+            Err(EventStoreErr::AlreadyStored(hash)) => {
+                stored += 1;
+                hash
+            }
+            Err(EventStoreErr::Rejected(hash)) => {
+                debug!("Rejected event {:?} from {}", hash, addr);
+                hash
+            }
+            Err(EventStoreErr::InvalidEvent(err)) => {
+                network.penalize(addr, Misbehavior::InvalidEvent);
+                return Err(err);
+            }
+        };
+
+        network.event_received(&hash);
+
+        if let Some(peer) = network.peer_mut(addr) {
+            peer.known_events.insert(hash);
+        }
+    }
+
+    // Unsolicited packets that only contain events
+    // that we already have are counted as flooding.
+    if !solicited && stored == packet.events.len() {
+        network.lock().penalize(addr, Misbehavior::Flooding);
+    }
+
+    if last_new.is_none() {
+        return Ok(());
+    }
+
+    // Ask the same peer for the parents of the received
+    // events that we are missing.
+    let missing = event_store.missing();
+    let mut network = network.lock();
+    let missing = network.request_events(addr, &missing, Instant::now());
+
+    for hashes in missing.chunks(MAX_HASHES) {
+        network.send_to_peer(addr, RequestEvents::new(hashes.to_vec()).to_bytes())?;
+    }
+
+    // A full packet may be the response to a range
+    // request in which case there are more events.
+    if packet.events.len() == SendEvents::MAX_EVENTS {
+        if let Some(from) = last_new {
+            let request = RequestEventRange::new(from, SendEvents::MAX_EVENTS as u16);
+            network.send_to_peer(addr, request.to_bytes())?;
+        }
+    }
+
+    Ok(())
+}
+
+fn handle_request_event_range(
+    network: &Arc<Mutex<Network>>,
+    addr: &SocketAddr,
+    packet: &[u8],
+) -> Result<(), &'static str> {
+    let packet = decode(network, addr, RequestEventRange::from_bytes(packet))?;
+    let mut network = network.lock();
+    let count = (packet.count as usize).min(SendEvents::MAX_EVENTS);
+
+    let events = match network.event_store() {
+        Some(event_store) => event_store.following(&packet.from, count),
+        None => return Ok(()),
+    };
+
+    debug!(
+        "Sending {} events following {:?} to {}",
+        events.len(),
+        packet.from,
+        addr
+    );
+
+    if events.is_empty() {
+        return Ok(());
+    }
+
+    network.send_to_peer(addr, SendEvents::new(events).to_bytes())
+}
+
+fn handle_announce_txs(
+    network: &Arc<Mutex<Network>>,
+    addr: &SocketAddr,
+    packet: &[u8],
+) -> Result<(), &'static str> {
+    let packet = decode(network, addr, AnnounceTxs::from_bytes(packet))?;
+    let mut network = network.lock();
+
+    match network.peer_mut(addr) {
+        Some(peer) => {
+            for hash in packet.hashes.iter() {
+                peer.known_txs.insert(*hash);
+            }
+        }
+        None => return Err("There is no listed peer with the given address"),
+    }
+
+    let missing = network.request_txs(&packet.hashes, Instant::now());
+
+    if missing.is_empty() {
+        return Ok(());
+    }
+
+    debug!("Requesting {} transactions from {}", missing.len(), addr);
+    network.send_to_peer(addr, RequestTxs::new(missing).to_bytes())
+}
+
+fn handle_request_txs(
+    network: &Arc<Mutex<Network>>,
+    addr: &SocketAddr,
+    packet: &[u8],
+) -> Result<(), &'static str> {
+    let packet = decode(network, addr, RequestTxs::from_bytes(packet))?;
+    let mut network = network.lock();
+
+    let txs: Vec<Vec<u8>> = match network.tx_store() {
+        Some(tx_store) => packet
+            .hashes
+            .iter()
+            .filter_map(|hash| tx_store.get(hash))
+            .take(SendTxs::MAX_TXS)
+            .collect(),
+        None => return Ok(()),
+    };
+
+    if txs.is_empty() {
+        return Ok(());
+    }
+
+    network.send_to_peer(addr, SendTxs::new(txs).to_bytes())
+}
+
+fn handle_send_txs(
+    network: &Arc<Mutex<Network>>,
+    addr: &SocketAddr,
+    packet: &[u8],
+) -> Result<(), &'static str> {
+    let packet = decode(network, addr, SendTxs::from_bytes(packet))?;
+    let solicited = is_reply(network, addr, SendTxs::PACKET_TYPE);
+    let tx_store = match network.lock().tx_store() {
+        Some(tx_store) => tx_store,
+        None => return Ok(()),
+    };
+
+    let mut stored = 0;
+
+    for tx in packet.txs.iter() {
+        // Validating a transaction is expensive so the network
+        // lock is only taken once the transaction is stored.
+        let result = tx_store.push(tx);
+        let mut network = network.lock();
+
+        let hash = match result {
+            Ok(hash) => {
+                debug!("Received new transaction {:?} from {}", hash, addr);
+                network.announce_tx(hash, Some(addr));
+                hash
+            }
+            Err(TxStoreErr::AlreadyStored(hash)) => {
+                stored += 1;
+                hash
+            }
+            Err(TxStoreErr::Rejected(hash)) => {
+                debug!("Rejected transaction {:?} from {}", hash, addr);
+                hash
+            }
+            Err(TxStoreErr::InvalidTx(err)) => {
+                network.penalize(addr, Misbehavior::InvalidTx);
+                return Err(err);
+            }
+        };
+
+        network.tx_received(&hash);
+
+        if let Some(peer) = network.peer_mut(addr) {
+            peer.known_txs.insert(hash);
+        }
+    }
+
+    // Unsolicited packets that only contain transactions
+    // that we already have are counted as flooding.
+    if !solicited && stored == packet.txs.len() {
+        network.lock().penalize(addr, Misbehavior::Flooding);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,112 @@
+/*
+  Copyright 2018 The Purple Library Authors
+  This file is part of the Purple Library.
+
+  The Purple Library is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  The Purple Library is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with the Purple Library. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use crypto::Hash;
+use std::collections::{HashSet, VecDeque};
+
+/// Bounded set of hashes that forgets the
+/// oldest inserted hash when it is full.
+#[derive(Debug, Clone)]
+pub struct KnownHashes {
+    /// The maximum number of stored hashes
+    capacity: usize,
+
+    /// The stored hashes
+    hashes: HashSet<Hash>,
+
+    /// The stored hashes in insertion order
+    order: VecDeque<Hash>,
+}
+
+impl KnownHashes {
+    pub fn new(capacity: usize) -> KnownHashes {
+        KnownHashes {
+            capacity: capacity,
+            hashes: HashSet::with_capacity(capacity),
+            order: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Inserts a hash into the set.
+    ///
+    /// Returns `false` if the hash is already stored.
+    pub fn insert(&mut self, hash: Hash) -> bool {
+        if self.capacity == 0 || self.hashes.contains(&hash) {
+            return false;
+        }
+
+        if self.order.len() >= self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.hashes.remove(&oldest);
+            }
+        }
+
+        self.hashes.insert(hash);
+        self.order.push_back(hash);
+
+        true
+    }
+
+    /// Returns `true` if the given hash is stored.
+    pub fn contains(&self, hash: &Hash) -> bool {
+        self.hashes.contains(hash)
+    }
+
+    /// Returns the number of stored hashes.
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    quickcheck! {
+        fn it_never_exceeds_capacity(hashes: Vec<Hash>, capacity: u8) -> bool {
+            let mut known = KnownHashes::new(capacity as usize);
+
+            for hash in hashes.iter() {
+                known.insert(*hash);
+            }
+
+            known.len() <= capacity as usize && known.hashes.len() == known.len()
+        }
+    }
+
+    #[test]
+    fn it_forgets_the_oldest_hash() {
+        let mut known = KnownHashes::new(2);
+        let h1 = Hash::random();
+        let h2 = Hash::random();
+        let h3 = Hash::random();
+
+        assert!(known.insert(h1));
+        assert!(known.insert(h2));
+        assert!(!known.insert(h2));
+        assert!(known.insert(h3));
+
+        assert!(!known.contains(&h1));
+        assert!(known.contains(&h2));
+        assert!(known.contains(&h3));
+    }
+}
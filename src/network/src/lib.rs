@@ -24,11 +24,14 @@ extern crate quickcheck;
 extern crate log;
 
 extern crate byteorder;
+extern crate bytes;
 extern crate crypto;
+extern crate elastic_array;
 extern crate env_logger;
 extern crate futures;
 extern crate hashdb;
 extern crate hex;
+extern crate net2;
 extern crate parking_lot;
 extern crate persistence;
 extern crate rand;
@@ -37,15 +40,44 @@ extern crate tokio;
 extern crate tokio_io_timeout;
 extern crate tokio_timer;
 
+mod address_book;
+mod ban_list;
 mod bootstrap;
+mod codec;
 mod connection;
+mod connection_manager;
+mod error;
+mod event_store;
+mod handlers;
+mod known_hashes;
+mod memory_transport;
 mod network;
 mod node_id;
 pub mod packets;
 mod peer;
+mod pinger;
+mod rate_limit;
+mod reputation;
+mod sync;
+mod transport;
+mod tx_store;
 
+pub use address_book::*;
+pub use ban_list::*;
 pub use bootstrap::*;
+pub use codec::*;
 pub use connection::*;
+pub use connection_manager::*;
+pub use error::*;
+pub use event_store::*;
+pub use known_hashes::*;
+pub use memory_transport::*;
 pub use network::*;
 pub use node_id::*;
 pub use peer::*;
+pub use pinger::*;
+pub use rate_limit::*;
+pub use reputation::*;
+pub use sync::*;
+pub use transport::*;
+pub use tx_store::*;
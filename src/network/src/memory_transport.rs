@@ -0,0 +1,596 @@
+/*
+  Copyright 2018 The Purple Library Authors
+  This file is part of the Purple Library.
+
+  The Purple Library is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  The Purple Library is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with the Purple Library. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use futures::sync::mpsc::{self, UnboundedSender};
+use futures::{future, Async, AsyncSink, Future, Poll, Sink, StartSend, Stream};
+use parking_lot::Mutex;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io;
+use tokio::timer::Delay;
+use transport::{Connecting, FrameStream, Incoming, Socket, Transport};
+
+/// The first port assigned to the outbound end of a connection
+const FIRST_EPHEMERAL_PORT: u16 = 49152;
+
+/// Faults injected in the frames sent over the memory transport
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkFaults {
+    /// The delay of each frame
+    pub latency: Duration,
+
+    /// The probability of a frame being dropped
+    pub loss: f64,
+
+    /// The probability of a frame being delivered
+    /// after the frame that follows it.
+    pub reorder: f64,
+}
+
+impl Default for LinkFaults {
+    fn default() -> LinkFaults {
+        LinkFaults {
+            latency: Duration::from_millis(0),
+            loss: 0.0,
+            reorder: 0.0,
+        }
+    }
+}
+
+struct MemoryNetState {
+    /// The seed of the random faults
+    seed: u64,
+
+    /// The faults injected in all the links
+    faults: LinkFaults,
+
+    /// The queues of accepted sockets by listening address
+    listeners: HashMap<SocketAddr, UnboundedSender<Socket>>,
+
+    /// Pairs of ip addresses that cannot reach each other
+    partitions: HashSet<(IpAddr, IpAddr)>,
+
+    /// The number of connections opened between each pair of ip addresses
+    connections: HashMap<(IpAddr, IpAddr), u64>,
+
+    /// The port of the outbound end of the next connection
+    next_port: u16,
+}
+
+impl MemoryNetState {
+    fn is_partitioned(&self, from: &IpAddr, to: &IpAddr) -> bool {
+        self.partitions.contains(&(*from, *to))
+    }
+
+    /// Returns the random number generator of the given
+    /// direction of the n-th connection between two ip
+    /// addresses, which only depends on the seed.
+    fn link_rng(&self, from: &IpAddr, to: &IpAddr, n: u64) -> StdRng {
+        let seed = format!("{}/{}/{}/{}", self.seed, from, to, n);
+        StdRng::from_seed(crypto::hash_slice(seed.as_bytes()).0)
+    }
+}
+
+/// In-process network connecting simulated nodes
+/// through memory transports.
+///
+/// The same seed and the same order of connections between
+/// each pair of nodes result in the same injected faults.
+#[derive(Clone)]
+pub struct MemoryNet {
+    state: Arc<Mutex<MemoryNetState>>,
+}
+
+impl MemoryNet {
+    pub fn new(seed: u64) -> MemoryNet {
+        MemoryNet {
+            state: Arc::new(Mutex::new(MemoryNetState {
+                seed: seed,
+                faults: LinkFaults::default(),
+                listeners: HashMap::new(),
+                partitions: HashSet::new(),
+                connections: HashMap::new(),
+                next_port: FIRST_EPHEMERAL_PORT,
+            })),
+        }
+    }
+
+    /// Returns the transport of the node with the given ip address.
+    pub fn transport(&self, ip: IpAddr) -> MemoryTransport {
+        MemoryTransport {
+            net: self.clone(),
+            ip: ip,
+        }
+    }
+
+    /// Sets the faults injected in the frames sent from now on.
+    pub fn set_faults(&self, faults: LinkFaults) {
+        self.state.lock().faults = faults;
+    }
+
+    /// Prevents the nodes of each side from reaching the nodes
+    /// of the other side until the partition is healed.
+    pub fn partition(&self, side_a: &[IpAddr], side_b: &[IpAddr]) {
+        let mut state = self.state.lock();
+
+        for a in side_a.iter() {
+            for b in side_b.iter() {
+                state.partitions.insert((*a, *b));
+                state.partitions.insert((*b, *a));
+            }
+        }
+    }
+
+    /// Removes all the partitions.
+    pub fn heal(&self) {
+        self.state.lock().partitions.clear();
+    }
+
+    /// Opens a connection from the given ip address to the
+    /// listener with the given address.
+    fn open(&self, from_ip: IpAddr, to: SocketAddr) -> io::Result<Socket> {
+        let mut state = self.state.lock();
+
+        if state.is_partitioned(&from_ip, &to.ip()) {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "partitioned"));
+        }
+
+        let listener = match state.listeners.get(&to) {
+            Some(listener) => listener.clone(),
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::ConnectionRefused,
+                    "no listener",
+                ))
+            }
+        };
+
+        let from = SocketAddr::new(from_ip, state.next_port);
+        state.next_port = state
+            .next_port
+            .checked_add(1)
+            .unwrap_or(FIRST_EPHEMERAL_PORT);
+
+        let n = {
+            let count = state.connections.entry((from_ip, to.ip())).or_insert(0);
+            *count += 1;
+            *count
+        };
+
+        let (client_sender, client_receiver) = mpsc::unbounded();
+        let (server_sender, server_receiver) = mpsc::unbounded();
+        let client_rng = state.link_rng(&from_ip, &to.ip(), n);
+        let server_rng = state.link_rng(&to.ip(), &from_ip, n);
+
+        let client = Socket {
+            addr: to,
+            reader: frame_stream(client_receiver),
+            writer: Box::new(MemorySink::new(
+                self.clone(),
+                from_ip,
+                to.ip(),
+                server_sender,
+                client_rng,
+            )),
+        };
+
+        let server = Socket {
+            addr: from,
+            reader: frame_stream(server_receiver),
+            writer: Box::new(MemorySink::new(
+                self.clone(),
+                to.ip(),
+                from_ip,
+                client_sender,
+                server_rng,
+            )),
+        };
+
+        if listener.unbounded_send(server).is_err() {
+            state.listeners.remove(&to);
+            return Err(io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                "no listener",
+            ));
+        }
+
+        Ok(client)
+    }
+}
+
+impl fmt::Debug for MemoryNet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = self.state.lock();
+
+        f.debug_struct("MemoryNet")
+            .field("seed", &state.seed)
+            .field("faults", &state.faults)
+            .field("listeners", &state.listeners.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+/// Transport of a simulated node in a `MemoryNet`
+#[derive(Debug, Clone)]
+pub struct MemoryTransport {
+    net: MemoryNet,
+    ip: IpAddr,
+}
+
+impl Transport for MemoryTransport {
+    fn listen(&self, addr: &SocketAddr) -> io::Result<Incoming> {
+        if addr.ip() != self.ip {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrNotAvailable,
+                "address of another node",
+            ));
+        }
+
+        let mut state = self.net.state.lock();
+
+        if state.listeners.contains_key(addr) {
+            return Err(io::Error::new(io::ErrorKind::AddrInUse, "address in use"));
+        }
+
+        let (sender, receiver) = mpsc::unbounded();
+        state.listeners.insert(*addr, sender);
+
+        Ok(Box::new(receiver.map_err(|_| {
+            io::Error::new(io::ErrorKind::Other, "listener closed")
+        })))
+    }
+
+    fn connect(&self, addr: &SocketAddr) -> Connecting {
+        Box::new(future::result(self.net.open(self.ip, *addr)))
+    }
+}
+
+fn frame_stream(receiver: mpsc::UnboundedReceiver<Vec<u8>>) -> FrameStream {
+    Box::new(receiver.map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "connection closed")))
+}
+
+/// Write half of a memory connection that injects the
+/// faults of the network in the sent frames.
+struct MemorySink {
+    net: MemoryNet,
+    from: IpAddr,
+    to: IpAddr,
+
+    /// The read queue of the remote end
+    remote: UnboundedSender<Vec<u8>>,
+
+    /// Queue of frames delivered after their deadline
+    delayed: Option<UnboundedSender<(Instant, Vec<u8>)>>,
+
+    /// A frame held back to be delivered after the next one
+    held: Option<Vec<u8>>,
+
+    rng: StdRng,
+}
+
+impl MemorySink {
+    fn new(
+        net: MemoryNet,
+        from: IpAddr,
+        to: IpAddr,
+        remote: UnboundedSender<Vec<u8>>,
+        rng: StdRng,
+    ) -> MemorySink {
+        MemorySink {
+            net: net,
+            from: from,
+            to: to,
+            remote: remote,
+            delayed: None,
+            held: None,
+            rng: rng,
+        }
+    }
+
+    /// Delivers a frame to the remote end after the given latency.
+    ///
+    /// Delayed frames go through a single queue so that their
+    /// order is preserved.
+    fn deliver(&mut self, frame: Vec<u8>, latency: Duration) -> io::Result<()> {
+        if latency == Duration::from_millis(0) {
+            return self
+                .remote
+                .unbounded_send(frame)
+                .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "connection closed"));
+        }
+
+        if self.delayed.is_none() {
+            let (sender, receiver) = mpsc::unbounded::<(Instant, Vec<u8>)>();
+            let remote = self.remote.clone();
+
+            tokio::spawn(receiver.for_each(move |(deadline, frame)| {
+                let remote = remote.clone();
+
+                Delay::new(deadline).then(move |_| {
+                    let _ = remote.unbounded_send(frame);
+                    Ok(())
+                })
+            }));
+
+            self.delayed = Some(sender);
+        }
+
+        if let Some(ref delayed) = self.delayed {
+            let _ = delayed.unbounded_send((Instant::now() + latency, frame));
+        }
+
+        Ok(())
+    }
+}
+
+impl Sink for MemorySink {
+    type SinkItem = Vec<u8>;
+    type SinkError = io::Error;
+
+    fn start_send(&mut self, frame: Vec<u8>) -> StartSend<Vec<u8>, io::Error> {
+        let (partitioned, faults) = {
+            let state = self.net.state.lock();
+            (
+                state.is_partitioned(&self.from, &self.to),
+                state.faults.clone(),
+            )
+        };
+
+        if partitioned {
+            return Ok(AsyncSink::Ready);
+        }
+
+        if faults.loss > 0.0 && self.rng.gen::<f64>() < faults.loss {
+            return Ok(AsyncSink::Ready);
+        }
+
+        if faults.reorder > 0.0 && self.held.is_none() && self.rng.gen::<f64>() < faults.reorder {
+            self.held = Some(frame);
+            return Ok(AsyncSink::Ready);
+        }
+
+        self.deliver(frame, faults.latency)?;
+
+        if let Some(held) = self.held.take() {
+            self.deliver(held, faults.latency)?;
+        }
+
+        Ok(AsyncSink::Ready)
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), io::Error> {
+        // A held frame is not delayed past a flush
+        if let Some(held) = self.held.take() {
+            let latency = self.net.state.lock().faults.latency;
+            self.deliver(held, latency)?;
+        }
+
+        Ok(Async::Ready(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use connection::{connect_to_peer, start_listener};
+    use crypto::Identity;
+    use futures::{stream, task};
+    use network::Network;
+    use std::sync::atomic::AtomicBool;
+    use tokio::runtime::current_thread;
+    use NodeId;
+
+    const PORT: u16 = 44034;
+
+    fn node_ip(i: usize) -> IpAddr {
+        IpAddr::V4([10, 0, (i / 250) as u8, (i % 250 + 1) as u8].into())
+    }
+
+    fn node_addr(i: usize) -> SocketAddr {
+        SocketAddr::new(node_ip(i), PORT)
+    }
+
+    fn node(net: &MemoryNet, i: usize) -> Arc<Mutex<Network>> {
+        let identity = Identity::new();
+        let mut network = Network::new(
+            NodeId(*identity.pkey()),
+            identity.skey().clone(),
+            "test".to_owned(),
+        );
+
+        network.set_transport(Arc::new(net.transport(node_ip(i))));
+
+        Arc::new(Mutex::new(network))
+    }
+
+    fn connect(nodes: &[Arc<Mutex<Network>>], from: usize, to: usize) {
+        connect_to_peer(
+            nodes[from].clone(),
+            Arc::new(AtomicBool::new(true)),
+            nodes.len(),
+            &node_addr(to),
+        );
+    }
+
+    fn handshakes(network: &Arc<Mutex<Network>>) -> usize {
+        network
+            .lock()
+            .peers()
+            .filter(|peer| peer.id.is_some())
+            .count()
+    }
+
+    /// Runs the tasks spawned on the given runtime until the
+    /// given condition holds or until the given number of
+    /// scheduler steps have run.
+    fn run_until<F: Fn() -> bool>(
+        runtime: &mut current_thread::Runtime,
+        steps: usize,
+        condition: F,
+    ) -> bool {
+        let mut remaining = steps;
+
+        runtime
+            .block_on(future::poll_fn(|| -> Poll<bool, ()> {
+                if condition() {
+                    return Ok(Async::Ready(true));
+                }
+
+                if remaining == 0 {
+                    return Ok(Async::Ready(false));
+                }
+
+                // Yield to the other tasks and poll again on the next step
+                remaining -= 1;
+                task::current().notify();
+                Ok(Async::NotReady)
+            }))
+            .unwrap()
+    }
+
+    /// Sends the given frames over a new connection and
+    /// returns the frames received by the other end.
+    fn transmit(net: &MemoryNet, frames: Vec<Vec<u8>>) -> Vec<Vec<u8>> {
+        let listener = net.transport(node_ip(0)).listen(&node_addr(0)).unwrap();
+        let client = net
+            .transport(node_ip(1))
+            .connect(&node_addr(0))
+            .wait()
+            .unwrap();
+        let (server, _) = listener.into_future().wait().ok().unwrap();
+        let server = server.unwrap();
+
+        assert_eq!(server.addr.ip(), node_ip(1));
+        assert_eq!(client.addr, node_addr(0));
+
+        let Socket { writer, .. } = client;
+        writer
+            .send_all(stream::iter_ok::<_, io::Error>(frames))
+            .wait()
+            .unwrap();
+
+        server.reader.collect().wait().unwrap()
+    }
+
+    #[test]
+    fn it_injects_faults_reproducibly() {
+        let frames: Vec<Vec<u8>> = (0..100).map(|i| vec![i]).collect();
+        let faults = LinkFaults {
+            loss: 0.2,
+            reorder: 0.2,
+            ..LinkFaults::default()
+        };
+
+        let net = MemoryNet::new(42);
+        net.set_faults(faults.clone());
+        let received = transmit(&net, frames.clone());
+
+        let net = MemoryNet::new(42);
+        net.set_faults(faults.clone());
+        assert_eq!(transmit(&net, frames.clone()), received);
+
+        let net = MemoryNet::new(43);
+        net.set_faults(faults);
+        assert_ne!(transmit(&net, frames.clone()), received);
+
+        let mut sorted = received.clone();
+        sorted.sort();
+
+        assert!(received.len() < frames.len());
+        assert_ne!(sorted, received);
+        assert_eq!(transmit(&MemoryNet::new(42), frames.clone()), frames);
+    }
+
+    #[test]
+    fn it_refuses_connections_across_partitions() {
+        let net = MemoryNet::new(42);
+        let transport = net.transport(node_ip(1));
+        let _listener = net.transport(node_ip(0)).listen(&node_addr(0)).unwrap();
+
+        assert!(transport.connect(&node_addr(2)).wait().is_err());
+        assert!(transport.listen(&node_addr(0)).is_err());
+
+        net.partition(&[node_ip(0)], &[node_ip(1)]);
+        assert!(transport.connect(&node_addr(0)).wait().is_err());
+
+        net.heal();
+        assert!(transport.connect(&node_addr(0)).wait().is_ok());
+    }
+
+    #[test]
+    fn it_learns_the_addresses_of_inbound_peers_through_a_bootnode() {
+        let (a, b, c) = (0, 1, 2);
+        let net = MemoryNet::new(42);
+        let nodes: Vec<Arc<Mutex<Network>>> = (0..3).map(|i| node(&net, i)).collect();
+        let mut runtime = current_thread::Runtime::new().unwrap();
+
+        let nodes_clone = nodes.clone();
+        runtime
+            .block_on(future::lazy(move || -> Result<(), ()> {
+                let nodes = nodes_clone;
+
+                for (i, network) in nodes.iter().enumerate() {
+                    let accept_connections = Arc::new(AtomicBool::new(true));
+                    start_listener(network.clone(), accept_connections, 3, &node_addr(i));
+                }
+
+                connect(&nodes, a, b);
+                Ok(())
+            }))
+            .unwrap();
+
+        assert!(run_until(&mut runtime, 1000, || handshakes(&nodes[b]) == 1));
+
+        // The bootnode records the port advertised by A rather
+        // than the ephemeral port A has connected from.
+        assert_eq!(
+            nodes[b].lock().address_book().best(10, &[]),
+            vec![node_addr(a)]
+        );
+
+        let nodes_clone = nodes.clone();
+        runtime
+            .block_on(future::lazy(move || -> Result<(), ()> {
+                connect(&nodes_clone, c, b);
+                Ok(())
+            }))
+            .unwrap();
+
+        assert!(run_until(&mut runtime, 1000, || nodes[c]
+            .lock()
+            .address_book()
+            .get(&node_addr(a))
+            .is_some()));
+
+        // C can now dial A directly
+        let nodes_clone = nodes.clone();
+        runtime
+            .block_on(future::lazy(move || -> Result<(), ()> {
+                connect(&nodes_clone, c, a);
+                Ok(())
+            }))
+            .unwrap();
+
+        assert!(run_until(&mut runtime, 1000, || handshakes(&nodes[a]) == 2));
+    }
+}
@@ -16,33 +16,534 @@
   along with the Purple Library. If not, see <http://www.gnu.org/licenses/>.
 */
 
-use std::net::SocketAddr;
+use address_book::{unix_timestamp, AddressBook};
+use ban_list::BanList;
+use connection::ConnectionType;
+use crypto::{Hash, SecretKey as Sk};
+use error::NetworkErr;
+use event_store::EventStore;
+use known_hashes::KnownHashes;
+use packets::{AnnounceEvents, AnnounceTxs, Ping};
+use rand::Rng;
+use rate_limit::RateLimits;
+use reputation::{Misbehavior, Reputation, BAN_DURATION, BAN_THRESHOLD};
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use transport::{TcpTransport, Transport};
+use tx_store::TxStore;
 use NodeId;
 use Peer;
 
-#[derive(Debug, Clone)]
+/// The version of the protocol spoken by this node
+pub const PROTOCOL_VERSION: u16 = 1;
+
+/// The lowest protocol version of a peer that we can talk to
+pub const MIN_PROTOCOL_VERSION: u16 = 1;
+
+/// Time in milliseconds after which a requested
+/// event is requested again from another peer.
+pub const EVENT_REQUEST_TIMEOUT: u64 = 10000;
+
+/// The maximum number of peers an event is
+/// requested from before giving up.
+pub const MAX_EVENT_REQUEST_ATTEMPTS: usize = 3;
+
+/// Time in milliseconds after which a requested
+/// transaction can be requested again.
+pub const TX_REQUEST_TIMEOUT: u64 = 10000;
+
+/// The number of consecutive unanswered pings
+/// after which a peer is considered stale.
+pub const MAX_MISSED_PONGS: u32 = 3;
+
+/// The node relays events
+pub const CAPABILITY_EVENTS: u32 = 1;
+
+/// The node relays transactions
+pub const CAPABILITY_TRANSACTIONS: u32 = 1 << 1;
+
+/// The capabilities announced by this node
+pub const NODE_CAPABILITIES: u32 = CAPABILITY_EVENTS | CAPABILITY_TRANSACTIONS;
+
+#[derive(Debug)]
 pub struct Network {
-    /// List of peers we are connected to
-    peers: Vec<Peer>,
+    /// Mapping between the addresses and the peers we are connected to
+    peers: HashMap<SocketAddr, Peer>,
+
+    /// Mapping between the node ids of the peers that have
+    /// completed the handshake and their addresses
+    node_ids: HashMap<NodeId, SocketAddr>,
 
     /// Our node id
     node_id: NodeId,
 
+    /// Our secret key
+    secret_key: Sk,
+
     /// The name of the network we are on
     network_name: String,
+
+    /// The id of the network we are on
+    network_id: Hash,
+
+    /// The port on which we accept connections
+    ///
+    /// Equal to 0 if we are not listening.
+    listen_port: u16,
+
+    /// Book of known peer addresses
+    address_book: AddressBook,
+
+    /// Penalty points of the peers
+    reputation: Reputation,
+
+    /// Banned node ids and ip addresses
+    ban_list: BanList,
+
+    /// The limits applied to the traffic of each peer
+    rate_limits: RateLimits,
+
+    /// The transport over which peers are connected
+    transport: Arc<dyn Transport>,
+
+    /// Storage of the gossiped events
+    event_store: Option<Arc<dyn EventStore>>,
+
+    /// Hashes of the events that we have requested
+    requested_events: HashMap<Hash, EventRequest>,
+
+    /// Storage of the relayed transactions
+    tx_store: Option<Arc<dyn TxStore>>,
+
+    /// Hashes of the transactions that we have requested
+    /// and the time at which they were requested.
+    requested_txs: HashMap<Hash, Instant>,
 }
 
 impl Network {
-    pub fn new(node_id: NodeId, network_name: String) -> Network {
+    pub fn new(node_id: NodeId, secret_key: Sk, network_name: String) -> Network {
         Network {
-            peers: Vec::new(),
+            peers: HashMap::new(),
+            node_ids: HashMap::new(),
             node_id: node_id,
+            secret_key: secret_key,
+            network_id: network_id(&network_name),
             network_name: network_name,
+            listen_port: 0,
+            address_book: AddressBook::new(),
+            reputation: Reputation::new(),
+            ban_list: BanList::new(),
+            rate_limits: RateLimits::default(),
+            transport: Arc::new(TcpTransport),
+            event_store: None,
+            requested_events: HashMap::new(),
+            tx_store: None,
+            requested_txs: HashMap::new(),
         }
     }
 
-    pub fn add_peer(&mut self, peer: Peer) {
-        self.peers.push(peer);
+    /// Adds a new peer to the peer table.
+    ///
+    /// Returns an error if there is already a
+    /// listed peer with the same address.
+    pub fn add_peer(&mut self, peer: Peer) -> Result<(), NetworkErr> {
+        if self.peers.contains_key(&peer.ip) {
+            return Err(NetworkErr::PeerAlreadyListed);
+        }
+
+        if let Some(ref id) = peer.id {
+            if self.node_ids.contains_key(id) {
+                return Err(NetworkErr::DuplicateNodeId);
+            }
+
+            self.node_ids.insert(id.clone(), peer.ip);
+        }
+
+        self.peers.insert(peer.ip, peer);
+        Ok(())
+    }
+
+    /// Returns our node id.
+    pub fn node_id(&self) -> NodeId {
+        self.node_id.clone()
+    }
+
+    /// Returns our secret key.
+    pub fn secret_key(&self) -> &Sk {
+        &self.secret_key
+    }
+
+    /// Returns the name of the network we are on.
+    pub fn network_name(&self) -> &str {
+        &self.network_name
+    }
+
+    /// Returns the id of the network we are on.
+    pub fn network_id(&self) -> &Hash {
+        &self.network_id
+    }
+
+    /// Returns the port on which we accept connections.
+    pub fn listen_port(&self) -> u16 {
+        self.listen_port
+    }
+
+    /// Sets the port on which we accept connections, which
+    /// is advertised to peers in our connect packet.
+    pub fn set_listen_port(&mut self, port: u16) {
+        self.listen_port = port;
+    }
+
+    /// Returns the book of known peer addresses.
+    pub fn address_book(&self) -> &AddressBook {
+        &self.address_book
+    }
+
+    /// Returns a mutable reference to the book of known peer addresses.
+    pub fn address_book_mut(&mut self) -> &mut AddressBook {
+        &mut self.address_book
+    }
+
+    /// Returns the transport over which peers are connected.
+    pub fn transport(&self) -> Arc<dyn Transport> {
+        self.transport.clone()
+    }
+
+    /// Sets the transport over which peers are connected
+    /// from now on. Defaults to TCP.
+    pub fn set_transport(&mut self, transport: Arc<dyn Transport>) {
+        self.transport = transport;
+    }
+
+    /// Returns the limits applied to the traffic of each peer.
+    pub fn rate_limits(&self) -> &RateLimits {
+        &self.rate_limits
+    }
+
+    /// Sets the limits applied to the traffic of the peers
+    /// that connect from now on.
+    pub fn set_rate_limits(&mut self, rate_limits: RateLimits) {
+        self.rate_limits = rate_limits;
+    }
+
+    /// Returns the list of bans.
+    pub fn ban_list(&self) -> &BanList {
+        &self.ban_list
+    }
+
+    /// Replaces the list of bans, e.g. with the persisted one.
+    pub fn set_ban_list(&mut self, ban_list: BanList) {
+        self.ban_list = ban_list;
+    }
+
+    /// Returns `true` if the given ip address is currently banned.
+    pub fn is_ip_banned(&self, ip: &IpAddr) -> bool {
+        self.ban_list.is_ip_banned(ip, unix_timestamp())
+    }
+
+    /// Returns `true` if the given node id is currently banned.
+    pub fn is_node_id_banned(&self, node_id: &NodeId) -> bool {
+        self.ban_list.is_node_id_banned(node_id, unix_timestamp())
+    }
+
+    /// Bans the given ip address for the given number of
+    /// seconds and drops the peers connected from it.
+    pub fn ban_ip(&mut self, ip: IpAddr, duration: u64) {
+        self.ban_list
+            .ban_ip(ip, unix_timestamp().saturating_add(duration));
+
+        let addrs: Vec<SocketAddr> = self
+            .peers
+            .keys()
+            .filter(|addr| addr.ip() == ip)
+            .cloned()
+            .collect();
+
+        for addr in addrs.iter() {
+            self.remove_peer_with_addr(addr);
+        }
+    }
+
+    /// Bans the given node id for the given number of
+    /// seconds and drops the peer with that id.
+    pub fn ban_node_id(&mut self, node_id: NodeId, duration: u64) {
+        if let Some(addr) = self.addr_of(&node_id) {
+            self.remove_peer_with_addr(&addr);
+        }
+
+        self.ban_list
+            .ban_node_id(node_id, unix_timestamp().saturating_add(duration));
+    }
+
+    /// Lifts the ban of the given ip address and resets its
+    /// penalty points. Returns `false` if it was not banned.
+    pub fn unban_ip(&mut self, ip: &IpAddr) -> bool {
+        self.reputation.clear(ip);
+        self.ban_list.unban_ip(ip)
+    }
+
+    /// Lifts the ban of the given node id. Returns
+    /// `false` if it was not banned.
+    pub fn unban_node_id(&mut self, node_id: &NodeId) -> bool {
+        self.ban_list.unban_node_id(node_id)
+    }
+
+    /// Adds the penalty of the given misbehavior to the peer with
+    /// the given address. Peers that cross the ban threshold are
+    /// banned by ip address and node id and dropped.
+    ///
+    /// Returns `true` if the peer has been banned.
+    pub fn penalize(&mut self, addr: &SocketAddr, misbehavior: Misbehavior) -> bool {
+        let points = self
+            .reputation
+            .penalize(addr.ip(), misbehavior, Instant::now());
+
+        debug!(
+            "Penalized {} for {:?}, {} penalty points",
+            addr, misbehavior, points
+        );
+
+        if points < BAN_THRESHOLD {
+            return false;
+        }
+
+        info!("Banning {} for misbehavior", addr);
+
+        let node_id = self.peers.get(addr).and_then(|peer| peer.id.clone());
+
+        if let Some(node_id) = node_id {
+            self.ban_node_id(node_id, BAN_DURATION);
+        }
+
+        self.ban_ip(addr.ip(), BAN_DURATION);
+        self.reputation.clear(&addr.ip());
+        self.ban_list.remove_expired(unix_timestamp());
+
+        true
+    }
+
+    /// Sets the storage of the gossiped events.
+    ///
+    /// Event packets are ignored until this is set.
+    pub fn set_event_store(&mut self, event_store: Box<dyn EventStore>) {
+        self.event_store = Some(Arc::from(event_store));
+    }
+
+    /// Returns the storage of the gossiped events.
+    pub fn event_store(&self) -> Option<Arc<dyn EventStore>> {
+        self.event_store.clone()
+    }
+
+    /// Returns the hashes out of the given ones that belong to
+    /// events which are neither stored nor already requested
+    /// and records them as requested from the peer with the
+    /// given address at the given time.
+    pub fn request_events(
+        &mut self,
+        addr: &SocketAddr,
+        hashes: &[Hash],
+        now: Instant,
+    ) -> Vec<Hash> {
+        let event_store = match self.event_store {
+            Some(ref event_store) => event_store,
+            None => return Vec::new(),
+        };
+
+        let mut missing = Vec::new();
+
+        for hash in hashes.iter() {
+            if event_store.contains(hash) || self.requested_events.contains_key(hash) {
+                continue;
+            }
+
+            self.requested_events.insert(
+                *hash,
+                EventRequest {
+                    requested_at: now,
+                    tried: vec![*addr],
+                },
+            );
+            missing.push(*hash);
+        }
+
+        missing
+    }
+
+    /// Returns the events that should be requested at the given
+    /// time, grouped by the address of the peer to request them
+    /// from, and records the requests.
+    ///
+    /// Requests that have timed out are retried against other
+    /// peers and the events that the event store is waiting on
+    /// are requested if they are not already.
+    pub fn sync_events(&mut self, now: Instant) -> HashMap<SocketAddr, Vec<Hash>> {
+        let timeout = Duration::from_millis(EVENT_REQUEST_TIMEOUT);
+        let mut requests: HashMap<SocketAddr, Vec<Hash>> = HashMap::new();
+        let event_store = match self.event_store {
+            Some(ref event_store) => event_store,
+            None => return requests,
+        };
+
+        let mut expired = Vec::new();
+
+        for (hash, request) in self.requested_events.iter_mut() {
+            if event_store.contains(hash) {
+                expired.push(*hash);
+                continue;
+            }
+
+            if now < request.requested_at + timeout {
+                continue;
+            }
+
+            if request.tried.len() >= MAX_EVENT_REQUEST_ATTEMPTS {
+                expired.push(*hash);
+                continue;
+            }
+
+            match pick_event_peer(&self.peers, hash, &request.tried) {
+                Some(addr) => {
+                    request.tried.push(addr);
+                    request.requested_at = now;
+                    requests.entry(addr).or_insert_with(Vec::new).push(*hash);
+                }
+                None => expired.push(*hash),
+            }
+        }
+
+        for hash in expired.iter() {
+            self.requested_events.remove(hash);
+        }
+
+        for hash in event_store.missing() {
+            if self.requested_events.contains_key(&hash) {
+                continue;
+            }
+
+            if let Some(addr) = pick_event_peer(&self.peers, &hash, &[]) {
+                self.requested_events.insert(
+                    hash,
+                    EventRequest {
+                        requested_at: now,
+                        tried: vec![addr],
+                    },
+                );
+                requests.entry(addr).or_insert_with(Vec::new).push(hash);
+            }
+        }
+
+        requests
+    }
+
+    /// Marks the event with the given hash as received.
+    pub fn event_received(&mut self, hash: &Hash) {
+        self.requested_events.remove(hash);
+    }
+
+    /// Announces the event with the given hash to all the peers
+    /// that have completed the handshake and that do not already
+    /// know about it, except for the given address.
+    pub fn announce_event(&mut self, hash: Hash, except: Option<&SocketAddr>) {
+        let packet = AnnounceEvents::new(vec![hash]).to_bytes();
+        self.announce(packet, hash, except, |peer| &mut peer.known_events);
+    }
+
+    /// Sets the storage of the relayed transactions.
+    ///
+    /// Transaction packets are ignored until this is set.
+    pub fn set_tx_store(&mut self, tx_store: Box<dyn TxStore>) {
+        self.tx_store = Some(Arc::from(tx_store));
+    }
+
+    /// Returns the storage of the relayed transactions.
+    pub fn tx_store(&self) -> Option<Arc<dyn TxStore>> {
+        self.tx_store.clone()
+    }
+
+    /// Returns the hashes out of the given ones that belong to
+    /// transactions which are neither stored nor already requested
+    /// and records them as requested at the given time.
+    pub fn request_txs(&mut self, hashes: &[Hash], now: Instant) -> Vec<Hash> {
+        let tx_store = match self.tx_store {
+            Some(ref tx_store) => tx_store,
+            None => return Vec::new(),
+        };
+
+        request_missing(
+            &mut self.requested_txs,
+            |hash| tx_store.contains(hash),
+            hashes,
+            now,
+            Duration::from_millis(TX_REQUEST_TIMEOUT),
+        )
+    }
+
+    /// Marks the transaction with the given hash as received.
+    pub fn tx_received(&mut self, hash: &Hash) {
+        self.requested_txs.remove(hash);
+    }
+
+    /// Announces the transaction with the given hash to all the
+    /// peers that have completed the handshake and that do not
+    /// already know about it, except for the given address.
+    pub fn announce_tx(&mut self, hash: Hash, except: Option<&SocketAddr>) {
+        let packet = AnnounceTxs::new(vec![hash]).to_bytes();
+        self.announce(packet, hash, except, |peer| &mut peer.known_txs);
+    }
+
+    /// Sends an announce packet for the given hash to the peers
+    /// whose known hashes, as selected by the given function,
+    /// do not contain it.
+    fn announce(
+        &mut self,
+        packet: Vec<u8>,
+        hash: Hash,
+        except: Option<&SocketAddr>,
+        known: fn(&mut Peer) -> &mut KnownHashes,
+    ) {
+        for (addr, peer) in self.peers.iter_mut() {
+            if Some(addr) == except || !peer.has_session_keys() {
+                continue;
+            }
+
+            if !known(peer).insert(hash) {
+                continue;
+            }
+
+            if let Err(err) = peer.send_packet(packet.clone()) {
+                debug!("Could not send announcement to {}: {}", addr, err);
+            }
+        }
+    }
+
+    /// Sends a ping with a random nonce to each peer that
+    /// has completed the handshake and returns the addresses
+    /// of the stale peers, which are not pinged anymore.
+    pub fn ping_peers(&mut self, now: Instant) -> Vec<SocketAddr> {
+        let mut rng = rand::thread_rng();
+        let mut stale = Vec::new();
+
+        for (addr, peer) in self.peers.iter_mut() {
+            if !peer.has_session_keys() {
+                continue;
+            }
+
+            if peer.missed_pongs() >= MAX_MISSED_PONGS {
+                stale.push(*addr);
+                continue;
+            }
+
+            let nonce: u64 = rng.gen();
+            peer.ping_sent(nonce, now);
+
+            if let Err(err) = peer.send_packet(Ping::new(nonce).to_bytes()) {
+                debug!("Could not send ping to {}: {}", addr, err);
+            }
+        }
+
+        stale
     }
 
     /// Returns the number of listed peers.
@@ -50,33 +551,459 @@ impl Network {
         self.peers.len()
     }
 
+    /// Returns an iterator over the listed peers.
+    pub fn peers(&self) -> impl Iterator<Item = &Peer> {
+        self.peers.values()
+    }
+
+    /// Returns the number of peers with the given connection type.
+    pub fn count_by_type(&self, connection_type: ConnectionType) -> usize {
+        self.peers
+            .values()
+            .filter(|x| x.connection_type == connection_type)
+            .count()
+    }
+
+    /// Returns the addresses of the peers with the given connection type.
+    pub fn addrs_by_type(&self, connection_type: ConnectionType) -> Vec<SocketAddr> {
+        self.peers
+            .values()
+            .filter(|x| x.connection_type == connection_type)
+            .map(|x| x.ip)
+            .collect()
+    }
+
+    /// Returns the peer with the given address.
+    pub fn peer(&self, addr: &SocketAddr) -> Option<&Peer> {
+        self.peers.get(addr)
+    }
+
+    /// Returns a mutable reference to the peer with the given address.
+    pub fn peer_mut(&mut self, addr: &SocketAddr) -> Option<&mut Peer> {
+        self.peers.get_mut(addr)
+    }
+
+    /// Returns the peer with the given node id.
+    pub fn peer_by_id(&self, node_id: &NodeId) -> Option<&Peer> {
+        match self.node_ids.get(node_id) {
+            Some(addr) => self.peers.get(addr),
+            None => None,
+        }
+    }
+
+    /// Returns the address of the peer with the given node id.
+    pub fn addr_of(&self, node_id: &NodeId) -> Option<SocketAddr> {
+        self.node_ids.get(node_id).cloned()
+    }
+
     /// Sets the node id of the peer with the given address.
     ///
-    /// This function will panic if there is no entry for the given address.
-    pub fn set_node_id(&mut self, addr: &SocketAddr, node_id: NodeId) {
-        match self.peers.iter().position(|x| x.ip == *addr) {
-            Some(idx) => self.peers[idx].set_id(node_id),
-            None => panic!("There is no listed peer with the given address!"),
+    /// Returns an error if there is no entry for the given address
+    /// or if we are already connected to a peer with the same id.
+    pub fn set_node_id(&mut self, addr: &SocketAddr, node_id: NodeId) -> Result<(), NetworkErr> {
+        if let Some(other) = self.node_ids.get(&node_id) {
+            if other != addr {
+                return Err(NetworkErr::DuplicateNodeId);
+            }
+        }
+
+        let peer = match self.peers.get_mut(addr) {
+            Some(peer) => peer,
+            None => return Err(NetworkErr::NoPeerWithAddress),
         };
+
+        // Remove the index of the previous id
+        if let Some(ref old_id) = peer.id {
+            self.node_ids.remove(old_id);
+        }
+
+        self.node_ids.insert(node_id.clone(), *addr);
+        peer.set_id(node_id);
+
+        Ok(())
     }
 
-    /// Removes the peer entry with the given address.
-    ///
-    /// This function will panic if there is no entry for the given address.
-    pub fn remove_peer_with_addr(&mut self, addr: &SocketAddr) {
-        match self.peers.iter().position(|x| x.ip == *addr) {
-            Some(idx) => self.peers.remove(idx),
-            None => panic!("There is no listed peer with the given address!"),
-        };
+    /// Removes the peer entry with the given address and returns it.
+    pub fn remove_peer_with_addr(&mut self, addr: &SocketAddr) -> Option<Peer> {
+        let peer = self.peers.remove(addr)?;
+
+        if let Some(ref id) = peer.id {
+            self.node_ids.remove(id);
+        }
+
+        Some(peer)
+    }
+
+    /// Queues a serialized packet to be sent to the peer with the given address.
+    pub fn send_to_peer(&mut self, addr: &SocketAddr, packet: Vec<u8>) -> Result<(), &'static str> {
+        match self.peers.get_mut(addr) {
+            Some(peer) => peer.send_packet(packet),
+            None => Err("There is no listed peer with the given address"),
+        }
     }
 
     /// Returns true if the peer with the given address has a `None` id field.
     ///
-    /// This function will panic if there is no entry for the given address.
-    pub fn is_none_id(&self, addr: &SocketAddr) -> bool {
-        match self.peers.iter().position(|x| x.ip == *addr) {
-            Some(idx) => self.peers[idx].id.is_none(),
-            None => panic!("There is no listed peer with the given address!"),
+    /// Returns an error if there is no entry for the given address.
+    pub fn is_none_id(&self, addr: &SocketAddr) -> Result<bool, NetworkErr> {
+        match self.peers.get(addr) {
+            Some(peer) => Ok(peer.id.is_none()),
+            None => Err(NetworkErr::NoPeerWithAddress),
+        }
+    }
+
+    /// Records the number of bytes received from
+    /// the peer with the given address.
+    pub fn record_bytes_in(&mut self, addr: &SocketAddr, bytes: u64) {
+        if let Some(peer) = self.peers.get_mut(addr) {
+            peer.bytes_in += bytes;
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct EventRequest {
+    /// The time of the last request
+    requested_at: Instant,
+
+    /// The addresses of the peers that the event has been
+    /// requested from. The last one is the current one.
+    tried: Vec<SocketAddr>,
+}
+
+/// Returns the address of a peer that has completed the handshake
+/// and that is not in the tried ones to request the event with the
+/// given hash from. Peers that have announced the event are preferred,
+/// then peers with a lower measured latency.
+fn pick_event_peer(
+    peers: &HashMap<SocketAddr, Peer>,
+    hash: &Hash,
+    tried: &[SocketAddr],
+) -> Option<SocketAddr> {
+    let mut candidates: Vec<&Peer> = peers
+        .values()
+        .filter(|peer| peer.has_session_keys() && !tried.contains(&peer.ip))
+        .collect();
+
+    // Peers without a measured latency come last
+    candidates.sort_by_key(|peer| (peer.latency.is_none(), peer.latency));
+
+    candidates
+        .iter()
+        .find(|peer| peer.known_events.contains(hash))
+        .or_else(|| candidates.first())
+        .map(|peer| peer.ip)
+}
+
+/// Filters out the given hashes that are either stored or that
+/// have been requested less than `timeout` ago and records the
+/// remaining ones as requested at the given time.
+fn request_missing<F>(
+    requested: &mut HashMap<Hash, Instant>,
+    is_stored: F,
+    hashes: &[Hash],
+    now: Instant,
+    timeout: Duration,
+) -> Vec<Hash>
+where
+    F: Fn(&Hash) -> bool,
+{
+    requested.retain(|_, requested_at| now < *requested_at + timeout);
+
+    let mut missing = Vec::new();
+
+    for hash in hashes.iter() {
+        if is_stored(hash) || requested.contains_key(hash) {
+            continue;
+        }
+
+        requested.insert(*hash, now);
+        missing.push(*hash);
+    }
+
+    missing
+}
+
+/// Returns the id of the network with the given name.
+///
+/// All networks currently start from the same genesis
+/// so the id is derived from the network name instead
+/// of the genesis hash.
+pub fn network_id(network_name: &str) -> Hash {
+    crypto::hash_slice(network_name.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crypto::Identity;
+    use event_store::EventStoreErr;
+    use futures::sync::mpsc;
+
+    fn network() -> Network {
+        let identity = Identity::new();
+        Network::new(
+            NodeId(*identity.pkey()),
+            identity.skey().clone(),
+            "test".to_owned(),
+        )
+    }
+
+    fn peer(addr: &str) -> Peer {
+        let (sender, _receiver) = mpsc::channel(8);
+        Peer::new(None, addr.parse().unwrap(), ConnectionType::Client, sender)
+    }
+
+    #[test]
+    fn it_indexes_peers_by_address_and_id() {
+        let mut network = network();
+        let addr: SocketAddr = "127.0.0.1:44034".parse().unwrap();
+        let id = NodeId(*Identity::new().pkey());
+
+        network.add_peer(peer("127.0.0.1:44034")).unwrap();
+        assert_eq!(network.is_none_id(&addr), Ok(true));
+
+        network.set_node_id(&addr, id.clone()).unwrap();
+        assert_eq!(network.is_none_id(&addr), Ok(false));
+        assert_eq!(network.addr_of(&id), Some(addr));
+        assert_eq!(network.peer_by_id(&id).unwrap().ip, addr);
+
+        assert!(network.remove_peer_with_addr(&addr).is_some());
+        assert!(network.peer_by_id(&id).is_none());
+        assert_eq!(network.peer_count(), 0);
+    }
+
+    #[test]
+    fn it_rejects_duplicate_peers() {
+        let mut network = network();
+        let addr1: SocketAddr = "127.0.0.1:44034".parse().unwrap();
+        let addr2: SocketAddr = "127.0.0.2:44034".parse().unwrap();
+        let id = NodeId(*Identity::new().pkey());
+
+        network.add_peer(peer("127.0.0.1:44034")).unwrap();
+        network.add_peer(peer("127.0.0.2:44034")).unwrap();
+
+        assert_eq!(
+            network.add_peer(peer("127.0.0.1:44034")),
+            Err(NetworkErr::PeerAlreadyListed)
+        );
+
+        network.set_node_id(&addr1, id.clone()).unwrap();
+
+        assert_eq!(
+            network.set_node_id(&addr2, id.clone()),
+            Err(NetworkErr::DuplicateNodeId)
+        );
+        assert_eq!(network.addr_of(&id), Some(addr1));
+    }
+
+    #[test]
+    fn it_does_not_panic_on_missing_peers() {
+        let mut network = network();
+        let addr: SocketAddr = "127.0.0.1:44034".parse().unwrap();
+        let id = NodeId(*Identity::new().pkey());
+
+        assert_eq!(
+            network.is_none_id(&addr),
+            Err(NetworkErr::NoPeerWithAddress)
+        );
+        assert_eq!(
+            network.set_node_id(&addr, id),
+            Err(NetworkErr::NoPeerWithAddress)
+        );
+        assert!(network.remove_peer_with_addr(&addr).is_none());
+        assert!(network.send_to_peer(&addr, vec![1]).is_err());
+    }
+
+    #[test]
+    fn network_id_depends_on_name() {
+        assert_eq!(network_id("purple"), network_id("purple"));
+        assert_ne!(network_id("purple"), network_id("test"));
+        assert_eq!(network().network_id(), &network_id("test"));
+    }
+
+    fn connected_peer(network: &mut Network, addr: &SocketAddr) -> mpsc::Receiver<Vec<u8>> {
+        let (sender, receiver) = mpsc::channel(8);
+        let mut local = Peer::new(None, *addr, ConnectionType::Client, sender);
+        let remote = peer(&addr.to_string());
+
+        local
+            .derive_session_keys(remote.kx_public_key(), &ConnectionType::Client)
+            .unwrap();
+        network.add_peer(local).unwrap();
+
+        receiver
+    }
+
+    #[derive(Debug)]
+    struct TestStore {
+        stored: Vec<Hash>,
+        missing: Vec<Hash>,
+    }
+
+    impl EventStore for TestStore {
+        fn contains(&self, hash: &Hash) -> bool {
+            self.stored.contains(hash)
+        }
+
+        fn get(&self, _hash: &Hash) -> Option<Vec<u8>> {
+            None
+        }
+
+        fn push(&self, _event: &[u8]) -> Result<Hash, EventStoreErr> {
+            Err(EventStoreErr::InvalidEvent("Not implemented"))
+        }
+
+        fn highest(&self) -> Hash {
+            self.stored[0]
+        }
+
+        fn missing(&self) -> Vec<Hash> {
+            self.missing.clone()
+        }
+
+        fn following(&self, _hash: &Hash, _count: usize) -> Vec<Vec<u8>> {
+            Vec::new()
         }
     }
+
+    #[test]
+    fn it_retries_event_requests_against_other_peers() {
+        let mut network = network();
+        let a1: SocketAddr = "127.0.0.1:44034".parse().unwrap();
+        let a2: SocketAddr = "127.0.0.2:44034".parse().unwrap();
+        let _r1 = connected_peer(&mut network, &a1);
+        let _r2 = connected_peer(&mut network, &a2);
+        let stored = Hash::random();
+        let announced = Hash::random();
+        let parent = Hash::random();
+        let timeout = Duration::from_millis(EVENT_REQUEST_TIMEOUT);
+        let now = Instant::now();
+
+        assert!(network.request_events(&a1, &[announced], now).is_empty());
+
+        network.set_event_store(Box::new(TestStore {
+            stored: vec![stored],
+            missing: vec![parent],
+        }));
+
+        assert_eq!(
+            network.request_events(&a1, &[stored, announced], now),
+            vec![announced]
+        );
+        assert!(network.request_events(&a2, &[announced], now).is_empty());
+
+        // The parent that the store is waiting on is requested once
+        let requests = network.sync_events(now);
+        assert_eq!(
+            requests.values().flatten().collect::<Vec<_>>(),
+            vec![&parent]
+        );
+        assert!(network.sync_events(now).is_empty());
+
+        // The requests have timed out and are sent to the other peer
+        let now = now + timeout;
+        let requests = network.sync_events(now);
+        assert!(requests[&a2].contains(&announced));
+        assert_eq!(requests.values().flatten().count(), 2);
+
+        // All the peers have been tried. Only the parent
+        // that the store is waiting on is requested again.
+        let now = now + timeout;
+        let requests = network.sync_events(now);
+        assert_eq!(
+            requests.values().flatten().collect::<Vec<_>>(),
+            vec![&parent]
+        );
+        assert_eq!(
+            network.request_events(&a1, &[announced], now),
+            vec![announced]
+        );
+
+        network.event_received(&announced);
+        assert_eq!(
+            network.request_events(&a2, &[announced], now),
+            vec![announced]
+        );
+    }
+
+    #[test]
+    fn it_announces_txs_once_per_peer() {
+        let mut network = network();
+        let a1: SocketAddr = "127.0.0.1:44034".parse().unwrap();
+        let a2: SocketAddr = "127.0.0.2:44034".parse().unwrap();
+        let a3: SocketAddr = "127.0.0.3:44034".parse().unwrap();
+        let hash = Hash::random();
+        let _receivers: Vec<_> = [a1, a2, a3]
+            .iter()
+            .map(|addr| connected_peer(&mut network, addr))
+            .collect();
+
+        // The third peer already knows about the transaction
+        network.peer_mut(&a3).unwrap().known_txs.insert(hash);
+
+        network.announce_tx(hash, Some(&a1));
+        network.announce_tx(hash, None);
+
+        let bytes_out = |addr: &SocketAddr| network.peer(addr).unwrap().bytes_out;
+
+        assert!(bytes_out(&a2) > 0);
+        assert_eq!(bytes_out(&a1), bytes_out(&a2));
+        assert_eq!(bytes_out(&a3), 0);
+    }
+
+    #[test]
+    fn it_reports_peers_that_miss_pongs_as_stale() {
+        let mut network = network();
+        let a1: SocketAddr = "127.0.0.1:44034".parse().unwrap();
+        let a2: SocketAddr = "127.0.0.2:44034".parse().unwrap();
+        let _r1 = connected_peer(&mut network, &a1);
+        let _r2 = connected_peer(&mut network, &a2);
+        let now = Instant::now();
+
+        for _ in 0..=MAX_MISSED_PONGS {
+            assert!(network.ping_peers(now).is_empty());
+
+            // The first peer keeps answering
+            let peer = network.peer_mut(&a1).unwrap();
+            peer.ping_sent(0, now);
+            assert!(peer.pong_received(0, now));
+        }
+
+        assert_eq!(network.ping_peers(now), vec![a2]);
+        assert!(network.peer(&a1).unwrap().latency.is_some());
+    }
+
+    #[test]
+    fn it_bans_misbehaving_peers() {
+        let mut network = network();
+        let a1: SocketAddr = "127.0.0.1:44034".parse().unwrap();
+        let a2: SocketAddr = "127.0.0.1:44035".parse().unwrap();
+        let a3: SocketAddr = "127.0.0.2:44034".parse().unwrap();
+        let id = NodeId(*Identity::new().pkey());
+        let _receivers: Vec<_> = [a1, a2, a3]
+            .iter()
+            .map(|addr| connected_peer(&mut network, addr))
+            .collect();
+
+        network.set_node_id(&a1, id.clone()).unwrap();
+
+        assert!(!network.penalize(&a1, Misbehavior::BadSignature));
+        assert!(network.penalize(&a2, Misbehavior::InvalidEvent));
+
+        // All the peers on the same ip address are dropped
+        // while only the node id of the banned peer is banned.
+        assert!(network.is_ip_banned(&a1.ip()));
+        assert!(!network.is_node_id_banned(&id));
+        assert!(!network.is_ip_banned(&a3.ip()));
+        assert_eq!(network.peer_count(), 1);
+
+        network.ban_node_id(id.clone(), BAN_DURATION);
+        assert!(network.is_node_id_banned(&id));
+        assert!(network.unban_node_id(&id));
+
+        assert!(network.unban_ip(&a1.ip()));
+        assert!(!network.is_ip_banned(&a1.ip()));
+        assert!(!network.penalize(&a1, Misbehavior::BadSignature));
+    }
 }
@@ -0,0 +1,99 @@
+/*
+  Copyright 2018 The Purple Library Authors
+  This file is part of the Purple Library.
+
+  The Purple Library is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  The Purple Library is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with the Purple Library. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use byteorder::{ReadBytesExt, WriteBytesExt};
+use crypto::Hash;
+use packets::hashes::{read_hashes, write_hashes};
+use std::io::Cursor;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnnounceEvents {
+    /// Hashes of events known by the sender
+    pub hashes: Vec<Hash>,
+}
+
+impl AnnounceEvents {
+    pub const PACKET_TYPE: u8 = 4;
+
+    pub fn new(hashes: Vec<Hash>) -> AnnounceEvents {
+        AnnounceEvents { hashes: hashes }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buffer: Vec<u8> = Vec::with_capacity(3 + 32 * self.hashes.len());
+        let packet_type: u8 = Self::PACKET_TYPE;
+
+        // Announce events packet structure:
+        // 1) Packet type(4)   - 8bits
+        // 2) Hashes count     - 16bits
+        // 3) Hashes           - Binary of hashes count * 32
+        buffer.write_u8(packet_type).unwrap();
+        write_hashes(&mut buffer, &self.hashes);
+
+        buffer
+    }
+
+    pub fn from_bytes(bin: &[u8]) -> Result<AnnounceEvents, &'static str> {
+        let mut rdr = Cursor::new(bin.to_vec());
+        let packet_type = if let Ok(result) = rdr.read_u8() {
+            result
+        } else {
+            return Err("Bad packet type");
+        };
+
+        if packet_type != Self::PACKET_TYPE {
+            return Err("Bad packet type");
+        }
+
+        // Consume cursor
+        let mut buf: Vec<u8> = rdr.into_inner();
+        let _: Vec<u8> = buf.drain(..1).collect();
+
+        let hashes = read_hashes(&mut buf)?;
+
+        if !buf.is_empty() {
+            return Err("Incorrect packet structure");
+        }
+
+        Ok(AnnounceEvents::new(hashes))
+    }
+}
+
+#[cfg(test)]
+use packets::hashes::arbitrary_hashes;
+
+#[cfg(test)]
+use quickcheck::Arbitrary;
+
+#[cfg(test)]
+impl Arbitrary for AnnounceEvents {
+    fn arbitrary<G: quickcheck::Gen>(g: &mut G) -> AnnounceEvents {
+        AnnounceEvents::new(arbitrary_hashes(g))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    quickcheck! {
+        fn serialize_deserialize(packet: AnnounceEvents) -> bool {
+            packet == AnnounceEvents::from_bytes(&AnnounceEvents::to_bytes(&packet)).unwrap()
+        }
+    }
+}
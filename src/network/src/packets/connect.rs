@@ -16,28 +16,75 @@
   along with the Purple Library. If not, see <http://www.gnu.org/licenses/>.
 */
 
-use byteorder::{ReadBytesExt, WriteBytesExt};
-use crypto::{PublicKey as Pk, SecretKey as Sk, Signature};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use crypto::{Hash, KxPublicKey, PublicKey as Pk, SecretKey as Sk, Signature};
 use std::io::Cursor;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Connect {
     node_id: Pk,
-    kx_key: Pk,
+    kx_key: KxPublicKey,
+    network_id: Hash,
+    protocol_version: u16,
+    capabilities: u32,
+    listen_port: u16,
     signature: Option<Signature>,
 }
 
 impl Connect {
     pub const PACKET_TYPE: u8 = 1;
 
-    pub fn new(node_id: Pk, kx_key: Pk) -> Connect {
+    pub fn new(
+        node_id: Pk,
+        kx_key: KxPublicKey,
+        network_id: Hash,
+        protocol_version: u16,
+        capabilities: u32,
+        listen_port: u16,
+    ) -> Connect {
         Connect {
             node_id: node_id,
             kx_key: kx_key,
+            network_id: network_id,
+            protocol_version: protocol_version,
+            capabilities: capabilities,
+            listen_port: listen_port,
             signature: None,
         }
     }
 
+    /// Returns the node id of the sender.
+    pub fn node_id(&self) -> &Pk {
+        &self.node_id
+    }
+
+    /// Returns the session key exchange public key of the sender.
+    pub fn kx_key(&self) -> &KxPublicKey {
+        &self.kx_key
+    }
+
+    /// Returns the id of the network the sender is on.
+    pub fn network_id(&self) -> &Hash {
+        &self.network_id
+    }
+
+    /// Returns the protocol version of the sender.
+    pub fn protocol_version(&self) -> u16 {
+        self.protocol_version
+    }
+
+    /// Returns the capabilities bitmask of the sender.
+    pub fn capabilities(&self) -> u32 {
+        self.capabilities
+    }
+
+    /// Returns the port on which the sender accepts connections.
+    ///
+    /// Equal to 0 if the sender does not accept connections.
+    pub fn listen_port(&self) -> u16 {
+        self.listen_port
+    }
+
     /// Signs the packet with the given secret key.
     pub fn sign(&mut self, skey: Sk) {
         // Assemble data
@@ -63,7 +110,7 @@ impl Connect {
     }
 
     pub fn to_bytes(&self) -> Vec<u8> {
-        let mut buffer: Vec<u8> = Vec::with_capacity(129);
+        let mut buffer: Vec<u8> = Vec::with_capacity(169);
         let packet_type: u8 = Self::PACKET_TYPE;
 
         let mut signature = if let Some(signature) = &self.signature {
@@ -74,13 +121,24 @@ impl Connect {
 
         let node_id = &self.node_id.0;
         let kx_key = &self.kx_key.0;
+        let network_id = &self.network_id.0;
 
         // Connect packet structure:
         // 1) Packet type(1)   - 8bits
-        // 2) Key exchange pk  - 32byte binary
-        // 3) Node id          - 32byte binary
-        // 4) Signature        - 64byte binary
+        // 2) Network id       - 32byte binary
+        // 3) Protocol version - 16bits
+        // 4) Capabilities     - 32bits
+        // 5) Listen port      - 16bits
+        // 6) Key exchange pk  - 32byte binary
+        // 7) Node id          - 32byte binary
+        // 8) Signature        - 64byte binary
         buffer.write_u8(packet_type).unwrap();
+        buffer.append(&mut network_id.to_vec());
+        buffer
+            .write_u16::<BigEndian>(self.protocol_version)
+            .unwrap();
+        buffer.write_u32::<BigEndian>(self.capabilities).unwrap();
+        buffer.write_u16::<BigEndian>(self.listen_port).unwrap();
         buffer.append(&mut kx_key.to_vec());
         buffer.append(&mut node_id.to_vec());
         buffer.append(&mut signature);
@@ -104,13 +162,48 @@ impl Connect {
         let mut buf: Vec<u8> = rdr.into_inner();
         let _: Vec<u8> = buf.drain(..1).collect();
 
+        let network_id = if buf.len() > 32 as usize {
+            let network_id_vec: Vec<u8> = buf.drain(..32).collect();
+            let mut b = [0; 32];
+
+            b.copy_from_slice(&network_id_vec);
+
+            Hash(b)
+        } else {
+            return Err("Incorrect packet structure");
+        };
+
+        let mut rdr = Cursor::new(buf);
+
+        let protocol_version = if let Ok(result) = rdr.read_u16::<BigEndian>() {
+            result
+        } else {
+            return Err("Bad protocol version");
+        };
+
+        let capabilities = if let Ok(result) = rdr.read_u32::<BigEndian>() {
+            result
+        } else {
+            return Err("Bad capabilities");
+        };
+
+        let listen_port = if let Ok(result) = rdr.read_u16::<BigEndian>() {
+            result
+        } else {
+            return Err("Bad listen port");
+        };
+
+        // Consume cursor
+        let mut buf: Vec<u8> = rdr.into_inner();
+        let _: Vec<u8> = buf.drain(..8).collect();
+
         let kx_key = if buf.len() > 32 as usize {
             let kx_key_vec: Vec<u8> = buf.drain(..32).collect();
             let mut b = [0; 32];
 
             b.copy_from_slice(&kx_key_vec);
 
-            Pk(b)
+            KxPublicKey(b)
         } else {
             return Err("Incorrect packet structure");
         };
@@ -136,6 +229,10 @@ impl Connect {
         let packet = Connect {
             node_id: node_id,
             kx_key: kx_key,
+            network_id: network_id,
+            protocol_version: protocol_version,
+            capabilities: capabilities,
+            listen_port: listen_port,
             signature: Some(signature),
         };
 
@@ -144,11 +241,16 @@ impl Connect {
 }
 
 fn assemble_sign_message(obj: &Connect) -> Vec<u8> {
-    let mut buf: Vec<u8> = Vec::with_capacity(64);
+    let mut buf: Vec<u8> = Vec::with_capacity(104);
 
+    let network_id = obj.network_id.0;
     let kx_key = obj.kx_key.0;
     let node_id = obj.node_id.0;
 
+    buf.append(&mut network_id.to_vec());
+    buf.write_u16::<BigEndian>(obj.protocol_version).unwrap();
+    buf.write_u32::<BigEndian>(obj.capabilities).unwrap();
+    buf.write_u16::<BigEndian>(obj.listen_port).unwrap();
     buf.append(&mut kx_key.to_vec());
     buf.append(&mut node_id.to_vec());
 
@@ -159,17 +261,21 @@ fn assemble_sign_message(obj: &Connect) -> Vec<u8> {
 use quickcheck::Arbitrary;
 
 #[cfg(test)]
-use crypto::Identity;
+use crypto::{gen_kx_keypair, Identity};
 
 #[cfg(test)]
 impl Arbitrary for Connect {
     fn arbitrary<G: quickcheck::Gen>(g: &mut G) -> Connect {
-        let id1 = Identity::new();
-        let id2 = Identity::new();
+        let id = Identity::new();
+        let (kx_key, _) = gen_kx_keypair();
 
         Connect {
-            node_id: *id1.pkey(),
-            kx_key: *id2.pkey(),
+            node_id: *id.pkey(),
+            kx_key: kx_key,
+            network_id: Arbitrary::arbitrary(g),
+            protocol_version: Arbitrary::arbitrary(g),
+            capabilities: Arbitrary::arbitrary(g),
+            listen_port: Arbitrary::arbitrary(g),
             signature: Some(Arbitrary::arbitrary(g)),
         }
     }
@@ -184,16 +290,49 @@ mod tests {
             tx == Connect::from_bytes(&Connect::to_bytes(&tx)).unwrap()
         }
 
-        fn verify_signature(id1: Identity, id2: Identity) -> bool {
+        fn verify_signature(id1: Identity, id2: Identity, network_id: Hash, protocol_version: u16, capabilities: u32) -> bool {
+            let (kx_key, _) = gen_kx_keypair();
             let mut packet = Connect {
                 node_id: *id1.pkey(),
-                kx_key: *id2.pkey(),
+                kx_key: kx_key,
+                network_id: network_id,
+                protocol_version: protocol_version,
+                capabilities: capabilities,
+                listen_port: 44034,
                 signature: None
             };
 
             packet.sign(id1.skey().clone());
-            packet.verify_sig()
+
+            let mut forged = packet.clone();
+            forged.sign(id2.skey().clone());
+
+            packet.verify_sig() && !forged.verify_sig()
         }
 
+        fn signature_covers_fields(id: Identity, network_id: Hash, protocol_version: u16, capabilities: u32, listen_port: u16) -> bool {
+            let (kx_key, _) = gen_kx_keypair();
+            let mut packet = Connect::new(*id.pkey(), kx_key, network_id, protocol_version, capabilities, listen_port);
+
+            packet.sign(id.skey().clone());
+
+            let mut other_network = packet.clone();
+            other_network.network_id = Hash::random();
+
+            let mut other_version = packet.clone();
+            other_version.protocol_version = protocol_version.wrapping_add(1);
+
+            let mut other_capabilities = packet.clone();
+            other_capabilities.capabilities = capabilities ^ 1;
+
+            let mut other_port = packet.clone();
+            other_port.listen_port = listen_port.wrapping_add(1);
+
+            packet.verify_sig()
+                && !other_network.verify_sig()
+                && !other_version.verify_sig()
+                && !other_capabilities.verify_sig()
+                && !other_port.verify_sig()
+        }
     }
 }
@@ -0,0 +1,82 @@
+/*
+  Copyright 2018 The Purple Library Authors
+  This file is part of the Purple Library.
+
+  The Purple Library is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  The Purple Library is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with the Purple Library. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use byteorder::{BigEndian, ByteOrder, WriteBytesExt};
+use crypto::Hash;
+
+/// The maximum number of hashes in a packet
+pub const MAX_HASHES: usize = 1024;
+
+/// Writes a list of hashes to the given buffer.
+///
+/// Hashes structure:
+/// 1) Hashes count     - 16bits
+/// 2) Hashes           - 32byte binary for each hash
+///
+/// This function will panic if there are more
+/// than `MAX_HASHES` hashes.
+pub(crate) fn write_hashes(buf: &mut Vec<u8>, hashes: &[Hash]) {
+    if hashes.len() > MAX_HASHES {
+        panic!("Too many hashes");
+    }
+
+    buf.write_u16::<BigEndian>(hashes.len() as u16).unwrap();
+
+    for hash in hashes.iter() {
+        buf.extend_from_slice(&hash.0);
+    }
+}
+
+/// Reads a list of hashes written with `write_hashes`
+/// from the start of the given buffer.
+pub(crate) fn read_hashes(buf: &mut Vec<u8>) -> Result<Vec<Hash>, &'static str> {
+    let hashes_count = if buf.len() >= 2 {
+        BigEndian::read_u16(&buf[..2]) as usize
+    } else {
+        return Err("Bad hashes count");
+    };
+
+    if hashes_count > MAX_HASHES {
+        return Err("Too many hashes");
+    }
+
+    if buf.len() < 2 + 32 * hashes_count {
+        return Err("Incorrect packet structure");
+    }
+
+    let _: Vec<u8> = buf.drain(..2).collect();
+    let mut hashes: Vec<Hash> = Vec::with_capacity(hashes_count);
+
+    for _ in 0..hashes_count {
+        let hash_vec: Vec<u8> = buf.drain(..32).collect();
+        let mut b = [0; 32];
+
+        b.copy_from_slice(&hash_vec);
+        hashes.push(Hash(b));
+    }
+
+    Ok(hashes)
+}
+
+#[cfg(test)]
+pub(crate) fn arbitrary_hashes<G: quickcheck::Gen>(g: &mut G) -> Vec<Hash> {
+    use quickcheck::Arbitrary;
+
+    let len = usize::arbitrary(g) % (MAX_HASHES + 1);
+    (0..len).map(|_| Hash::arbitrary(g)).collect()
+}
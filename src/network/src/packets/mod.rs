@@ -16,5 +16,30 @@
   along with the Purple Library. If not, see <http://www.gnu.org/licenses/>.
 */
 
+pub mod announce_events;
+pub mod announce_txs;
 pub mod connect;
+mod hashes;
+pub mod ping;
+pub mod pong;
+pub mod request_event_range;
+pub mod request_events;
+pub mod request_peers;
+pub mod request_txs;
+pub mod send_events;
+pub mod send_peers;
+pub mod send_txs;
+
+pub use self::announce_events::*;
+pub use self::announce_txs::*;
 pub use self::connect::*;
+pub use self::hashes::MAX_HASHES;
+pub use self::ping::*;
+pub use self::pong::*;
+pub use self::request_event_range::*;
+pub use self::request_events::*;
+pub use self::request_peers::*;
+pub use self::request_txs::*;
+pub use self::send_events::*;
+pub use self::send_peers::*;
+pub use self::send_txs::*;
@@ -0,0 +1,93 @@
+/*
+  Copyright 2018 The Purple Library Authors
+  This file is part of the Purple Library.
+
+  The Purple Library is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  The Purple Library is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with the Purple Library. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::io::Cursor;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pong {
+    /// The nonce of the answered ping
+    pub nonce: u64,
+}
+
+impl Pong {
+    pub const PACKET_TYPE: u8 = 12;
+
+    pub fn new(nonce: u64) -> Pong {
+        Pong { nonce: nonce }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buffer: Vec<u8> = Vec::with_capacity(9);
+        let packet_type: u8 = Self::PACKET_TYPE;
+
+        // Pong packet structure:
+        // 1) Packet type(12)  - 8bits
+        // 2) Nonce            - 64bits
+        buffer.write_u8(packet_type).unwrap();
+        buffer.write_u64::<BigEndian>(self.nonce).unwrap();
+
+        buffer
+    }
+
+    pub fn from_bytes(bin: &[u8]) -> Result<Pong, &'static str> {
+        let mut rdr = Cursor::new(bin.to_vec());
+        let packet_type = if let Ok(result) = rdr.read_u8() {
+            result
+        } else {
+            return Err("Bad packet type");
+        };
+
+        if packet_type != Self::PACKET_TYPE {
+            return Err("Bad packet type");
+        }
+
+        let nonce = if let Ok(result) = rdr.read_u64::<BigEndian>() {
+            result
+        } else {
+            return Err("Bad nonce");
+        };
+
+        if bin.len() != 9 {
+            return Err("Incorrect packet structure");
+        }
+
+        Ok(Pong::new(nonce))
+    }
+}
+
+#[cfg(test)]
+use quickcheck::Arbitrary;
+
+#[cfg(test)]
+impl Arbitrary for Pong {
+    fn arbitrary<G: quickcheck::Gen>(g: &mut G) -> Pong {
+        Pong::new(Arbitrary::arbitrary(g))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    quickcheck! {
+        fn serialize_deserialize(packet: Pong) -> bool {
+            packet == Pong::from_bytes(&Pong::to_bytes(&packet)).unwrap()
+        }
+    }
+}
@@ -0,0 +1,111 @@
+/*
+  Copyright 2018 The Purple Library Authors
+  This file is part of the Purple Library.
+
+  The Purple Library is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  The Purple Library is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with the Purple Library. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use crypto::Hash;
+use std::io::Cursor;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequestEventRange {
+    /// The hash of the event after which events are requested
+    pub from: Hash,
+
+    /// The maximum number of events to send back
+    pub count: u16,
+}
+
+impl RequestEventRange {
+    pub const PACKET_TYPE: u8 = 10;
+
+    pub fn new(from: Hash, count: u16) -> RequestEventRange {
+        RequestEventRange {
+            from: from,
+            count: count,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buffer: Vec<u8> = Vec::with_capacity(35);
+        let packet_type: u8 = Self::PACKET_TYPE;
+
+        // Request event range packet structure:
+        // 1) Packet type(10)  - 8bits
+        // 2) Count            - 16bits
+        // 3) From             - 32byte binary
+        buffer.write_u8(packet_type).unwrap();
+        buffer.write_u16::<BigEndian>(self.count).unwrap();
+        buffer.append(&mut self.from.0.to_vec());
+
+        buffer
+    }
+
+    pub fn from_bytes(bin: &[u8]) -> Result<RequestEventRange, &'static str> {
+        let mut rdr = Cursor::new(bin.to_vec());
+        let packet_type = if let Ok(result) = rdr.read_u8() {
+            result
+        } else {
+            return Err("Bad packet type");
+        };
+
+        if packet_type != Self::PACKET_TYPE {
+            return Err("Bad packet type");
+        }
+
+        let count = if let Ok(result) = rdr.read_u16::<BigEndian>() {
+            result
+        } else {
+            return Err("Bad count");
+        };
+
+        // Consume cursor
+        let mut buf: Vec<u8> = rdr.into_inner();
+        let _: Vec<u8> = buf.drain(..3).collect();
+
+        let from = if buf.len() == 32 as usize {
+            let mut b = [0; 32];
+            b.copy_from_slice(&buf);
+
+            Hash(b)
+        } else {
+            return Err("Incorrect packet structure");
+        };
+
+        Ok(RequestEventRange::new(from, count))
+    }
+}
+
+#[cfg(test)]
+use quickcheck::Arbitrary;
+
+#[cfg(test)]
+impl Arbitrary for RequestEventRange {
+    fn arbitrary<G: quickcheck::Gen>(g: &mut G) -> RequestEventRange {
+        RequestEventRange::new(Arbitrary::arbitrary(g), Arbitrary::arbitrary(g))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    quickcheck! {
+        fn serialize_deserialize(packet: RequestEventRange) -> bool {
+            packet == RequestEventRange::from_bytes(&RequestEventRange::to_bytes(&packet)).unwrap()
+        }
+    }
+}
@@ -0,0 +1,95 @@
+/*
+  Copyright 2018 The Purple Library Authors
+  This file is part of the Purple Library.
+
+  The Purple Library is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  The Purple Library is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with the Purple Library. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use byteorder::{ReadBytesExt, WriteBytesExt};
+use std::io::Cursor;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequestPeers {
+    /// The maximum number of addresses to send back
+    pub requested_peers: u8,
+}
+
+impl RequestPeers {
+    pub const PACKET_TYPE: u8 = 2;
+
+    pub fn new(requested_peers: u8) -> RequestPeers {
+        RequestPeers {
+            requested_peers: requested_peers,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buffer: Vec<u8> = Vec::with_capacity(2);
+        let packet_type: u8 = Self::PACKET_TYPE;
+
+        // Request peers packet structure:
+        // 1) Packet type(2)   - 8bits
+        // 2) Requested peers  - 8bits
+        buffer.write_u8(packet_type).unwrap();
+        buffer.write_u8(self.requested_peers).unwrap();
+
+        buffer
+    }
+
+    pub fn from_bytes(bin: &[u8]) -> Result<RequestPeers, &'static str> {
+        let mut rdr = Cursor::new(bin.to_vec());
+        let packet_type = if let Ok(result) = rdr.read_u8() {
+            result
+        } else {
+            return Err("Bad packet type");
+        };
+
+        if packet_type != Self::PACKET_TYPE {
+            return Err("Bad packet type");
+        }
+
+        let requested_peers = if let Ok(result) = rdr.read_u8() {
+            result
+        } else {
+            return Err("Bad requested peers");
+        };
+
+        if bin.len() != 2 {
+            return Err("Incorrect packet structure");
+        }
+
+        Ok(RequestPeers::new(requested_peers))
+    }
+}
+
+#[cfg(test)]
+use quickcheck::Arbitrary;
+
+#[cfg(test)]
+impl Arbitrary for RequestPeers {
+    fn arbitrary<G: quickcheck::Gen>(g: &mut G) -> RequestPeers {
+        RequestPeers::new(Arbitrary::arbitrary(g))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    quickcheck! {
+        fn serialize_deserialize(packet: RequestPeers) -> bool {
+            packet == RequestPeers::from_bytes(&RequestPeers::to_bytes(&packet)).unwrap()
+        }
+    }
+}
@@ -0,0 +1,156 @@
+/*
+  Copyright 2018 The Purple Library Authors
+  This file is part of the Purple Library.
+
+  The Purple Library is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  The Purple Library is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with the Purple Library. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use byteorder::{BigEndian, ByteOrder, ReadBytesExt, WriteBytesExt};
+use std::io::Cursor;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SendEvents {
+    /// Serialized events requested from the sender
+    pub events: Vec<Vec<u8>>,
+}
+
+impl SendEvents {
+    pub const PACKET_TYPE: u8 = 6;
+
+    /// The maximum number of events in a packet
+    pub const MAX_EVENTS: usize = 256;
+
+    pub fn new(events: Vec<Vec<u8>>) -> SendEvents {
+        SendEvents { events: events }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let events_len: usize = self.events.iter().map(|event| 4 + event.len()).sum();
+        let mut buffer: Vec<u8> = Vec::with_capacity(3 + events_len);
+        let packet_type: u8 = Self::PACKET_TYPE;
+
+        if self.events.len() > Self::MAX_EVENTS {
+            panic!("Too many events");
+        }
+
+        // Send events packet structure:
+        // 1) Packet type(6)   - 8bits
+        // 2) Events count     - 16bits
+        // 3) Events           - Binary of events length
+        //
+        // Event structure:
+        // 1) Event length     - 32bits
+        // 2) Event            - Binary of event length
+        buffer.write_u8(packet_type).unwrap();
+        buffer
+            .write_u16::<BigEndian>(self.events.len() as u16)
+            .unwrap();
+
+        for event in self.events.iter() {
+            buffer.write_u32::<BigEndian>(event.len() as u32).unwrap();
+            buffer.extend_from_slice(event);
+        }
+
+        buffer
+    }
+
+    pub fn from_bytes(bin: &[u8]) -> Result<SendEvents, &'static str> {
+        let mut rdr = Cursor::new(bin.to_vec());
+        let packet_type = if let Ok(result) = rdr.read_u8() {
+            result
+        } else {
+            return Err("Bad packet type");
+        };
+
+        if packet_type != Self::PACKET_TYPE {
+            return Err("Bad packet type");
+        }
+
+        let events_count = if let Ok(result) = rdr.read_u16::<BigEndian>() {
+            result as usize
+        } else {
+            return Err("Bad events count");
+        };
+
+        if events_count == 0 {
+            return Err("No events");
+        }
+
+        if events_count > Self::MAX_EVENTS {
+            return Err("Too many events");
+        }
+
+        // Consume cursor
+        let mut buf: Vec<u8> = rdr.into_inner();
+        let _: Vec<u8> = buf.drain(..3).collect();
+        let mut events: Vec<Vec<u8>> = Vec::with_capacity(events_count);
+
+        for _ in 0..events_count {
+            if buf.len() < 4 {
+                return Err("Bad event length");
+            }
+
+            let event_len = BigEndian::read_u32(&buf[..4]) as usize;
+
+            if event_len == 0 || buf.len() < 4 + event_len {
+                return Err("Incorrect packet structure");
+            }
+
+            let _: Vec<u8> = buf.drain(..4).collect();
+            events.push(buf.drain(..event_len).collect());
+        }
+
+        if !buf.is_empty() {
+            return Err("Incorrect packet structure");
+        }
+
+        Ok(SendEvents::new(events))
+    }
+}
+
+#[cfg(test)]
+use quickcheck::Arbitrary;
+
+#[cfg(test)]
+impl Arbitrary for SendEvents {
+    fn arbitrary<G: quickcheck::Gen>(g: &mut G) -> SendEvents {
+        let len = usize::arbitrary(g) % SendEvents::MAX_EVENTS + 1;
+        let events = (0..len)
+            .map(|_| {
+                let mut event: Vec<u8> = Arbitrary::arbitrary(g);
+                event.push(u8::arbitrary(g));
+                event
+            })
+            .collect();
+
+        SendEvents::new(events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    quickcheck! {
+        fn serialize_deserialize(packet: SendEvents) -> bool {
+            packet == SendEvents::from_bytes(&SendEvents::to_bytes(&packet)).unwrap()
+        }
+    }
+
+    #[test]
+    fn it_rejects_empty_packets() {
+        let packet = SendEvents::new(vec![]);
+        assert!(SendEvents::from_bytes(&packet.to_bytes()).is_err());
+    }
+}
@@ -0,0 +1,120 @@
+/*
+  Copyright 2018 The Purple Library Authors
+  This file is part of the Purple Library.
+
+  The Purple Library is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  The Purple Library is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with the Purple Library. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use address_book::{read_addr, write_addr};
+use byteorder::{ReadBytesExt, WriteBytesExt};
+use std::io::Cursor;
+use std::net::SocketAddr;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SendPeers {
+    /// Addresses of peers known by the sender
+    pub peers: Vec<SocketAddr>,
+}
+
+impl SendPeers {
+    pub const PACKET_TYPE: u8 = 3;
+
+    /// The maximum number of addresses in a packet
+    pub const MAX_PEERS: usize = 255;
+
+    pub fn new(peers: Vec<SocketAddr>) -> SendPeers {
+        SendPeers { peers: peers }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buffer: Vec<u8> = Vec::with_capacity(2 + 19 * self.peers.len());
+        let packet_type: u8 = Self::PACKET_TYPE;
+
+        if self.peers.len() > Self::MAX_PEERS {
+            panic!("Too many peer addresses");
+        }
+
+        // Send peers packet structure:
+        // 1) Packet type(3)   - 8bits
+        // 2) Peers count      - 8bits
+        // 3) Peer addresses   - Binary of addresses length
+        buffer.write_u8(packet_type).unwrap();
+        buffer.write_u8(self.peers.len() as u8).unwrap();
+
+        for addr in self.peers.iter() {
+            write_addr(&mut buffer, addr);
+        }
+
+        buffer
+    }
+
+    pub fn from_bytes(bin: &[u8]) -> Result<SendPeers, &'static str> {
+        let mut rdr = Cursor::new(bin.to_vec());
+        let packet_type = if let Ok(result) = rdr.read_u8() {
+            result
+        } else {
+            return Err("Bad packet type");
+        };
+
+        if packet_type != Self::PACKET_TYPE {
+            return Err("Bad packet type");
+        }
+
+        let peers_count = if let Ok(result) = rdr.read_u8() {
+            result
+        } else {
+            return Err("Bad peers count");
+        };
+
+        // Consume cursor
+        let mut buf: Vec<u8> = rdr.into_inner();
+        let _: Vec<u8> = buf.drain(..2).collect();
+        let mut peers: Vec<SocketAddr> = Vec::with_capacity(peers_count as usize);
+
+        for _ in 0..peers_count {
+            peers.push(read_addr(&mut buf)?);
+        }
+
+        if !buf.is_empty() {
+            return Err("Incorrect packet structure");
+        }
+
+        Ok(SendPeers::new(peers))
+    }
+}
+
+#[cfg(test)]
+use address_book::arbitrary_addr;
+
+#[cfg(test)]
+use quickcheck::Arbitrary;
+
+#[cfg(test)]
+impl Arbitrary for SendPeers {
+    fn arbitrary<G: quickcheck::Gen>(g: &mut G) -> SendPeers {
+        let len = usize::arbitrary(g) % (SendPeers::MAX_PEERS + 1);
+        SendPeers::new((0..len).map(|_| arbitrary_addr(g)).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    quickcheck! {
+        fn serialize_deserialize(packet: SendPeers) -> bool {
+            packet == SendPeers::from_bytes(&SendPeers::to_bytes(&packet)).unwrap()
+        }
+    }
+}
@@ -0,0 +1,156 @@
+/*
+  Copyright 2018 The Purple Library Authors
+  This file is part of the Purple Library.
+
+  The Purple Library is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  The Purple Library is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with the Purple Library. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use byteorder::{BigEndian, ByteOrder, ReadBytesExt, WriteBytesExt};
+use std::io::Cursor;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SendTxs {
+    /// Serialized transactions requested from the sender
+    pub txs: Vec<Vec<u8>>,
+}
+
+impl SendTxs {
+    pub const PACKET_TYPE: u8 = 9;
+
+    /// The maximum number of transactions in a packet
+    pub const MAX_TXS: usize = 256;
+
+    pub fn new(txs: Vec<Vec<u8>>) -> SendTxs {
+        SendTxs { txs: txs }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let txs_len: usize = self.txs.iter().map(|tx| 4 + tx.len()).sum();
+        let mut buffer: Vec<u8> = Vec::with_capacity(3 + txs_len);
+        let packet_type: u8 = Self::PACKET_TYPE;
+
+        if self.txs.len() > Self::MAX_TXS {
+            panic!("Too many transactions");
+        }
+
+        // Send txs packet structure:
+        // 1) Packet type(9)   - 8bits
+        // 2) Txs count        - 16bits
+        // 3) Transactions     - Binary of transactions length
+        //
+        // Transaction structure:
+        // 1) Tx length        - 32bits
+        // 2) Transaction      - Binary of tx length
+        buffer.write_u8(packet_type).unwrap();
+        buffer
+            .write_u16::<BigEndian>(self.txs.len() as u16)
+            .unwrap();
+
+        for tx in self.txs.iter() {
+            buffer.write_u32::<BigEndian>(tx.len() as u32).unwrap();
+            buffer.extend_from_slice(tx);
+        }
+
+        buffer
+    }
+
+    pub fn from_bytes(bin: &[u8]) -> Result<SendTxs, &'static str> {
+        let mut rdr = Cursor::new(bin.to_vec());
+        let packet_type = if let Ok(result) = rdr.read_u8() {
+            result
+        } else {
+            return Err("Bad packet type");
+        };
+
+        if packet_type != Self::PACKET_TYPE {
+            return Err("Bad packet type");
+        }
+
+        let txs_count = if let Ok(result) = rdr.read_u16::<BigEndian>() {
+            result as usize
+        } else {
+            return Err("Bad transactions count");
+        };
+
+        if txs_count == 0 {
+            return Err("No transactions");
+        }
+
+        if txs_count > Self::MAX_TXS {
+            return Err("Too many transactions");
+        }
+
+        // Consume cursor
+        let mut buf: Vec<u8> = rdr.into_inner();
+        let _: Vec<u8> = buf.drain(..3).collect();
+        let mut txs: Vec<Vec<u8>> = Vec::with_capacity(txs_count);
+
+        for _ in 0..txs_count {
+            if buf.len() < 4 {
+                return Err("Bad transaction length");
+            }
+
+            let tx_len = BigEndian::read_u32(&buf[..4]) as usize;
+
+            if tx_len == 0 || buf.len() < 4 + tx_len {
+                return Err("Incorrect packet structure");
+            }
+
+            let _: Vec<u8> = buf.drain(..4).collect();
+            txs.push(buf.drain(..tx_len).collect());
+        }
+
+        if !buf.is_empty() {
+            return Err("Incorrect packet structure");
+        }
+
+        Ok(SendTxs::new(txs))
+    }
+}
+
+#[cfg(test)]
+use quickcheck::Arbitrary;
+
+#[cfg(test)]
+impl Arbitrary for SendTxs {
+    fn arbitrary<G: quickcheck::Gen>(g: &mut G) -> SendTxs {
+        let len = usize::arbitrary(g) % SendTxs::MAX_TXS + 1;
+        let txs = (0..len)
+            .map(|_| {
+                let mut tx: Vec<u8> = Arbitrary::arbitrary(g);
+                tx.push(u8::arbitrary(g));
+                tx
+            })
+            .collect();
+
+        SendTxs::new(txs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    quickcheck! {
+        fn serialize_deserialize(packet: SendTxs) -> bool {
+            packet == SendTxs::from_bytes(&SendTxs::to_bytes(&packet)).unwrap()
+        }
+    }
+
+    #[test]
+    fn it_rejects_empty_packets() {
+        let packet = SendTxs::new(vec![]);
+        assert!(SendTxs::from_bytes(&packet.to_bytes()).is_err());
+    }
+}
@@ -16,11 +16,48 @@
   along with the Purple Library. If not, see <http://www.gnu.org/licenses/>.
 */
 
-use crypto::{gen_kx_keypair, KxPublicKey as Pk, KxSecretKey as Sk, SessionKey};
+use byteorder::{BigEndian, ByteOrder};
+use connection::ConnectionType;
+use crypto::secretbox::{self, Key, Nonce, NONCEBYTES};
+use crypto::{
+    client_session_keys, gen_kx_keypair, server_session_keys, KxPublicKey as Pk, KxSecretKey as Sk,
+    SessionKey,
+};
+use futures::sync::mpsc::Sender;
+use known_hashes::KnownHashes;
+use packets::{RequestEventRange, RequestEvents, RequestTxs, SendEvents, SendTxs};
+use rate_limit::{RateLimiter, RateLimits};
+use std::collections::VecDeque;
 use std::net::SocketAddr;
+use std::time::{Duration, Instant};
 use NodeId;
 
-#[derive(Debug, Clone)]
+/// The size in bytes of the nonce counter prepended to encrypted frames
+const NONCE_COUNTER_SIZE: usize = 8;
+
+/// The number of event hashes remembered as known by a peer
+const KNOWN_EVENTS_CAPACITY: usize = 8192;
+
+/// The number of transaction hashes remembered as known by a peer
+const KNOWN_TXS_CAPACITY: usize = 32768;
+
+/// Time in milliseconds after which a request sent
+/// to a peer is no longer expected to be answered.
+const REQUEST_REPLY_TIMEOUT: u64 = 10000;
+
+/// The maximum number of requests of each kind that
+/// are remembered as awaiting a reply from a peer.
+const MAX_PENDING_REQUESTS: usize = 64;
+
+/// The number of seconds over which the packets
+/// dropped by the rate limiter are counted.
+const DROP_WINDOW: u64 = 10;
+
+/// The number of packets that can be dropped by the rate
+/// limiter in a window before the peer is flooding us.
+const MAX_WINDOW_DROPS: u32 = 20;
+
+#[derive(Debug)]
 pub struct Peer {
     /// The id of the peer
     ///
@@ -32,30 +69,127 @@ pub struct Peer {
     /// The ip address of the peer
     pub ip: SocketAddr,
 
+    /// Whether we or the peer have initiated the connection
+    pub connection_type: ConnectionType,
+
+    /// The time at which the connection has been established
+    pub connected_at: Instant,
+
+    /// The protocol version of the peer
+    ///
+    /// Equal to `None` until the handshake is completed.
+    pub protocol_version: Option<u16>,
+
+    /// The capabilities bitmask of the peer
+    ///
+    /// Equal to `None` until the handshake is completed.
+    pub capabilities: Option<u32>,
+
+    /// The number of bytes received from the peer
+    pub bytes_in: u64,
+
+    /// The number of bytes sent to the peer
+    pub bytes_out: u64,
+
+    /// The number of received packets dropped by the rate limiter
+    pub dropped_in: u64,
+
+    /// The number of packets dropped because the outbound queue was full
+    pub dropped_out: u64,
+
+    /// Hashes of the events that the peer is known to have
+    pub known_events: KnownHashes,
+
+    /// Hashes of the transactions that the peer is known to have
+    pub known_txs: KnownHashes,
+
+    /// The round-trip time measured by the last answered ping
+    ///
+    /// Equal to `None` until a pong has been received.
+    pub latency: Option<Duration>,
+
+    /// The nonce and send time of the ping awaiting a pong
+    ping: Option<(u64, Instant)>,
+
+    /// The number of consecutive pings left unanswered
+    missed_pongs: u32,
+
+    /// The send times of the event requests awaiting a reply
+    event_requests: VecDeque<Instant>,
+
+    /// The send times of the transaction requests awaiting a reply
+    tx_requests: VecDeque<Instant>,
+
+    /// The token buckets limiting the received packets
+    rate_limiter: RateLimiter,
+
+    /// The start of the window in which dropped packets are counted
+    drop_window_start: Instant,
+
+    /// The number of packets dropped since the start of the window
+    window_drops: u32,
+
     /// Session generated public key
     pk: Pk,
 
     /// Session generated secret key
     sk: Sk,
 
-    /// Our encryption key
+    /// Key used to decrypt the packets received from the peer
     rx: Option<SessionKey>,
 
-    /// The peer's encryption key
+    /// Key used to encrypt the packets sent to the peer
     tx: Option<SessionKey>,
+
+    /// The nonce counter of the last packet received from the peer
+    rx_nonce: u64,
+
+    /// The nonce counter of the next packet sent to the peer
+    tx_nonce: u64,
+
+    /// Bounded queue of serialized packets that are
+    /// written to the write half of the peer's socket.
+    outbound: Sender<Vec<u8>>,
 }
 
 impl Peer {
-    pub fn new(id: Option<NodeId>, ip: SocketAddr) -> Peer {
+    pub fn new(
+        id: Option<NodeId>,
+        ip: SocketAddr,
+        connection_type: ConnectionType,
+        outbound: Sender<Vec<u8>>,
+    ) -> Peer {
         let (pk, sk) = gen_kx_keypair();
+        let now = Instant::now();
 
         Peer {
             id: id,
             ip: ip,
+            connection_type: connection_type,
+            connected_at: now,
+            protocol_version: None,
+            capabilities: None,
+            bytes_in: 0,
+            bytes_out: 0,
+            dropped_in: 0,
+            dropped_out: 0,
+            known_events: KnownHashes::new(KNOWN_EVENTS_CAPACITY),
+            known_txs: KnownHashes::new(KNOWN_TXS_CAPACITY),
+            latency: None,
+            ping: None,
+            missed_pongs: 0,
+            event_requests: VecDeque::new(),
+            tx_requests: VecDeque::new(),
+            rate_limiter: RateLimiter::new(&RateLimits::default(), now),
+            drop_window_start: now,
+            window_drops: 0,
             pk: pk,
             sk: sk,
             rx: None,
             tx: None,
+            rx_nonce: 0,
+            tx_nonce: 1,
+            outbound: outbound,
         }
     }
 
@@ -69,4 +203,437 @@ impl Peer {
         self.rx = Some(rx);
         self.tx = Some(tx);
     }
+
+    /// Returns the session generated key exchange public key.
+    pub fn kx_public_key(&self) -> &Pk {
+        &self.pk
+    }
+
+    /// Derives the session keys from our session generated
+    /// key pair and the key exchange public key of the peer.
+    ///
+    /// The side that initiated the connection acts as the client.
+    pub fn derive_session_keys(
+        &mut self,
+        peer_pk: &Pk,
+        client_or_server: &ConnectionType,
+    ) -> Result<(), &'static str> {
+        let keys = match client_or_server {
+            ConnectionType::Client => client_session_keys(&self.pk, &self.sk, peer_pk),
+            ConnectionType::Server => server_session_keys(&self.pk, &self.sk, peer_pk),
+        };
+
+        match keys {
+            Ok((rx, tx)) => {
+                self.set_session_keys(rx, tx);
+                Ok(())
+            }
+            Err(_) => Err("Invalid key exchange public key"),
+        }
+    }
+
+    /// Replaces the limits applied to the received packets.
+    pub fn set_rate_limits(&mut self, limits: &RateLimits) {
+        self.rate_limiter = RateLimiter::new(limits, Instant::now());
+    }
+
+    /// Returns `true` if a received packet of the given type and
+    /// size in bytes is within the rate limits of the peer.
+    ///
+    /// Packets over the limits are counted as dropped.
+    pub fn allow_packet(&mut self, packet_type: u8, len: usize, now: Instant) -> bool {
+        if self.rate_limiter.allow(packet_type, len, now) {
+            return true;
+        }
+
+        if now >= self.drop_window_start + Duration::from_secs(DROP_WINDOW) {
+            self.drop_window_start = now;
+            self.window_drops = 0;
+        }
+
+        self.dropped_in += 1;
+        self.window_drops = self.window_drops.saturating_add(1);
+        false
+    }
+
+    /// Returns `true` if the last dropped packet is the first one
+    /// over the drops tolerated in the current window.
+    ///
+    /// Bursts that occasionally exceed the rate limits are not
+    /// flooding and sustained flooding is only reported once
+    /// per window.
+    pub fn is_flooding(&self) -> bool {
+        self.window_drops == MAX_WINDOW_DROPS + 1
+    }
+
+    /// Returns `true` if the session keys have been derived.
+    pub fn has_session_keys(&self) -> bool {
+        self.rx.is_some() && self.tx.is_some()
+    }
+
+    /// Queues a serialized packet to be sent to the peer.
+    ///
+    /// The packet is encrypted if the session keys have
+    /// been derived. The packet is dropped and counted if
+    /// the outbound queue is full. Queued requests are
+    /// recorded as awaiting a reply.
+    pub fn send_packet(&mut self, packet: Vec<u8>) -> Result<(), &'static str> {
+        let packet_type = packet.first().cloned();
+        let packet = if self.has_session_keys() {
+            self.encrypt_packet(&packet)?
+        } else {
+            packet
+        };
+
+        // Account for the length prefix of the frame
+        let len = packet.len() as u64 + 4;
+
+        match self.outbound.try_send(packet) {
+            Ok(()) => {
+                self.bytes_out += len;
+
+                if let Some(packet_type) = packet_type {
+                    self.request_sent(packet_type, Instant::now());
+                }
+
+                Ok(())
+            }
+            Err(ref err) if err.is_full() => {
+                debug!("Outbound queue of {} is full, dropping packet", self.ip);
+                self.dropped_out += 1;
+                Ok(())
+            }
+            Err(_) => Err("The connection to the peer is closed"),
+        }
+    }
+
+    /// Records a ping with the given nonce as sent at `now`.
+    ///
+    /// A previous ping that is still awaiting a pong
+    /// is counted as missed.
+    pub fn ping_sent(&mut self, nonce: u64, now: Instant) {
+        if self.ping.is_some() {
+            self.missed_pongs += 1;
+        }
+
+        self.ping = Some((nonce, now));
+    }
+
+    /// Records a pong received at `now`, updating the latency
+    /// of the peer. Returns `false` if the nonce does not match
+    /// the one of the ping awaiting a pong.
+    pub fn pong_received(&mut self, nonce: u64, now: Instant) -> bool {
+        match self.ping {
+            Some((expected, sent_at)) if expected == nonce => {
+                self.latency = Some(now.duration_since(sent_at));
+                self.ping = None;
+                self.missed_pongs = 0;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns the number of consecutive pings left unanswered.
+    pub fn missed_pongs(&self) -> u32 {
+        self.missed_pongs
+    }
+
+    /// Records a packet of the given type as sent at `now`.
+    ///
+    /// Packets other than event and transaction
+    /// requests are not expected to be answered.
+    pub fn request_sent(&mut self, packet_type: u8, now: Instant) {
+        let requests = match packet_type {
+            RequestEvents::PACKET_TYPE | RequestEventRange::PACKET_TYPE => &mut self.event_requests,
+            RequestTxs::PACKET_TYPE => &mut self.tx_requests,
+            _ => return,
+        };
+
+        if requests.len() == MAX_PENDING_REQUESTS {
+            requests.pop_front();
+        }
+
+        requests.push_back(now);
+    }
+
+    /// Records a packet of the given type as received at `now`.
+    ///
+    /// Returns `true` if the packet is the reply to a request
+    /// that we have sent less than `REQUEST_REPLY_TIMEOUT`
+    /// milliseconds ago, in which case the request is no
+    /// longer awaiting a reply.
+    pub fn reply_received(&mut self, packet_type: u8, now: Instant) -> bool {
+        let requests = match packet_type {
+            SendEvents::PACKET_TYPE => &mut self.event_requests,
+            SendTxs::PACKET_TYPE => &mut self.tx_requests,
+            _ => return false,
+        };
+
+        let timeout = Duration::from_millis(REQUEST_REPLY_TIMEOUT);
+
+        // Peers do not reply to requests for data they do not have
+        while let Some(sent_at) = requests.front().cloned() {
+            if now < sent_at + timeout {
+                break;
+            }
+
+            requests.pop_front();
+        }
+
+        requests.pop_front().is_some()
+    }
+
+    /// Encrypts and authenticates a packet with our tx session key.
+    ///
+    /// Encrypted frame structure:
+    /// 1) Nonce counter    - 64bits
+    /// 2) Ciphertext       - Binary of packet length + 16 bytes
+    pub fn encrypt_packet(&mut self, packet: &[u8]) -> Result<Vec<u8>, &'static str> {
+        let key = match self.tx {
+            Some(ref tx) => Key(tx.0),
+            None => return Err("The session keys have not been derived"),
+        };
+
+        // Never re-use a nonce with the same key
+        if self.tx_nonce == u64::max_value() {
+            return Err("The nonce counter is exhausted");
+        }
+
+        let counter = self.tx_nonce;
+        let mut ciphertext = secretbox::seal(packet, &nonce_from_counter(counter), &key);
+        let mut frame = vec![0; NONCE_COUNTER_SIZE];
+
+        BigEndian::write_u64(&mut frame, counter);
+        frame.append(&mut ciphertext);
+        self.tx_nonce += 1;
+
+        Ok(frame)
+    }
+
+    /// Authenticates and decrypts a frame received from the peer
+    /// with our rx session key.
+    ///
+    /// The nonce counter of each received frame must be greater
+    /// than the one of the previous frame so that replayed frames
+    /// are rejected.
+    pub fn decrypt_packet(&mut self, frame: &[u8]) -> Result<Vec<u8>, &'static str> {
+        let key = match self.rx {
+            Some(ref rx) => Key(rx.0),
+            None => return Err("The session keys have not been derived"),
+        };
+
+        if frame.len() <= NONCE_COUNTER_SIZE {
+            return Err("Incorrect frame structure");
+        }
+
+        let counter = BigEndian::read_u64(&frame[..NONCE_COUNTER_SIZE]);
+
+        if counter <= self.rx_nonce {
+            return Err("Invalid nonce counter");
+        }
+
+        match secretbox::open(
+            &frame[NONCE_COUNTER_SIZE..],
+            &nonce_from_counter(counter),
+            &key,
+        ) {
+            Ok(packet) => {
+                self.rx_nonce = counter;
+                Ok(packet)
+            }
+            Err(_) => Err("Frame authentication failed"),
+        }
+    }
+}
+
+fn nonce_from_counter(counter: u64) -> Nonce {
+    let mut nonce = [0; NONCEBYTES];
+    BigEndian::write_u64(&mut nonce[NONCEBYTES - NONCE_COUNTER_SIZE..], counter);
+
+    Nonce(nonce)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crypto::Hash;
+    use futures::sync::mpsc;
+    use packets::{AnnounceTxs, Ping};
+    use rate_limit::BucketLimit;
+
+    fn session_pair() -> (Peer, Peer) {
+        let (sender, _receiver) = mpsc::channel(8);
+        let addr = "127.0.0.1:44034".parse().unwrap();
+        let mut client = Peer::new(None, addr, ConnectionType::Client, sender.clone());
+        let mut server = Peer::new(None, addr, ConnectionType::Server, sender);
+        let client_pk = client.kx_public_key().clone();
+        let server_pk = server.kx_public_key().clone();
+
+        client
+            .derive_session_keys(&server_pk, &ConnectionType::Client)
+            .unwrap();
+        server
+            .derive_session_keys(&client_pk, &ConnectionType::Server)
+            .unwrap();
+
+        (client, server)
+    }
+
+    quickcheck! {
+        fn encrypt_decrypt(packets: Vec<Vec<u8>>) -> bool {
+            let (mut client, mut server) = session_pair();
+
+            packets.iter().all(|packet| {
+                let frame = client.encrypt_packet(packet).unwrap();
+                server.decrypt_packet(&frame).unwrap() == *packet
+            })
+        }
+    }
+
+    #[test]
+    fn it_rejects_tampered_frames() {
+        let (mut client, mut server) = session_pair();
+        let mut frame = client.encrypt_packet(&[1, 2, 3]).unwrap();
+        let last = frame.len() - 1;
+
+        frame[last] ^= 1;
+
+        assert!(server.decrypt_packet(&frame).is_err());
+    }
+
+    #[test]
+    fn it_rejects_replayed_frames() {
+        let (mut client, mut server) = session_pair();
+        let frame = client.encrypt_packet(&[1, 2, 3]).unwrap();
+
+        assert!(server.decrypt_packet(&frame).is_ok());
+        assert!(server.decrypt_packet(&frame).is_err());
+    }
+
+    #[test]
+    fn it_rejects_frames_encrypted_with_the_wrong_key() {
+        let (mut client, _) = session_pair();
+        let (_, mut server) = session_pair();
+        let frame = client.encrypt_packet(&[1, 2, 3]).unwrap();
+
+        assert!(server.decrypt_packet(&frame).is_err());
+    }
+
+    #[test]
+    fn it_measures_latency_and_counts_missed_pongs() {
+        let (mut peer, _) = session_pair();
+        let now = Instant::now();
+
+        peer.ping_sent(1, now);
+        peer.ping_sent(2, now);
+        assert_eq!(peer.missed_pongs(), 1);
+
+        assert!(!peer.pong_received(1, now));
+        assert!(peer.pong_received(2, now + Duration::from_millis(40)));
+        assert_eq!(peer.latency, Some(Duration::from_millis(40)));
+        assert_eq!(peer.missed_pongs(), 0);
+        assert!(!peer.pong_received(2, now));
+    }
+
+    #[test]
+    fn it_matches_replies_with_pending_requests() {
+        let (mut peer, _) = session_pair();
+        let now = Instant::now();
+        let timeout = Duration::from_millis(REQUEST_REPLY_TIMEOUT);
+
+        // Unsolicited replies
+        assert!(!peer.reply_received(SendEvents::PACKET_TYPE, now));
+        assert!(!peer.reply_received(SendTxs::PACKET_TYPE, now));
+
+        peer.request_sent(RequestEvents::PACKET_TYPE, now);
+        peer.request_sent(RequestEventRange::PACKET_TYPE, now);
+        peer.request_sent(RequestTxs::PACKET_TYPE, now);
+        peer.request_sent(Ping::PACKET_TYPE, now);
+
+        assert!(peer.reply_received(SendEvents::PACKET_TYPE, now));
+        assert!(peer.reply_received(SendTxs::PACKET_TYPE, now));
+        assert!(!peer.reply_received(SendTxs::PACKET_TYPE, now));
+
+        // Requests that are not answered in time expire
+        assert!(!peer.reply_received(SendEvents::PACKET_TYPE, now + timeout));
+    }
+
+    #[test]
+    fn it_records_the_requests_sent_to_the_peer() {
+        let (sender, _receiver) = mpsc::channel(8);
+        let addr = "127.0.0.1:44034".parse().unwrap();
+        let mut peer = Peer::new(None, addr, ConnectionType::Client, sender);
+        let request = RequestTxs::new(vec![Hash::random()]);
+
+        assert!(peer.send_packet(request.to_bytes()).is_ok());
+        assert!(peer.reply_received(SendTxs::PACKET_TYPE, Instant::now()));
+        assert!(!peer.reply_received(SendEvents::PACKET_TYPE, Instant::now()));
+    }
+
+    #[test]
+    fn it_drops_packets_when_the_outbound_queue_is_full() {
+        let (sender, _receiver) = mpsc::channel(0);
+        let addr = "127.0.0.1:44034".parse().unwrap();
+        let mut peer = Peer::new(None, addr, ConnectionType::Client, sender);
+
+        assert!(peer.send_packet(vec![1, 2, 3]).is_ok());
+        assert!(peer.send_packet(vec![1, 2, 3]).is_ok());
+        assert_eq!(peer.bytes_out, 7);
+        assert_eq!(peer.dropped_out, 1);
+    }
+
+    #[test]
+    fn it_counts_rate_limited_packets() {
+        let (mut peer, _) = session_pair();
+        let limits = RateLimits {
+            handshake: BucketLimit::new(1, 0),
+            ..RateLimits::default()
+        };
+        let now = Instant::now();
+
+        peer.set_rate_limits(&limits);
+
+        assert!(peer.allow_packet(Ping::PACKET_TYPE, 9, now));
+        assert!(!peer.allow_packet(Ping::PACKET_TYPE, 9, now));
+        assert!(peer.allow_packet(AnnounceTxs::PACKET_TYPE, 9, now));
+        assert_eq!(peer.dropped_in, 1);
+    }
+
+    #[test]
+    fn it_reports_sustained_flooding_once_per_window() {
+        let (mut peer, _) = session_pair();
+        let limits = RateLimits {
+            handshake: BucketLimit::new(0, 0),
+            ..RateLimits::default()
+        };
+        let now = Instant::now();
+
+        peer.set_rate_limits(&limits);
+
+        for _ in 0..MAX_WINDOW_DROPS {
+            assert!(!peer.allow_packet(Ping::PACKET_TYPE, 9, now));
+            assert!(!peer.is_flooding());
+        }
+
+        assert!(!peer.allow_packet(Ping::PACKET_TYPE, 9, now));
+        assert!(peer.is_flooding());
+        assert!(!peer.allow_packet(Ping::PACKET_TYPE, 9, now));
+        assert!(!peer.is_flooding());
+
+        // Drops are counted again in the next window
+        let next = now + Duration::from_secs(DROP_WINDOW);
+
+        assert!(!peer.allow_packet(Ping::PACKET_TYPE, 9, next));
+        assert!(!peer.is_flooding());
+    }
+
+    #[test]
+    fn it_derives_matching_session_keys() {
+        let (client, server) = session_pair();
+
+        assert_eq!(client.rx, server.tx);
+        assert_eq!(client.tx, server.rx);
+        assert!(client.has_session_keys() && server.has_session_keys());
+    }
 }
@@ -0,0 +1,52 @@
+/*
+  Copyright 2018 The Purple Library Authors
+  This file is part of the Purple Library.
+
+  The Purple Library is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  The Purple Library is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with the Purple Library. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use futures::Stream;
+use network::Network;
+use parking_lot::Mutex;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::executor::Spawn;
+use tokio::timer::Interval;
+
+/// Interval in milliseconds at which the peers are pinged.
+const PING_INTERVAL: u64 = 5000;
+
+/// Starts the task that pings the peers and drops the
+/// ones that have stopped answering.
+pub fn start_pinger(network: Arc<Mutex<Network>>) -> Spawn {
+    let interval = Duration::from_millis(PING_INTERVAL);
+
+    let fut = Interval::new(Instant::now() + interval, interval)
+        .map_err(|e| warn!("pinger interval errored = {:?}", e))
+        .for_each(move |now| {
+            let mut network = network.lock();
+
+            for addr in network.ping_peers(now) {
+                info!("Dropping stale peer {}", addr);
+
+                // Dropping the peer closes its outbound
+                // channel which ends the connection.
+                network.remove_peer_with_addr(&addr);
+            }
+
+            Ok(())
+        });
+
+    tokio::spawn(fut)
+}
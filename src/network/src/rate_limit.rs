@@ -0,0 +1,280 @@
+/*
+  Copyright 2018 The Purple Library Authors
+  This file is part of the Purple Library.
+
+  The Purple Library is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  The Purple Library is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with the Purple Library. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use packets::*;
+use std::cmp;
+use std::time::{Duration, Instant};
+
+/// The classes of packets that are rate limited separately
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PacketClass {
+    /// Connect, ping and peer exchange packets
+    Handshake,
+
+    /// Event announcements
+    Gossip,
+
+    /// Event requests and responses
+    Sync,
+
+    /// Transaction announcements, requests and responses
+    TxRelay,
+}
+
+impl PacketClass {
+    /// Returns the class of the packet with the given type.
+    ///
+    /// Unknown packet types are counted as handshake packets.
+    pub fn of(packet_type: u8) -> PacketClass {
+        match packet_type {
+            AnnounceEvents::PACKET_TYPE => PacketClass::Gossip,
+            RequestEvents::PACKET_TYPE
+            | SendEvents::PACKET_TYPE
+            | RequestEventRange::PACKET_TYPE => PacketClass::Sync,
+            AnnounceTxs::PACKET_TYPE | RequestTxs::PACKET_TYPE | SendTxs::PACKET_TYPE => {
+                PacketClass::TxRelay
+            }
+            _ => PacketClass::Handshake,
+        }
+    }
+}
+
+/// The size and refill rate of a token bucket
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct BucketLimit {
+    /// The maximum number of tokens that can be spent at once
+    pub capacity: u64,
+
+    /// The number of tokens added each second
+    pub per_second: u64,
+}
+
+impl BucketLimit {
+    pub fn new(capacity: u64, per_second: u64) -> BucketLimit {
+        BucketLimit {
+            capacity: capacity,
+            per_second: per_second,
+        }
+    }
+}
+
+/// Limits applied to the traffic of each peer.
+///
+/// Packet buckets are spent one token per packet and
+/// the inbound bytes bucket one token per received byte.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct RateLimits {
+    /// Limit of the handshake packets
+    pub handshake: BucketLimit,
+
+    /// Limit of the gossip packets
+    pub gossip: BucketLimit,
+
+    /// Limit of the sync packets
+    pub sync: BucketLimit,
+
+    /// Limit of the transaction relay packets
+    pub tx_relay: BucketLimit,
+
+    /// Limit of the received bytes
+    pub inbound_bytes: BucketLimit,
+
+    /// The number of received frames that can wait to be processed
+    pub inbound_queue: usize,
+
+    /// The number of packets that can wait to be sent
+    pub outbound_queue: usize,
+}
+
+impl Default for RateLimits {
+    fn default() -> RateLimits {
+        RateLimits {
+            handshake: BucketLimit::new(20, 2),
+            gossip: BucketLimit::new(500, 100),
+            sync: BucketLimit::new(200, 50),
+            tx_relay: BucketLimit::new(1000, 200),
+            inbound_bytes: BucketLimit::new(32 * 1024 * 1024, 4 * 1024 * 1024),
+            inbound_queue: 64,
+            outbound_queue: 1024,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct TokenBucket {
+    limit: BucketLimit,
+
+    /// The tokens at the time of the last refill
+    tokens: u64,
+
+    /// The time from which tokens are refilled
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    fn new(limit: BucketLimit, now: Instant) -> TokenBucket {
+        TokenBucket {
+            limit: limit,
+            tokens: limit.capacity,
+            refilled_at: now,
+        }
+    }
+
+    /// Adds the tokens refilled until the given time.
+    fn refill(&mut self, now: Instant) {
+        if now <= self.refilled_at || self.limit.per_second == 0 {
+            return;
+        }
+
+        let elapsed = now.duration_since(self.refilled_at);
+        let elapsed_ms = elapsed.as_secs() * 1000 + u64::from(elapsed.subsec_millis());
+        let added = elapsed_ms.saturating_mul(self.limit.per_second) / 1000;
+
+        if self.tokens.saturating_add(added) >= self.limit.capacity {
+            self.tokens = self.limit.capacity;
+            self.refilled_at = now;
+        } else if added > 0 {
+            // Only move forward by the refilled tokens so
+            // that partial refill periods are not lost.
+            self.tokens += added;
+            self.refilled_at += Duration::from_millis(added * 1000 / self.limit.per_second);
+        }
+    }
+
+    /// Spends the given amount of tokens. Returns `false`
+    /// and spends nothing if there are not enough tokens.
+    fn take(&mut self, amount: u64, now: Instant) -> bool {
+        self.refill(now);
+
+        if self.tokens < amount {
+            return false;
+        }
+
+        self.tokens -= amount;
+        true
+    }
+}
+
+/// The token buckets of a peer
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    handshake: TokenBucket,
+    gossip: TokenBucket,
+    sync: TokenBucket,
+    tx_relay: TokenBucket,
+    inbound_bytes: TokenBucket,
+}
+
+impl RateLimiter {
+    pub fn new(limits: &RateLimits, now: Instant) -> RateLimiter {
+        RateLimiter {
+            handshake: TokenBucket::new(limits.handshake, now),
+            gossip: TokenBucket::new(limits.gossip, now),
+            sync: TokenBucket::new(limits.sync, now),
+            tx_relay: TokenBucket::new(limits.tx_relay, now),
+            inbound_bytes: TokenBucket::new(limits.inbound_bytes, now),
+        }
+    }
+
+    /// Returns `true` if a received packet of the given type and
+    /// size in bytes is within the limits at the given time.
+    pub fn allow(&mut self, packet_type: u8, len: usize, now: Instant) -> bool {
+        let bucket = match PacketClass::of(packet_type) {
+            PacketClass::Handshake => &mut self.handshake,
+            PacketClass::Gossip => &mut self.gossip,
+            PacketClass::Sync => &mut self.sync,
+            PacketClass::TxRelay => &mut self.tx_relay,
+        };
+
+        // Frames larger than the bucket are always dropped
+        let len = cmp::min(len as u64, self.inbound_bytes.limit.capacity + 1);
+
+        if !bucket.take(1, now) {
+            return false;
+        }
+
+        self.inbound_bytes.take(len, now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits() -> RateLimits {
+        RateLimits {
+            gossip: BucketLimit::new(2, 10),
+            inbound_bytes: BucketLimit::new(1000, 1000),
+            ..RateLimits::default()
+        }
+    }
+
+    #[test]
+    fn it_limits_each_class_separately() {
+        let now = Instant::now();
+        let mut limiter = RateLimiter::new(&limits(), now);
+
+        assert!(limiter.allow(AnnounceEvents::PACKET_TYPE, 10, now));
+        assert!(limiter.allow(AnnounceEvents::PACKET_TYPE, 10, now));
+        assert!(!limiter.allow(AnnounceEvents::PACKET_TYPE, 10, now));
+        assert!(limiter.allow(AnnounceTxs::PACKET_TYPE, 10, now));
+
+        // One gossip token is refilled every 100ms
+        assert!(!limiter.allow(
+            AnnounceEvents::PACKET_TYPE,
+            10,
+            now + Duration::from_millis(50)
+        ));
+        assert!(limiter.allow(
+            AnnounceEvents::PACKET_TYPE,
+            10,
+            now + Duration::from_millis(100)
+        ));
+    }
+
+    #[test]
+    fn it_limits_inbound_bytes() {
+        let now = Instant::now();
+        let mut limiter = RateLimiter::new(&limits(), now);
+
+        assert!(!limiter.allow(SendTxs::PACKET_TYPE, 1001, now));
+        assert!(limiter.allow(SendTxs::PACKET_TYPE, 600, now));
+        assert!(!limiter.allow(SendTxs::PACKET_TYPE, 600, now));
+        assert!(limiter.allow(SendTxs::PACKET_TYPE, 600, now + Duration::from_millis(200)));
+    }
+
+    #[test]
+    fn it_classifies_packets() {
+        assert_eq!(
+            PacketClass::of(Connect::PACKET_TYPE),
+            PacketClass::Handshake
+        );
+        assert_eq!(PacketClass::of(Ping::PACKET_TYPE), PacketClass::Handshake);
+        assert_eq!(
+            PacketClass::of(AnnounceEvents::PACKET_TYPE),
+            PacketClass::Gossip
+        );
+        assert_eq!(PacketClass::of(SendEvents::PACKET_TYPE), PacketClass::Sync);
+        assert_eq!(
+            PacketClass::of(RequestTxs::PACKET_TYPE),
+            PacketClass::TxRelay
+        );
+        assert_eq!(PacketClass::of(255), PacketClass::Handshake);
+    }
+}
@@ -0,0 +1,179 @@
+/*
+  Copyright 2018 The Purple Library Authors
+  This file is part of the Purple Library.
+
+  The Purple Library is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  The Purple Library is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with the Purple Library. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+/// The number of penalty points at which a peer is banned
+pub const BAN_THRESHOLD: u32 = 100;
+
+/// The duration in seconds of a ban
+pub const BAN_DURATION: u64 = 86400;
+
+/// The number of seconds it takes for one penalty point to decay
+const DECAY_SECS_PER_POINT: u64 = 6;
+
+/// Behaviour of a peer that is penalized
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Misbehavior {
+    /// The peer has sent a packet that could not be decoded
+    MalformedPacket,
+
+    /// The peer has sent a packet or frame with an invalid signature
+    BadSignature,
+
+    /// The peer has sent an invalid event
+    InvalidEvent,
+
+    /// The peer has sent an invalid transaction
+    InvalidTx,
+
+    /// The peer has sent data that we did not need
+    Flooding,
+}
+
+impl Misbehavior {
+    /// Returns the number of penalty points of the misbehavior.
+    pub fn penalty(&self) -> u32 {
+        match *self {
+            Misbehavior::MalformedPacket => 20,
+            Misbehavior::BadSignature => 50,
+            Misbehavior::InvalidEvent => 50,
+            Misbehavior::InvalidTx => 20,
+            Misbehavior::Flooding => 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Score {
+    /// The penalty points at the time of the last update
+    points: u32,
+
+    /// The time from which the points decay
+    updated_at: Instant,
+}
+
+impl Score {
+    /// Applies the points that have decayed until the given time.
+    fn decay(&mut self, now: Instant) {
+        if now <= self.updated_at {
+            return;
+        }
+
+        let decayed = now.duration_since(self.updated_at).as_secs() / DECAY_SECS_PER_POINT;
+
+        if decayed >= u64::from(self.points) {
+            self.points = 0;
+            self.updated_at = now;
+        } else {
+            // Only move forward by the decayed points so
+            // that partial decay periods are not lost.
+            self.points -= decayed as u32;
+            self.updated_at += Duration::from_secs(decayed * DECAY_SECS_PER_POINT);
+        }
+    }
+}
+
+/// Penalty points of the peers by ip address.
+///
+/// The score is kept by ip address so that it
+/// survives reconnections of the peer.
+#[derive(Debug, Clone, Default)]
+pub struct Reputation {
+    scores: HashMap<IpAddr, Score>,
+}
+
+impl Reputation {
+    pub fn new() -> Reputation {
+        Reputation {
+            scores: HashMap::new(),
+        }
+    }
+
+    /// Adds the penalty of the given misbehavior to the score
+    /// of the given ip address and returns the new score.
+    pub fn penalize(&mut self, ip: IpAddr, misbehavior: Misbehavior, now: Instant) -> u32 {
+        // Forget the scores that have fully decayed
+        self.scores.retain(|_, score| {
+            score.decay(now);
+            score.points > 0
+        });
+
+        let score = self.scores.entry(ip).or_insert(Score {
+            points: 0,
+            updated_at: now,
+        });
+
+        score.points = score.points.saturating_add(misbehavior.penalty());
+        score.points
+    }
+
+    /// Returns the score of the given ip address at the given time.
+    pub fn points(&self, ip: &IpAddr, now: Instant) -> u32 {
+        match self.scores.get(ip) {
+            Some(score) => {
+                let mut score = score.clone();
+                score.decay(now);
+                score.points
+            }
+            None => 0,
+        }
+    }
+
+    /// Resets the score of the given ip address.
+    pub fn clear(&mut self, ip: &IpAddr) {
+        self.scores.remove(ip);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_accumulates_penalties() {
+        let mut reputation = Reputation::new();
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let now = Instant::now();
+
+        reputation.penalize(ip, Misbehavior::MalformedPacket, now);
+        assert_eq!(reputation.penalize(ip, Misbehavior::BadSignature, now), 70);
+        assert_eq!(reputation.points(&"10.0.0.2".parse().unwrap(), now), 0);
+    }
+
+    #[test]
+    fn it_decays_penalties() {
+        let mut reputation = Reputation::new();
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let now = Instant::now();
+        let period = Duration::from_secs(DECAY_SECS_PER_POINT);
+
+        reputation.penalize(ip, Misbehavior::Flooding, now);
+        assert_eq!(reputation.points(&ip, now + period * 4), 6);
+
+        // Partial decay periods are carried over
+        reputation.penalize(ip, Misbehavior::Flooding, now + period / 2);
+        assert_eq!(reputation.points(&ip, now + period * 4), 16);
+        assert_eq!(reputation.points(&ip, now + period * 30), 0);
+
+        reputation.clear(&ip);
+        assert_eq!(reputation.points(&ip, now), 0);
+    }
+}
@@ -0,0 +1,59 @@
+/*
+  Copyright 2018 The Purple Library Authors
+  This file is part of the Purple Library.
+
+  The Purple Library is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  The Purple Library is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with the Purple Library. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use futures::Stream;
+use network::Network;
+use packets::{RequestEvents, MAX_HASHES};
+use parking_lot::Mutex;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::executor::Spawn;
+use tokio::timer::Interval;
+
+/// Interval in milliseconds at which the
+/// event requests are checked.
+const EVENT_SYNC_INTERVAL: u64 = 1000;
+
+/// Starts the task that requests the missing events
+/// and retries the timed out requests.
+pub fn start_event_sync(network: Arc<Mutex<Network>>) -> Spawn {
+    let interval = Duration::from_millis(EVENT_SYNC_INTERVAL);
+
+    let fut = Interval::new(Instant::now() + interval, interval)
+        .map_err(|e| warn!("event sync interval errored = {:?}", e))
+        .for_each(move |now| {
+            let mut network = network.lock();
+            let requests = network.sync_events(now);
+
+            for (addr, hashes) in requests.iter() {
+                debug!("Requesting {} events from {}", hashes.len(), addr);
+
+                for hashes in hashes.chunks(MAX_HASHES) {
+                    let packet = RequestEvents::new(hashes.to_vec());
+
+                    if let Err(err) = network.send_to_peer(addr, packet.to_bytes()) {
+                        debug!("Could not request events from {}: {}", addr, err);
+                    }
+                }
+            }
+
+            Ok(())
+        });
+
+    tokio::spawn(fut)
+}
@@ -0,0 +1,134 @@
+/*
+  Copyright 2018 The Purple Library Authors
+  This file is part of the Purple Library.
+
+  The Purple Library is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  The Purple Library is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with the Purple Library. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use codec::PacketCodec;
+use futures::{Future, Sink, Stream};
+use net2::TcpBuilder;
+use std::fmt::Debug;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::codec::Framed;
+use tokio::io;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::reactor::Handle;
+use tokio_io_timeout::TimeoutStream;
+
+/// Time in milliseconds after which a blocked write to a peer
+/// fails. Idle peers are detected by the pinger instead.
+const PEER_TIMEOUT: u64 = 3000;
+
+const LISTEN_BACKLOG: i32 = 1024;
+
+/// Stream of the frames received from a peer
+pub type FrameStream = Box<dyn Stream<Item = Vec<u8>, Error = io::Error> + Send>;
+
+/// Sink of the frames sent to a peer
+pub type FrameSink = Box<dyn Sink<SinkItem = Vec<u8>, SinkError = io::Error> + Send>;
+
+/// Stream of the sockets of the accepted connections
+pub type Incoming = Box<dyn Stream<Item = Socket, Error = io::Error> + Send>;
+
+/// Future resolving to the socket of an opened connection
+pub type Connecting = Box<dyn Future<Item = Socket, Error = io::Error> + Send>;
+
+/// An established connection over which packet frames are exchanged
+pub struct Socket {
+    /// The address of the remote end of the connection
+    pub addr: SocketAddr,
+
+    /// The frames received from the remote end
+    pub reader: FrameStream,
+
+    /// The frames sent to the remote end
+    pub writer: FrameSink,
+}
+
+/// The means by which connections to peers are opened and accepted.
+pub trait Transport: Debug + Send + Sync {
+    /// Starts listening for connections on the given address.
+    fn listen(&self, addr: &SocketAddr) -> io::Result<Incoming>;
+
+    /// Opens a connection to the given address.
+    fn connect(&self, addr: &SocketAddr) -> Connecting;
+}
+
+/// Transport over TCP sockets using the packet framing codec
+#[derive(Debug, Clone, Default)]
+pub struct TcpTransport;
+
+impl Transport for TcpTransport {
+    fn listen(&self, addr: &SocketAddr) -> io::Result<Incoming> {
+        // A connection that is reset before its address can
+        // be read is skipped instead of ending the stream.
+        let incoming = bind_listener(addr)?
+            .incoming()
+            .filter_map(|sock| match tcp_socket(sock) {
+                Ok(sock) => Some(sock),
+                Err(err) => {
+                    debug!("Dropping incoming connection: {:?}", err);
+                    None
+                }
+            });
+
+        Ok(Box::new(incoming))
+    }
+
+    fn connect(&self, addr: &SocketAddr) -> Connecting {
+        Box::new(TcpStream::connect(addr).and_then(tcp_socket))
+    }
+}
+
+/// Wraps a TCP stream into a framed socket.
+fn tcp_socket(sock: TcpStream) -> io::Result<Socket> {
+    let addr = sock.peer_addr()?;
+    let mut sock = TimeoutStream::new(sock);
+
+    // Peers that stop answering pings are dropped by the
+    // pinger so only writes are subject to a timeout.
+    sock.set_write_timeout(Some(Duration::from_millis(PEER_TIMEOUT)));
+
+    let (writer, reader) = Framed::new(sock, PacketCodec::default()).split();
+
+    Ok(Socket {
+        addr: addr,
+        reader: Box::new(reader),
+        writer: Box::new(writer),
+    })
+}
+
+/// Binds a TCP listener to the given address.
+///
+/// IPv6 sockets are bound with `IPV6_V6ONLY` so that
+/// a node can listen on both `0.0.0.0` and `::` with
+/// the same port.
+fn bind_listener(addr: &SocketAddr) -> io::Result<TcpListener> {
+    let builder = match addr {
+        SocketAddr::V4(_) => TcpBuilder::new_v4()?,
+        SocketAddr::V6(_) => {
+            let builder = TcpBuilder::new_v6()?;
+            builder.only_v6(true)?;
+            builder
+        }
+    };
+
+    builder.reuse_address(true)?;
+    builder.bind(addr)?;
+
+    let listener = builder.listen(LISTEN_BACKLOG)?;
+    TcpListener::from_std(listener, &Handle::default())
+}
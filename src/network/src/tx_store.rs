@@ -0,0 +1,54 @@
+/*
+  Copyright 2018 The Purple Library Authors
+  This file is part of the Purple Library.
+
+  The Purple Library is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  The Purple Library is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with the Purple Library. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use crypto::Hash;
+use std::fmt::Debug;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TxStoreErr {
+    /// The transaction with the given hash is already stored
+    AlreadyStored(Hash),
+
+    /// The transaction with the given hash is well formed but
+    /// could not be stored e.g. because it is not valid against
+    /// our current ledger state or because the pool is full.
+    Rejected(Hash),
+
+    /// The transaction could not be decoded or has an invalid hash
+    InvalidTx(&'static str),
+}
+
+/// Storage of the transactions that are relayed between peers.
+///
+/// The network layer only handles serialized transactions so
+/// that it does not depend on the ledger and the mempool.
+///
+/// Implementations synchronize their own state so that
+/// transactions are validated without holding the network lock.
+pub trait TxStore: Debug + Send + Sync {
+    /// Returns `true` if the transaction with the given hash is stored.
+    fn contains(&self, hash: &Hash) -> bool;
+
+    /// Returns the serialized transaction with the given hash.
+    fn get(&self, hash: &Hash) -> Option<Vec<u8>>;
+
+    /// Validates and stores a serialized transaction.
+    ///
+    /// Returns the hash of the stored transaction.
+    fn push(&self, tx: &[u8]) -> Result<Hash, TxStoreErr>;
+}
@@ -38,6 +38,38 @@ impl PersistentDb {
             cf: cf,
         }
     }
+
+    /// Writes the given values under the given keys in a
+    /// single atomic transaction. A value of `None` removes
+    /// the key instead.
+    pub fn write_batch<I>(&mut self, batch: I)
+    where
+        I: IntoIterator<Item = (Vec<u8>, Option<Vec<u8>>)>,
+    {
+        let db_ref = &self.db_ref;
+        let mut tx = db_ref.transaction();
+
+        for (key, val) in batch {
+            match val {
+                Some(val) => tx.put(self.cf, &key, &val),
+                None => tx.delete(self.cf, &key),
+            }
+        }
+
+        db_ref.write(tx).unwrap();
+    }
+
+    /// Returns the stored keys that start with the
+    /// given prefix along with their values.
+    pub fn prefixed(&self, prefix: &[u8]) -> Vec<(Vec<u8>, Vec<u8>)> {
+        match self.db_ref.iter_from_prefix(self.cf, prefix) {
+            Some(iter) => iter
+                .take_while(|(key, _)| key.starts_with(prefix))
+                .map(|(key, val)| (key.to_vec(), val.to_vec()))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
 }
 
 impl HashDB<BlakeDbHasher, ElasticArray128<u8>> for PersistentDb {
@@ -177,6 +209,35 @@ mod tests {
         assert!(persistent_db.contains(&key));
     }
 
+    #[test]
+    fn it_writes_batches() {
+        let config = DatabaseConfig::with_columns(None);
+        let dir = tempdir().unwrap();
+        let db = Database::open(&config, dir.path().to_str().unwrap()).unwrap();
+        let db_ref = Arc::new(db);
+        let mut persistent_db = PersistentDb::new(db_ref, None);
+        let removed = persistent_db.insert(b"Removed");
+        let prefix = crypto::hash_slice(b"prefix");
+        let mut first = prefix.0.to_vec();
+        let mut second = prefix.0.to_vec();
+
+        first.push(1);
+        second.push(2);
+
+        persistent_db.write_batch(vec![
+            (first.clone(), Some(b"First".to_vec())),
+            (second.clone(), Some(b"Second".to_vec())),
+            (removed.0.to_vec(), None),
+        ]);
+
+        assert!(!persistent_db.contains(&removed));
+        assert_eq!(
+            persistent_db.prefixed(&prefix.0),
+            vec![(first, b"First".to_vec()), (second, b"Second".to_vec())]
+        );
+        assert!(persistent_db.prefixed(&[0; 33]).is_empty());
+    }
+
     #[test]
     fn remove() {
         let config = DatabaseConfig::with_columns(None);
@@ -0,0 +1,61 @@
+/*
+  Copyright 2018 The Purple Library Authors
+  This file is part of the Purple Library.
+
+  The Purple Library is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  The Purple Library is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with the Purple Library. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use network::RateLimits;
+use std::fs;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+
+/// Node settings loaded from a TOML configuration file.
+///
+/// All settings are optional and are overridden by
+/// their command line equivalents.
+#[derive(Deserialize, Debug, Default)]
+pub struct Config {
+    /// The addresses the node listens on for peer connections
+    pub listen_addresses: Option<Vec<IpAddr>>,
+
+    /// The port the node listens on for peer connections
+    pub port: Option<u16>,
+
+    /// The directory in which the node stores its data
+    pub data_dir: Option<PathBuf>,
+
+    /// The hex encoded node ids of the initial validator set
+    pub validators: Option<Vec<String>>,
+
+    /// The limits applied to the traffic of each peer.
+    ///
+    /// Omitted limits keep their default values.
+    pub rate_limits: Option<RateLimits>,
+}
+
+impl Config {
+    /// Loads the configuration file at the given path.
+    pub fn load(path: &Path) -> Result<Config, String> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) => return Err(format!("Unable to read config file: {}", err)),
+        };
+
+        match toml::from_str(&contents) {
+            Ok(config) => Ok(config),
+            Err(err) => Err(format!("Invalid config file: {}", err)),
+        }
+    }
+}
@@ -0,0 +1,290 @@
+/*
+  Copyright 2018 The Purple Library Authors
+  This file is part of the Purple Library.
+
+  The Purple Library is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  The Purple Library is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with the Purple Library. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use causality::Stamp;
+use consensus::{CGError, ConsensusMachine};
+use crypto::Hash;
+use events::Event;
+use network::{network_id, EventStore, EventStoreErr, NodeId};
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+/// Stores the events gossiped by the network
+/// layer in the consensus machine.
+#[derive(Debug)]
+pub struct ConsensusStore {
+    consensus: Arc<Mutex<ConsensusMachine>>,
+}
+
+impl ConsensusStore {
+    pub fn new(consensus: Arc<Mutex<ConsensusMachine>>) -> ConsensusStore {
+        ConsensusStore {
+            consensus: consensus,
+        }
+    }
+}
+
+impl EventStore for ConsensusStore {
+    fn contains(&self, hash: &Hash) -> bool {
+        self.consensus.lock().contains(hash)
+    }
+
+    fn get(&self, hash: &Hash) -> Option<Vec<u8>> {
+        let event = self.consensus.lock().get(hash)?;
+        event.to_bytes().ok()
+    }
+
+    fn push(&self, event: &[u8]) -> Result<Hash, EventStoreErr> {
+        let event = Event::from_bytes(event).map_err(EventStoreErr::InvalidEvent)?;
+        let hash = match event.hash() {
+            Some(hash) => hash,
+            None => return Err(EventStoreErr::InvalidEvent("Hash field is missing")),
+        };
+
+        match self.consensus.lock().push(Arc::new(event)) {
+            Ok(()) => Ok(hash),
+            Err(CGError::AlreadyInCG) => Err(EventStoreErr::AlreadyStored(hash)),
+
+            // The event may be valid for a node that is
+            // further along or behind in the total order.
+            Err(ref err) if err.is_transient() => Err(EventStoreErr::Rejected(hash)),
+            Err(_) => Err(EventStoreErr::InvalidEvent("Invalid event")),
+        }
+    }
+
+    fn highest(&self) -> Hash {
+        self.consensus.lock().highest().hash().unwrap()
+    }
+
+    fn missing(&self) -> Vec<Hash> {
+        self.consensus.lock().missing_parents()
+    }
+
+    fn following(&self, hash: &Hash, count: usize) -> Vec<Vec<u8>> {
+        self.consensus
+            .lock()
+            .following(hash, count)
+            .iter()
+            .filter_map(|event| event.to_bytes().ok())
+            .collect()
+    }
+}
+
+/// Returns the root event of the causal graph of the
+/// network with the given name.
+///
+/// There is no genesis event yet so every node starts
+/// from the same placeholder event whose hash is the
+/// id of the network.
+pub fn genesis_event(network_name: &str) -> Arc<Event> {
+    Arc::new(Event::Dummy(
+        NodeId::new([0; 32]),
+        network_id(network_name),
+        None,
+        Stamp::seed(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crypto::Identity;
+    use events::Heartbeat;
+    use futures::{future, task, Async, Poll};
+    use network::{connect_to_peer, start_listener, MemoryNet, Network};
+    use std::net::{IpAddr, SocketAddr};
+    use std::sync::atomic::AtomicBool;
+    use tokio::runtime::current_thread;
+
+    const PORT: u16 = 44034;
+    const NETWORK_NAME: &str = "test";
+
+    fn node_ip(i: usize) -> IpAddr {
+        IpAddr::V4([10, 0, (i / 250) as u8, (i % 250 + 1) as u8].into())
+    }
+
+    fn node_addr(i: usize) -> SocketAddr {
+        SocketAddr::new(node_ip(i), PORT)
+    }
+
+    /// Creates a node whose events are stored in a consensus
+    /// machine in which the given node is the only validator.
+    fn node(net: &MemoryNet, i: usize, validator: &NodeId) -> Arc<Mutex<Network>> {
+        let identity = Identity::new();
+        let node_id = NodeId(*identity.pkey());
+        let mut consensus = ConsensusMachine::new(node_id.clone(), genesis_event(NETWORK_NAME));
+        let mut network = Network::new(node_id, identity.skey().clone(), NETWORK_NAME.to_owned());
+
+        consensus.add_validator(validator);
+        network.set_transport(Arc::new(net.transport(node_ip(i))));
+        network.set_event_store(Box::new(ConsensusStore::new(Arc::new(Mutex::new(
+            consensus,
+        )))));
+
+        Arc::new(Mutex::new(network))
+    }
+
+    fn connect(nodes: &[Arc<Mutex<Network>>], from: usize, to: usize) {
+        connect_to_peer(
+            nodes[from].clone(),
+            Arc::new(AtomicBool::new(true)),
+            nodes.len(),
+            &node_addr(to),
+        );
+    }
+
+    fn handshakes(network: &Arc<Mutex<Network>>) -> usize {
+        network
+            .lock()
+            .peers()
+            .filter(|peer| peer.id.is_some())
+            .count()
+    }
+
+    fn contains(network: &Arc<Mutex<Network>>, hash: &Hash) -> bool {
+        network.lock().event_store().unwrap().contains(hash)
+    }
+
+    fn heartbeat(identity: &Identity, parent: &Event) -> Event {
+        let mut heartbeat = Heartbeat {
+            node_id: NodeId(*identity.pkey()),
+            stamp: Stamp::seed().join(parent.stamp().peek()).event(),
+            root_hash: Some(Hash::random()),
+            hash: None,
+            parent_hash: parent.hash().unwrap(),
+            signature: None,
+            transactions: Vec::new(),
+        };
+
+        heartbeat.sign(identity.skey().clone());
+        heartbeat.hash();
+
+        Event::Heartbeat(heartbeat)
+    }
+
+    /// Runs the tasks spawned on the given runtime until the
+    /// given condition holds or until the given number of
+    /// scheduler steps have run.
+    fn run_until<F: Fn() -> bool>(
+        runtime: &mut current_thread::Runtime,
+        steps: usize,
+        condition: F,
+    ) -> bool {
+        let mut remaining = steps;
+
+        runtime
+            .block_on(future::poll_fn(|| -> Poll<bool, ()> {
+                if condition() {
+                    return Ok(Async::Ready(true));
+                }
+
+                if remaining == 0 {
+                    return Ok(Async::Ready(false));
+                }
+
+                // Yield to the other tasks and poll again on the next step
+                remaining -= 1;
+                task::current().notify();
+                Ok(Async::NotReady)
+            }))
+            .unwrap()
+    }
+
+    #[test]
+    fn it_gossips_and_syncs_events_between_simulated_nodes() {
+        const SIDE: usize = 12;
+
+        let net = MemoryNet::new(42);
+        let validator = Identity::new();
+        let validator_id = NodeId(*validator.pkey());
+        let nodes: Vec<Arc<Mutex<Network>>> = (0..SIDE * 2)
+            .map(|i| node(&net, i, &validator_id))
+            .collect();
+        let side_a: Vec<IpAddr> = (0..SIDE).map(node_ip).collect();
+        let side_b: Vec<IpAddr> = (SIDE..SIDE * 2).map(node_ip).collect();
+        let mut runtime = current_thread::Runtime::new().unwrap();
+
+        net.partition(&side_a, &side_b);
+
+        let nodes_clone = nodes.clone();
+        runtime
+            .block_on(future::lazy(move || -> Result<(), ()> {
+                let nodes = nodes_clone;
+                let max_peers = nodes.len();
+
+                for (i, network) in nodes.iter().enumerate() {
+                    let accept_connections = Arc::new(AtomicBool::new(true));
+                    start_listener(
+                        network.clone(),
+                        accept_connections,
+                        max_peers,
+                        &node_addr(i),
+                    );
+                }
+
+                // Each node dials two nodes of its side and one
+                // node of the other side, which is unreachable.
+                for side in [0, SIDE].iter() {
+                    for i in 0..SIDE {
+                        connect(&nodes, side + i, side + (i + 1) % SIDE);
+                        connect(&nodes, side + i, side + (i + 5) % SIDE);
+                        connect(&nodes, side + i, (side + SIDE + i) % (SIDE * 2));
+                    }
+                }
+
+                Ok(())
+            }))
+            .unwrap();
+
+        assert!(run_until(&mut runtime, 10000, || nodes
+            .iter()
+            .all(|n| handshakes(n) == 4)));
+
+        // The event is gossiped to the first side only
+        let event = heartbeat(&validator, &genesis_event(NETWORK_NAME));
+        let hash = {
+            let mut network = nodes[0].lock();
+            let event_store = network.event_store().unwrap();
+            let hash = event_store.push(&event.to_bytes().unwrap()).unwrap();
+
+            network.announce_event(hash, None);
+            hash
+        };
+
+        assert!(run_until(&mut runtime, 10000, || nodes[..SIDE]
+            .iter()
+            .all(|n| contains(n, &hash))));
+        assert!(nodes[SIDE..].iter().all(|n| !contains(n, &hash)));
+
+        // Once healed, a node of the second side syncs the
+        // event on handshake and gossips it to its side.
+        net.heal();
+
+        let nodes_clone = nodes.clone();
+        runtime
+            .block_on(future::lazy(move || -> Result<(), ()> {
+                connect(&nodes_clone, SIDE, 0);
+                Ok(())
+            }))
+            .unwrap();
+
+        assert!(run_until(&mut runtime, 10000, || nodes
+            .iter()
+            .all(|n| contains(n, &hash))));
+    }
+}
@@ -0,0 +1,222 @@
+/*
+  Copyright 2018 The Purple Library Authors
+  This file is part of the Purple Library.
+
+  The Purple Library is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  The Purple Library is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with the Purple Library. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use account::Balance;
+use byteorder::{BigEndian, ReadBytesExt};
+use crypto::Hash;
+use fetch_ledger_root;
+use patricia_trie::{Trie, TrieDB};
+use persistence::{BlakeDbHasher, Codec, PersistentDb};
+use std::io::Cursor;
+use transactions::Tx;
+
+/// Opens the ledger trie with the given root hash.
+pub fn open_ledger<'a>(
+    ledger: &'a PersistentDb,
+    root: &'a Hash,
+) -> Result<TrieDB<'a, BlakeDbHasher, Codec>, &'static str> {
+    match TrieDB::<BlakeDbHasher, Codec>::new(ledger, root) {
+        Ok(trie) => Ok(trie),
+        Err(_) => Err("Unable to open the ledger"),
+    }
+}
+
+/// Reads the nonce of the account with the given
+/// hex encoded address from the ledger.
+pub fn read_nonce(
+    trie: &TrieDB<BlakeDbHasher, Codec>,
+    address: &str,
+) -> Result<Option<u64>, &'static str> {
+    // The key of a nonce has the following format:
+    // `<account-address>.n`
+    let nonce_key = format!("{}.n", address);
+
+    match trie.get(nonce_key.as_bytes()) {
+        Ok(Some(nonce)) => {
+            let mut rdr = Cursor::new(nonce.to_vec());
+
+            match rdr.read_u64::<BigEndian>() {
+                Ok(nonce) => Ok(Some(nonce)),
+                Err(_) => Err("Bad nonce"),
+            }
+        }
+        Ok(None) => Ok(None),
+        Err(_) => Err("Unable to read from the ledger"),
+    }
+}
+
+/// Reads the balance of the given hex encoded currency
+/// hash of the account with the given hex encoded address.
+pub fn read_balance(
+    trie: &TrieDB<BlakeDbHasher, Codec>,
+    address: &str,
+    asset_hash: &str,
+) -> Result<Option<Balance>, &'static str> {
+    // The key of a currency entry has the following format:
+    // `<account-address>.<currency-hash>`
+    let cur_key = format!("{}.{}", address, asset_hash);
+
+    match trie.get(cur_key.as_bytes()) {
+        Ok(Some(balance)) => match Balance::from_bytes(&balance) {
+            Ok(balance) => Ok(Some(balance)),
+            Err(_) => Err("Bad balance"),
+        },
+        Ok(None) => Ok(None),
+        Err(_) => Err("Unable to read from the ledger"),
+    }
+}
+
+/// Validates a transaction against the current state of the
+/// ledger and returns the nonce of the account that created it.
+///
+/// A transaction is valid if it is signed by its creator and
+/// if its creator is able to pay the transaction fee. The hash
+/// of the transaction must already have been verified.
+pub fn validate_tx(
+    node_storage: &PersistentDb,
+    ledger: &PersistentDb,
+    tx: &mut Tx,
+) -> Result<u64, &'static str> {
+    if !tx.verify_sig() {
+        return Err("Invalid transaction signature");
+    }
+
+    let root = fetch_ledger_root(node_storage)?;
+    let trie = open_ledger(ledger, &root)?;
+    let creator = hex::encode(tx.creator().to_bytes());
+    let fee_hash = hex::encode(&tx.fee_hash().0);
+
+    let balance = match read_balance(&trie, &creator, &fee_hash)? {
+        Some(balance) => balance,
+        None => return Err("The transaction creator cannot pay the fee"),
+    };
+
+    if balance < tx.fee() {
+        return Err("The transaction creator cannot pay the fee");
+    }
+
+    // Accounts that are not yet in the ledger have a nonce of 0
+    Ok(read_nonce(&trie, &creator)?.unwrap_or(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use account::{Address, NormalAddress};
+    use crypto::Identity;
+    use elastic_array::ElasticArray128;
+    use hashdb::HashDB;
+    use kvdb_rocksdb::{Database, DatabaseConfig};
+    use patricia_trie::{TrieDBMut, TrieMut};
+    use std::sync::Arc;
+    use tempfile::{tempdir, TempDir};
+    use transactions::CreateCurrency;
+
+    /// Opens a node storage and a ledger in which the given
+    /// account owns the given balance of the given asset.
+    fn ledger_with_balance(
+        address: &NormalAddress,
+        asset_hash: &Hash,
+        balance: &[u8],
+    ) -> (TempDir, PersistentDb, PersistentDb) {
+        let config = DatabaseConfig::with_columns(Some(2));
+        let dir = tempdir().unwrap();
+        let db = Arc::new(Database::open(&config, dir.path().to_str().unwrap()).unwrap());
+        let mut node_storage = PersistentDb::new(db.clone(), Some(0));
+        let mut ledger = PersistentDb::new(db, Some(1));
+        let mut root = Hash::NULL_RLP;
+
+        {
+            let mut trie = TrieDBMut::<BlakeDbHasher, Codec>::new(&mut ledger, &mut root);
+            let address = hex::encode(Address::Normal(*address).to_bytes());
+            let cur_key = format!("{}.{}", address, hex::encode(&asset_hash.0));
+
+            trie.insert(cur_key.as_bytes(), balance).unwrap();
+            trie.commit();
+        }
+
+        node_storage.emplace(
+            crypto::hash_slice(b"ledger_root"),
+            ElasticArray128::from_slice(&root.0),
+        );
+
+        (dir, node_storage, ledger)
+    }
+
+    fn create_currency(id: &Identity, fee_hash: Hash) -> CreateCurrency {
+        let creator = NormalAddress::from_pkey(*id.pkey());
+        let mut tx = CreateCurrency {
+            creator,
+            receiver: Address::Normal(creator),
+            asset_hash: Hash::random(),
+            coin_supply: 10000,
+            precision: 18,
+            fee_hash,
+            fee: Balance::from_bytes(b"1.0").unwrap(),
+            hash: None,
+            signature: None,
+        };
+
+        tx.sign(id.skey().clone());
+        tx.hash();
+        tx
+    }
+
+    #[test]
+    fn it_validates_transactions() {
+        let id = Identity::new();
+        let fee_hash = crypto::hash_slice(b"purple");
+        let creator = NormalAddress::from_pkey(*id.pkey());
+        let (_dir, node_storage, ledger) = ledger_with_balance(&creator, &fee_hash, b"10.0");
+        let mut tx = Tx::CreateCurrency(create_currency(&id, fee_hash));
+
+        assert_eq!(validate_tx(&node_storage, &ledger, &mut tx), Ok(0));
+    }
+
+    #[test]
+    fn it_rejects_transactions_with_forged_signatures() {
+        let id = Identity::new();
+        let forger = Identity::new();
+        let fee_hash = crypto::hash_slice(b"purple");
+        let creator = NormalAddress::from_pkey(*id.pkey());
+        let (_dir, node_storage, ledger) = ledger_with_balance(&creator, &fee_hash, b"10.0");
+
+        // Signed by another key on behalf of the creator
+        let mut forged = create_currency(&id, fee_hash);
+        forged.sign(forger.skey().clone());
+        forged.hash();
+
+        // Modified after being signed and hashed again
+        let mut tampered = create_currency(&id, fee_hash);
+        tampered.coin_supply = 20000;
+        tampered.hash();
+
+        let mut forged = Tx::CreateCurrency(forged);
+        let mut tampered = Tx::CreateCurrency(tampered);
+
+        assert!(forged.verify_hash() && tampered.verify_hash());
+        assert_eq!(
+            validate_tx(&node_storage, &ledger, &mut forged),
+            Err("Invalid transaction signature")
+        );
+        assert_eq!(
+            validate_tx(&node_storage, &ledger, &mut tampered),
+            Err("Invalid transaction signature")
+        );
+    }
+}
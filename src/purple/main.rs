@@ -16,100 +16,216 @@
   along with the Purple Library. If not, see <http://www.gnu.org/licenses/>.
 */
 
+#[cfg(test)]
+extern crate tempfile;
+
 #[macro_use]
 extern crate log;
 #[macro_use]
 extern crate unwrap;
 #[macro_use]
 extern crate jsonrpc_macros;
+#[macro_use]
+extern crate serde_derive;
 
+extern crate account;
+extern crate byteorder;
+extern crate causality;
 extern crate clap;
+extern crate consensus;
 extern crate crypto;
 extern crate dirs;
 extern crate elastic_array;
 extern crate env_logger;
+extern crate events;
 extern crate futures;
 extern crate hashdb;
+extern crate hex;
 extern crate itc;
 extern crate jsonrpc_core;
+extern crate jsonrpc_http_server;
 extern crate jump;
 extern crate kvdb;
 extern crate kvdb_rocksdb;
+extern crate mempool;
 extern crate network;
 extern crate parking_lot;
+extern crate patricia_trie;
 extern crate persistence;
+extern crate rlp;
+extern crate serde;
 extern crate tokio;
+extern crate toml;
+extern crate transactions;
+
+mod config;
+mod event_store;
+mod ledger;
+mod rpc;
+mod tx_store;
 
 use clap::{App, Arg};
-use crypto::Identity;
+use config::Config;
+use consensus::{ConsensusDb, ConsensusMachine};
+use crypto::{Hash, Identity, SecretKey};
 use elastic_array::ElasticArray128;
+use event_store::{genesis_event, ConsensusStore};
+use events::Event;
 use futures::future::ok;
 use futures::Future;
 use hashdb::HashDB;
 use kvdb_rocksdb::{Database, DatabaseConfig};
+use mempool::Mempool;
 use network::*;
 use parking_lot::Mutex;
 use persistence::PersistentDb;
+use rpc::start_rpc;
 use std::alloc::System;
-use std::path::Path;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
 use std::sync::atomic::AtomicBool;
+use std::sync::mpsc::Receiver;
 use std::sync::Arc;
+use std::thread;
+use tx_store::MempoolStore;
 
 // Enforce usage of system allocator.
 #[global_allocator]
 static GLOBAL: System = System;
 
-const NUM_OF_COLUMNS: u32 = 3;
+const NUM_OF_COLUMNS: u32 = 4;
 const DEFAULT_NETWORK_NAME: &'static str = "purple";
+const DEFAULT_RPC_PORT: u16 = 44035;
+const DEFAULT_LISTEN_ADDRESS: &'static str = "127.0.0.1";
 
 fn main() {
     env_logger::init();
 
     let argv = parse_cli_args();
-    let db = Arc::new(open_database(&argv.network_name));
+    let db = Arc::new(open_database(&argv.data_dir, &argv.network_name));
 
     let mut node_storage = PersistentDb::new(db.clone(), Some(1));
-    let ledger = PersistentDb::new(db, Some(2));
+    let ledger = PersistentDb::new(db.clone(), Some(2));
+    let consensus_db = ConsensusDb::new(PersistentDb::new(db, Some(3)));
 
-    let node_id = fetch_node_id(&mut node_storage);
+    let (node_id, skey) = fetch_credentials(&mut node_storage);
+
+    // A node that is not given an initial validator
+    // set is the only validator of its network.
+    let validators = if argv.validators.is_empty() {
+        vec![node_id.clone()]
+    } else {
+        argv.validators.clone()
+    };
+
+    let consensus = Arc::new(Mutex::new(load_consensus(
+        &node_id,
+        &argv.network_name,
+        &validators,
+        consensus_db,
+    )));
     let network = Arc::new(Mutex::new(Network::new(
         node_id,
+        skey,
         argv.network_name.to_owned(),
     )));
+
+    network.lock().set_rate_limits(argv.rate_limits.clone());
+
+    // Restore the bans of misbehaving peers
+    load_ban_list(&network, &node_storage);
+
+    let finalized = consensus.lock().subscribe();
+
+    // Hand the gossiped events to the consensus machine
+    network
+        .lock()
+        .set_event_store(Box::new(ConsensusStore::new(consensus)));
+
     let accept_connections = Arc::new(AtomicBool::new(true));
+    let mempool = Arc::new(Mutex::new(Mempool::new(
+        argv.mempool_size as usize * 1024 * 1024,
+    )));
+
+    // Relay the transactions that are valid against our ledger
+    network.lock().set_tx_store(Box::new(MempoolStore::new(
+        mempool.clone(),
+        node_storage.clone(),
+        ledger.clone(),
+    )));
+
+    // Drop the transactions that have been included
+    // in finalized heartbeats from the mempool.
+    start_mempool_cleaner(finalized, mempool.clone());
+
+    // Start the JSON-RPC server. The server is
+    // shut down when the handle is dropped.
+    let _rpc_server = if argv.disable_rpc {
+        None
+    } else {
+        let rpc_addr = SocketAddr::new("127.0.0.1".parse().unwrap(), argv.rpc_port);
+
+        Some(start_rpc(
+            &rpc_addr,
+            network.clone(),
+            node_storage.clone(),
+            ledger,
+            mempool,
+        ))
+    };
 
     // Start the tokio runtime
     tokio::run(ok(()).and_then(move |_| {
         // Start listening to connections
-        start_listener(network.clone(), accept_connections.clone(), argv.max_peers);
+        for addr in argv.listen_addresses.iter() {
+            start_listener(
+                network.clone(),
+                accept_connections.clone(),
+                argv.max_peers,
+                addr,
+            );
+        }
 
         // Start bootstrap process
         bootstrap(
-            network,
+            network.clone(),
             accept_connections,
             node_storage.clone(),
             argv.max_peers,
+            argv.outbound_peers,
         );
 
+        // Request the missing events from our peers
+        start_event_sync(network.clone());
+        start_pinger(network.clone());
+
+        // Periodically persist the known peer addresses
+        start_bootstrap_cache_writer(network, node_storage.clone());
+
         Ok(())
     }));
 }
 
-// Fetch stored node id or create new identity and store it
-fn fetch_node_id(db: &mut PersistentDb) -> NodeId {
+// Fetch stored node id and secret key or create
+// new identity and store it
+fn fetch_credentials(db: &mut PersistentDb) -> (NodeId, SecretKey) {
     let node_id_key = crypto::hash_slice(b"node_id");
+    let node_skey_key = crypto::hash_slice(b"node_skey");
 
-    match db.get(&node_id_key) {
-        Some(id) => {
-            let mut buf = [0; 32];
-            buf.copy_from_slice(&id);
+    match (db.get(&node_id_key), db.get(&node_skey_key)) {
+        (Some(id), Some(skey)) => {
+            let mut id_buf = [0; 32];
+            let mut skey_buf = [0; 64];
 
-            NodeId::new(buf)
+            id_buf.copy_from_slice(&id);
+            skey_buf.copy_from_slice(&skey);
+
+            (NodeId::new(id_buf), SecretKey(skey_buf))
         }
-        None => {
+        _ => {
             // Create new identity and write keys to database
             let identity = Identity::new();
-            let node_skey_key = crypto::hash_slice(b"node_skey");
 
             let bin_pkey = identity.pkey().0;
             let bin_skey = identity.skey().0;
@@ -117,25 +233,139 @@ fn fetch_node_id(db: &mut PersistentDb) -> NodeId {
             db.emplace(node_id_key, ElasticArray128::<u8>::from_slice(&bin_pkey));
             db.emplace(node_skey_key, ElasticArray128::<u8>::from_slice(&bin_skey));
 
-            NodeId::new(bin_pkey)
+            (NodeId::new(bin_pkey), identity.skey().clone())
+        }
+    }
+}
+
+// Fetch the stored root hash of the ledger trie or
+// the root of an empty trie if there is none.
+//
+// Nothing applies finalized transactions to the ledger
+// yet so no root is ever stored and the account RPCs
+// read the empty trie until a ledger applier exists.
+fn fetch_ledger_root(db: &PersistentDb) -> Result<Hash, &'static str> {
+    let ledger_root_key = crypto::hash_slice(b"ledger_root");
+
+    match db.get(&ledger_root_key) {
+        Some(root) => {
+            if root.len() != 32 {
+                return Err("Bad ledger root");
+            }
+
+            let mut buf = [0; 32];
+            buf.copy_from_slice(&root);
+
+            Ok(Hash(buf))
         }
+        None => Ok(Hash::NULL_RLP),
     }
 }
 
-fn open_database(network_name: &str) -> Database {
-    let config = DatabaseConfig::with_columns(Some(NUM_OF_COLUMNS));
-    let path = Path::new(&dirs::home_dir().unwrap())
-        .join("purple")
-        .join(network_name)
-        .join("db");
+// Load the stored state of the consensus machine. Panics
+// if the stored state is not consistent since starting
+// from the genesis event would discard the stored events.
+//
+// The given validators are the initial validator set, which
+// is only used if there is no stored validator set.
+fn load_consensus(
+    node_id: &NodeId,
+    network_name: &str,
+    validators: &[NodeId],
+    db: ConsensusDb,
+) -> ConsensusMachine {
+    let genesis = genesis_event(network_name);
+
+    let mut consensus = match ConsensusMachine::load(node_id.clone(), genesis, db) {
+        Ok(consensus) => consensus,
+        Err(err) => panic!("Invalid consensus state: {}", err),
+    };
+
+    if consensus.validator_count() == 0 {
+        for validator in validators.iter() {
+            consensus.add_validator(validator);
+        }
+    }
+
+    info!(
+        "Loaded consensus state with {} finalized events and {} validators",
+        consensus.finalized_count(),
+        consensus.validator_count()
+    );
+
+    consensus
+}
+
+// Parse a hex encoded node id.
+fn parse_node_id(hex_id: &str) -> Option<NodeId> {
+    let bin = hex::decode(hex_id).ok()?;
+
+    if bin.len() != 32 {
+        return None;
+    }
+
+    let mut buf = [0; 32];
+    buf.copy_from_slice(&bin);
+
+    Some(NodeId::new(buf))
+}
+
+// Remove the transactions of each finalized heartbeat from the
+// mempool. This stops once the consensus machine is dropped.
+fn start_mempool_cleaner(finalized: Receiver<Arc<Event>>, mempool: Arc<Mutex<Mempool>>) {
+    thread::spawn(move || {
+        for event in finalized.iter() {
+            if let Event::Heartbeat(ref heartbeat) = *event {
+                mempool.lock().remove_included(heartbeat);
+            }
+        }
+    });
+}
+
+fn open_database(data_dir: &Path, network_name: &str) -> Database {
+    let path = data_dir.join(network_name).join("db");
 
-    Database::open(&config, path.to_str().unwrap()).unwrap()
+    open_columns(path.to_str().unwrap(), NUM_OF_COLUMNS).unwrap()
+}
+
+// Open the database at the given path with the given number
+// of columns. A database that has been created with fewer
+// columns is opened with its own number of columns and the
+// missing columns are added.
+fn open_columns(path: &str, columns: u32) -> io::Result<Database> {
+    let config = DatabaseConfig::with_columns(Some(columns));
+
+    match Database::open(&config, path) {
+        Ok(db) => Ok(db),
+        Err(err) => {
+            for existing in (1..columns).rev() {
+                let config = DatabaseConfig::with_columns(Some(existing));
+
+                if let Ok(db) = Database::open(&config, path) {
+                    for _ in existing..columns {
+                        db.add_column()?;
+                    }
+
+                    return Ok(db);
+                }
+            }
+
+            Err(err)
+        }
+    }
 }
 
 struct Argv {
     network_name: String,
     mempool_size: u16,
     max_peers: usize,
+    outbound_peers: usize,
+    rpc_port: u16,
+    disable_rpc: bool,
+    listen_addresses: Vec<SocketAddr>,
+    data_dir: PathBuf,
+    rate_limits: RateLimits,
+    validators: Vec<NodeId>,
 }
 
 fn parse_cli_args() -> Argv {
@@ -158,11 +388,75 @@ fn parse_cli_args() -> Argv {
             Arg::with_name("max_peers")
                 .long("max-peers")
                 .value_name("MAX_PEERS")
-                .help("The maximum number of allowed peer connections")
+                .help("The maximum number of allowed inbound peer connections")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("outbound_peers")
+                .long("outbound-peers")
+                .value_name("OUTBOUND_PEERS")
+                .help("The number of outbound peer connections to keep")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("rpc_port")
+                .long("rpc-port")
+                .value_name("RPC_PORT")
+                .help("The port of the JSON-RPC server")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("disable_rpc")
+                .long("disable-rpc")
+                .help("Do not start the JSON-RPC server"),
+        )
+        .arg(
+            Arg::with_name("config")
+                .long("config")
+                .value_name("CONFIG")
+                .help("The path of a TOML configuration file")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("listen_address")
+                .long("listen-address")
+                .value_name("LISTEN_ADDRESS")
+                .help("An IPv4 or IPv6 address to listen on for peer connections. Can be given multiple times")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("port")
+                .long("port")
+                .value_name("PORT")
+                .help("The port to listen on for peer connections")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("data_dir")
+                .long("data-dir")
+                .value_name("DATA_DIR")
+                .help("The directory in which the node stores its data")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("validator")
+                .long("validator")
+                .value_name("VALIDATOR")
+                .help("A hex encoded node id of the initial validator set. Can be given multiple times")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
         .get_matches();
 
+    let config: Config = if let Some(arg) = matches.value_of("config") {
+        unwrap!(Config::load(Path::new(arg)), "Bad value for <CONFIG>")
+    } else {
+        Config::default()
+    };
+
     let network_name: String = if let Some(arg) = matches.value_of("network_name") {
         unwrap!(arg.parse(), "Expected value for <NETWORK_NAME>")
     } else {
@@ -181,9 +475,179 @@ fn parse_cli_args() -> Argv {
         8
     };
 
+    let outbound_peers: usize = if let Some(arg) = matches.value_of("outbound_peers") {
+        unwrap!(arg.parse(), "Bad value for <OUTBOUND_PEERS>")
+    } else {
+        8
+    };
+
+    let rpc_port: u16 = if let Some(arg) = matches.value_of("rpc_port") {
+        unwrap!(arg.parse(), "Bad value for <RPC_PORT>")
+    } else {
+        DEFAULT_RPC_PORT
+    };
+
+    let disable_rpc = matches.is_present("disable_rpc");
+
+    let listen_ips: Vec<IpAddr> = if let Some(args) = matches.values_of("listen_address") {
+        args.map(|arg| unwrap!(arg.parse(), "Bad value for <LISTEN_ADDRESS>"))
+            .collect()
+    } else if let Some(addresses) = config.listen_addresses {
+        addresses
+    } else {
+        vec![DEFAULT_LISTEN_ADDRESS.parse().unwrap()]
+    };
+
+    let port: u16 = if let Some(arg) = matches.value_of("port") {
+        unwrap!(arg.parse(), "Bad value for <PORT>")
+    } else if let Some(port) = config.port {
+        port
+    } else {
+        PORT
+    };
+
+    let listen_addresses: Vec<SocketAddr> = listen_ips
+        .iter()
+        .map(|ip| SocketAddr::new(*ip, port))
+        .collect();
+
+    let data_dir: PathBuf = if let Some(arg) = matches.value_of("data_dir") {
+        PathBuf::from(arg)
+    } else if let Some(data_dir) = config.data_dir {
+        data_dir
+    } else {
+        Path::new(&dirs::home_dir().unwrap()).join("purple")
+    };
+
+    let rate_limits = config.rate_limits.unwrap_or_default();
+
+    let validators: Vec<NodeId> = if let Some(args) = matches.values_of("validator") {
+        args.map(|arg| unwrap!(parse_node_id(arg), "Bad value for <VALIDATOR>"))
+            .collect()
+    } else if let Some(validators) = config.validators {
+        validators
+            .iter()
+            .map(|v| unwrap!(parse_node_id(v), "Bad value for <VALIDATOR>"))
+            .collect()
+    } else {
+        Vec::new()
+    };
+
     Argv {
         network_name: network_name,
         max_peers: max_peers,
+        outbound_peers: outbound_peers,
         mempool_size: mempool_size,
+        rpc_port: rpc_port,
+        disable_rpc: disable_rpc,
+        listen_addresses: listen_addresses,
+        data_dir: data_dir,
+        rate_limits: rate_limits,
+        validators: validators,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use causality::Stamp;
+    use events::Heartbeat;
+    use network::EventStore;
+    use tempfile::tempdir;
+
+    #[test]
+    fn it_seeds_the_initial_validator_set() {
+        let dir = tempdir().unwrap();
+        let config = DatabaseConfig::with_columns(None);
+        let db = Arc::new(Database::open(&config, dir.path().to_str().unwrap()).unwrap());
+        let consensus_db = || ConsensusDb::new(PersistentDb::new(db.clone(), None));
+        let identities: Vec<Identity> = (0..2).map(|_| Identity::new()).collect();
+        let validators: Vec<NodeId> = identities.iter().map(|i| NodeId(*i.pkey())).collect();
+        let consensus = load_consensus(&validators[0], "test", &validators, consensus_db());
+
+        assert!(validators.iter().all(|v| consensus.is_validator(v)));
+
+        // The events of the validators are accepted
+        let genesis = genesis_event("test");
+        let mut heartbeat = Heartbeat {
+            node_id: validators[1].clone(),
+            stamp: Stamp::seed().join(genesis.stamp().peek()).event(),
+            root_hash: Some(Hash::random()),
+            hash: None,
+            parent_hash: genesis.hash().unwrap(),
+            signature: None,
+            transactions: Vec::new(),
+        };
+
+        heartbeat.sign(identities[1].skey().clone());
+        heartbeat.hash();
+
+        let heartbeat = Event::Heartbeat(heartbeat);
+        let store = ConsensusStore::new(Arc::new(Mutex::new(consensus)));
+
+        assert_eq!(
+            store.push(&heartbeat.to_bytes().unwrap()),
+            Ok(heartbeat.hash().unwrap())
+        );
+        drop(store);
+
+        // The stored validator set is kept after a restart
+        let stranger = NodeId(*Identity::new().pkey());
+        let consensus = load_consensus(&validators[0], "test", &[stranger.clone()], consensus_db());
+
+        assert!(validators.iter().all(|v| consensus.is_validator(v)));
+        assert!(!consensus.is_validator(&stranger));
+        assert!(consensus.contains(&heartbeat.hash().unwrap()));
+    }
+
+    #[test]
+    fn it_rejects_bad_ledger_roots() {
+        let dir = tempdir().unwrap();
+        let config = DatabaseConfig::with_columns(None);
+        let db = Arc::new(Database::open(&config, dir.path().to_str().unwrap()).unwrap());
+        let mut storage = PersistentDb::new(db, None);
+        let root = Hash::random();
+
+        assert_eq!(fetch_ledger_root(&storage), Ok(Hash::NULL_RLP));
+
+        storage.emplace(
+            crypto::hash_slice(b"ledger_root"),
+            ElasticArray128::from_slice(&root.0),
+        );
+        assert_eq!(fetch_ledger_root(&storage), Ok(root));
+
+        storage.emplace(
+            crypto::hash_slice(b"ledger_root"),
+            ElasticArray128::from_slice(&[1, 2, 3]),
+        );
+        assert_eq!(fetch_ledger_root(&storage), Err("Bad ledger root"));
+    }
+
+    #[test]
+    fn it_adds_missing_columns_to_the_database() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().to_str().unwrap();
+        let key = crypto::hash_slice(b"key");
+
+        {
+            let config = DatabaseConfig::with_columns(Some(NUM_OF_COLUMNS - 1));
+            let db = Arc::new(Database::open(&config, path).unwrap());
+            let mut storage = PersistentDb::new(db, Some(0));
+
+            storage.emplace(key.clone(), ElasticArray128::from_slice(b"value"));
+        }
+
+        let db = Arc::new(open_columns(path, NUM_OF_COLUMNS).unwrap());
+        let storage = PersistentDb::new(db.clone(), Some(0));
+        let mut consensus_storage = PersistentDb::new(db.clone(), Some(NUM_OF_COLUMNS - 1));
+
+        assert_eq!(db.num_columns(), NUM_OF_COLUMNS);
+        assert_eq!(storage.get(&key).unwrap().to_vec(), b"value".to_vec());
+
+        consensus_storage.emplace(key.clone(), ElasticArray128::from_slice(b"state"));
+        assert_eq!(
+            consensus_storage.get(&key).unwrap().to_vec(),
+            b"state".to_vec()
+        );
     }
 }
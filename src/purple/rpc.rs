@@ -0,0 +1,381 @@
+/*
+  Copyright 2018 The Purple Library Authors
+  This file is part of the Purple Library.
+
+  The Purple Library is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  The Purple Library is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with the Purple Library. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use account::Address;
+use byteorder::{BigEndian, ReadBytesExt};
+use fetch_ledger_root;
+use jsonrpc_core::{Error, IoHandler, Result};
+use jsonrpc_http_server::{Server, ServerBuilder};
+use ledger::{open_ledger, read_balance, read_nonce, validate_tx};
+use mempool::{Mempool, MempoolErr};
+use network::{write_ban_list, Network, NodeId};
+use parking_lot::Mutex;
+use patricia_trie::Trie;
+use persistence::PersistentDb;
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use transactions::Tx;
+
+#[derive(Serialize, Debug, Clone)]
+pub struct NodeInfo {
+    /// The hex encoded node id
+    pub node_id: String,
+
+    /// The name of the network the node is on
+    pub network_name: String,
+
+    /// The number of listed peers
+    pub peer_count: usize,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct PeerInfo {
+    /// The address of the peer
+    pub address: String,
+
+    /// The hex encoded node id of the peer
+    ///
+    /// Equal to `None` until the handshake is completed.
+    pub node_id: Option<String>,
+
+    /// Whether we or the peer have initiated the connection
+    pub connection_type: String,
+
+    /// The last measured round-trip time in milliseconds
+    pub latency_ms: Option<u64>,
+
+    /// The number of bytes received from the peer
+    pub bytes_in: u64,
+
+    /// The number of bytes sent to the peer
+    pub bytes_out: u64,
+
+    /// The number of received packets dropped by the rate limiter
+    pub dropped_in: u64,
+
+    /// The number of packets dropped because the outbound queue was full
+    pub dropped_out: u64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct BanInfo {
+    /// The banned ip address or hex encoded node id
+    pub target: String,
+
+    /// Unix timestamp in seconds at which the ban expires
+    pub expires_at: u64,
+}
+
+build_rpc_trait! {
+    pub trait PurpleRpc {
+        /// Returns information about the running node.
+        #[rpc(name = "node_info")]
+        fn node_info(&self) -> Result<NodeInfo>;
+
+        /// Returns information about the peers we are connected to.
+        #[rpc(name = "peer_info")]
+        fn peer_info(&self) -> Result<Vec<PeerInfo>>;
+
+        /// Returns the banned ip addresses and node ids.
+        #[rpc(name = "list_bans")]
+        fn list_bans(&self) -> Result<Vec<BanInfo>>;
+
+        /// Bans the given ip address or hex encoded node
+        /// id for the given number of seconds, which must
+        /// be greater than zero.
+        #[rpc(name = "ban_peer")]
+        fn ban_peer(&self, String, u64) -> Result<bool>;
+
+        /// Lifts the ban of the given ip address or hex encoded
+        /// node id. Returns `false` if it was not banned.
+        #[rpc(name = "unban_peer")]
+        fn unban_peer(&self, String) -> Result<bool>;
+
+        /// Returns the nonce of the account with the
+        /// given hex encoded address.
+        ///
+        /// This is a placeholder until finalized transactions
+        /// are applied to the ledger since no account exists
+        /// before then.
+        #[rpc(name = "account_nonce")]
+        fn account_nonce(&self, String) -> Result<u64>;
+
+        /// Returns a mapping between the hex encoded asset
+        /// hashes and the balances of the account with the
+        /// given hex encoded address.
+        ///
+        /// This is a placeholder until finalized transactions
+        /// are applied to the ledger since no account exists
+        /// before then.
+        #[rpc(name = "account_balances")]
+        fn account_balances(&self, String) -> Result<HashMap<String, String>>;
+
+        /// Decodes and submits a hex encoded raw transaction.
+        ///
+        /// Returns the hex encoded hash of the transaction.
+        #[rpc(name = "submit_tx")]
+        fn submit_tx(&self, String) -> Result<String>;
+    }
+}
+
+struct RpcImpl {
+    network: Arc<Mutex<Network>>,
+    node_storage: PersistentDb,
+    ledger: PersistentDb,
+    mempool: Arc<Mutex<Mempool>>,
+}
+
+impl PurpleRpc for RpcImpl {
+    fn node_info(&self) -> Result<NodeInfo> {
+        let network = self.network.lock();
+        let node_id = network.node_id();
+
+        Ok(NodeInfo {
+            node_id: hex::encode(&(node_id.0).0),
+            network_name: network.network_name().to_owned(),
+            peer_count: network.peer_count(),
+        })
+    }
+
+    fn peer_info(&self) -> Result<Vec<PeerInfo>> {
+        let network = self.network.lock();
+        let peers = network
+            .peers()
+            .map(|peer| PeerInfo {
+                address: peer.ip.to_string(),
+                node_id: peer.id.as_ref().map(|id| hex::encode(&(id.0).0)),
+                connection_type: format!("{:?}", peer.connection_type),
+                latency_ms: peer
+                    .latency
+                    .map(|latency| latency.as_secs() * 1000 + u64::from(latency.subsec_millis())),
+                bytes_in: peer.bytes_in,
+                bytes_out: peer.bytes_out,
+                dropped_in: peer.dropped_in,
+                dropped_out: peer.dropped_out,
+            })
+            .collect();
+
+        Ok(peers)
+    }
+
+    fn list_bans(&self) -> Result<Vec<BanInfo>> {
+        let network = self.network.lock();
+        let ban_list = network.ban_list();
+        let node_ids = ban_list.node_ids().map(|(node_id, until)| BanInfo {
+            target: hex::encode(&(node_id.0).0),
+            expires_at: *until,
+        });
+        let ips = ban_list.ips().map(|(ip, until)| BanInfo {
+            target: ip.to_string(),
+            expires_at: *until,
+        });
+
+        Ok(node_ids.chain(ips).collect())
+    }
+
+    fn ban_peer(&self, target: String, duration: u64) -> Result<bool> {
+        // A ban of zero seconds has already expired
+        if duration == 0 {
+            return Err(Error::invalid_params(
+                "The ban duration must be greater than zero",
+            ));
+        }
+
+        match parse_ban_target(&target)? {
+            BanTarget::Ip(ip) => self.network.lock().ban_ip(ip, duration),
+            BanTarget::NodeId(node_id) => self.network.lock().ban_node_id(node_id, duration),
+        }
+
+        write_ban_list(&self.network, &mut self.node_storage.clone());
+        Ok(true)
+    }
+
+    fn unban_peer(&self, target: String) -> Result<bool> {
+        let unbanned = match parse_ban_target(&target)? {
+            BanTarget::Ip(ip) => self.network.lock().unban_ip(&ip),
+            BanTarget::NodeId(node_id) => self.network.lock().unban_node_id(&node_id),
+        };
+
+        write_ban_list(&self.network, &mut self.node_storage.clone());
+        Ok(unbanned)
+    }
+
+    fn account_nonce(&self, address: String) -> Result<u64> {
+        let address = parse_address(&address)?;
+        let root = fetch_ledger_root(&self.node_storage).map_err(|_| Error::internal_error())?;
+        let trie = open_ledger(&self.ledger, &root).map_err(|_| Error::internal_error())?;
+
+        match read_nonce(&trie, &address).map_err(|_| Error::internal_error())? {
+            Some(nonce) => Ok(nonce),
+            None => Err(Error::invalid_params("The account does not exist")),
+        }
+    }
+
+    fn account_balances(&self, address: String) -> Result<HashMap<String, String>> {
+        let address = parse_address(&address)?;
+        let root = fetch_ledger_root(&self.node_storage).map_err(|_| Error::internal_error())?;
+        let trie = open_ledger(&self.ledger, &root).map_err(|_| Error::internal_error())?;
+        let mut balances = HashMap::new();
+
+        // Retrieve the current currency group index
+        let cur_idx = match trie.get(b"ci") {
+            Ok(Some(idx)) => {
+                let mut rdr = Cursor::new(idx.to_vec());
+
+                match rdr.read_u64::<BigEndian>() {
+                    Ok(idx) => idx,
+                    Err(_) => return Err(Error::internal_error()),
+                }
+            }
+            Ok(None) => return Ok(balances),
+            Err(_) => return Err(Error::internal_error()),
+        };
+
+        for i in 0..(cur_idx + 1) {
+            let currencies_key = format!("c.{}", i);
+            let currencies: Vec<Vec<u8>> = match trie.get(currencies_key.as_bytes()) {
+                Ok(Some(currencies)) => rlp::decode_list(&currencies),
+                Ok(None) => continue,
+                Err(_) => return Err(Error::internal_error()),
+            };
+
+            for asset_hash in currencies.iter() {
+                let asset_hash = hex::encode(asset_hash);
+                let balance = read_balance(&trie, &address, &asset_hash)
+                    .map_err(|_| Error::internal_error())?;
+
+                if let Some(balance) = balance {
+                    balances.insert(asset_hash, format!("{}", balance));
+                }
+            }
+        }
+
+        Ok(balances)
+    }
+
+    fn submit_tx(&self, tx: String) -> Result<String> {
+        let bin = match hex::decode(&tx) {
+            Ok(bin) => bin,
+            Err(_) => return Err(Error::invalid_params("Invalid hex string")),
+        };
+
+        let mut tx = match Tx::from_bytes(&bin) {
+            Ok(tx) => tx,
+            Err(err) => return Err(Error::invalid_params(err)),
+        };
+
+        if !tx.verify_hash() {
+            return Err(Error::invalid_params("Invalid transaction hash"));
+        }
+
+        let hash = tx.hash().unwrap();
+        let nonce = match validate_tx(&self.node_storage, &self.ledger, &mut tx) {
+            Ok(nonce) => nonce,
+            Err(err) => return Err(Error::invalid_params(err)),
+        };
+
+        let result = self.mempool.lock().append(Arc::new(tx), nonce);
+
+        match result {
+            Ok(()) => {
+                // Relay the transaction to our peers
+                self.network.lock().announce_tx(hash, None);
+                Ok(hex::encode(&hash.0))
+            }
+            Err(MempoolErr::AlreadyInMempool) => Err(Error::invalid_params(
+                "The transaction is already in the mempool",
+            )),
+            Err(MempoolErr::Full) => Err(Error::invalid_params(
+                "The mempool is full and the transaction fee is too low",
+            )),
+            Err(_) => Err(Error::invalid_params("Invalid transaction")),
+        }
+    }
+}
+
+/// Starts the HTTP JSON-RPC server on the given address.
+///
+/// The server is stopped when the returned handle is dropped.
+pub fn start_rpc(
+    addr: &SocketAddr,
+    network: Arc<Mutex<Network>>,
+    node_storage: PersistentDb,
+    ledger: PersistentDb,
+    mempool: Arc<Mutex<Mempool>>,
+) -> Server {
+    info!("Starting JSON-RPC server on {}", addr);
+
+    let rpc = RpcImpl {
+        network: network,
+        node_storage: node_storage,
+        ledger: ledger,
+        mempool: mempool,
+    };
+
+    let mut io = IoHandler::new();
+    io.extend_with(rpc.to_delegate());
+
+    unwrap!(
+        ServerBuilder::new(io).start_http(addr),
+        "Unable to start JSON-RPC server"
+    )
+}
+
+/// Validates a hex encoded address and returns it
+/// in the format used by the ledger keys.
+fn parse_address(address: &str) -> Result<String> {
+    let bin = match hex::decode(address) {
+        Ok(bin) => bin,
+        Err(_) => return Err(Error::invalid_params("Invalid hex string")),
+    };
+
+    if bin.is_empty() {
+        return Err(Error::invalid_params("Empty address"));
+    }
+
+    match Address::from_bytes(&bin) {
+        Ok(address) => Ok(hex::encode(address.to_bytes())),
+        Err(err) => Err(Error::invalid_params(err)),
+    }
+}
+
+enum BanTarget {
+    Ip(IpAddr),
+    NodeId(NodeId),
+}
+
+/// Parses an ip address or a hex encoded node id.
+fn parse_ban_target(target: &str) -> Result<BanTarget> {
+    if let Ok(ip) = target.parse::<IpAddr>() {
+        return Ok(BanTarget::Ip(ip));
+    }
+
+    match hex::decode(target) {
+        Ok(ref bin) if bin.len() == 32 => {
+            let mut buf = [0; 32];
+            buf.copy_from_slice(bin);
+
+            Ok(BanTarget::NodeId(NodeId::new(buf)))
+        }
+        _ => Err(Error::invalid_params(
+            "Expected an ip address or a hex encoded node id",
+        )),
+    }
+}
@@ -0,0 +1,96 @@
+/*
+  Copyright 2018 The Purple Library Authors
+  This file is part of the Purple Library.
+
+  The Purple Library is free software: you can redistribute it and/or modify
+  it under the terms of the GNU General Public License as published by
+  the Free Software Foundation, either version 3 of the License, or
+  (at your option) any later version.
+
+  The Purple Library is distributed in the hope that it will be useful,
+  but WITHOUT ANY WARRANTY; without even the implied warranty of
+  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+  GNU General Public License for more details.
+
+  You should have received a copy of the GNU General Public License
+  along with the Purple Library. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use crypto::Hash;
+use ledger::validate_tx;
+use mempool::{Mempool, MempoolErr};
+use network::{TxStore, TxStoreErr};
+use parking_lot::Mutex;
+use persistence::PersistentDb;
+use std::fmt;
+use std::sync::Arc;
+use transactions::Tx;
+
+/// Stores the transactions relayed by the
+/// network layer in the mempool.
+pub struct MempoolStore {
+    mempool: Arc<Mutex<Mempool>>,
+    node_storage: PersistentDb,
+    ledger: PersistentDb,
+}
+
+impl MempoolStore {
+    pub fn new(
+        mempool: Arc<Mutex<Mempool>>,
+        node_storage: PersistentDb,
+        ledger: PersistentDb,
+    ) -> MempoolStore {
+        MempoolStore {
+            mempool: mempool,
+            node_storage: node_storage,
+            ledger: ledger,
+        }
+    }
+}
+
+impl fmt::Debug for MempoolStore {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MempoolStore")
+            .field("mempool", &self.mempool)
+            .finish()
+    }
+}
+
+impl TxStore for MempoolStore {
+    fn contains(&self, hash: &Hash) -> bool {
+        self.mempool.lock().contains(hash)
+    }
+
+    fn get(&self, hash: &Hash) -> Option<Vec<u8>> {
+        let tx = self.mempool.lock().get(hash)?;
+        tx.to_bytes().ok()
+    }
+
+    fn push(&self, tx: &[u8]) -> Result<Hash, TxStoreErr> {
+        let mut tx = Tx::from_bytes(tx).map_err(TxStoreErr::InvalidTx)?;
+
+        if !tx.verify_hash() {
+            return Err(TxStoreErr::InvalidTx("Invalid transaction hash"));
+        }
+
+        let hash = tx.hash().unwrap();
+
+        if self.mempool.lock().contains(&hash) {
+            return Err(TxStoreErr::AlreadyStored(hash));
+        }
+
+        let nonce = match validate_tx(&self.node_storage, &self.ledger, &mut tx) {
+            Ok(nonce) => nonce,
+            Err(err) => {
+                debug!("Invalid transaction {:?}: {}", hash, err);
+                return Err(TxStoreErr::Rejected(hash));
+            }
+        };
+
+        match self.mempool.lock().append(Arc::new(tx), nonce) {
+            Ok(()) => Ok(hash),
+            Err(MempoolErr::AlreadyInMempool) => Err(TxStoreErr::AlreadyStored(hash)),
+            Err(_) => Err(TxStoreErr::Rejected(hash)),
+        }
+    }
+}
@@ -545,7 +545,14 @@ impl Burn {
         unimplemented!();
     }
 
+    /// Returns the address of the account that issued the transaction.
+    pub fn creator(&self) -> Address {
+        self.burner
+    }
+
     impl_hash!();
+    impl_fee!();
+    impl_signature!();
     impl_validate_signature!();
 }
 
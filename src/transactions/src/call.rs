@@ -436,7 +436,14 @@ impl Call {
         Ok(call)
     }
 
+    /// Returns the address of the account that issued the transaction.
+    pub fn creator(&self) -> Address {
+        self.from
+    }
+
     impl_hash!();
+    impl_fee!();
+    impl_signature!();
 }
 
 fn assemble_hash_message(obj: &Call) -> Vec<u8> {
@@ -598,7 +598,13 @@ impl CreateCurrency {
         unimplemented!();
     }
 
+    /// Returns the address of the account that issued the transaction.
+    pub fn creator(&self) -> Address {
+        Address::Normal(self.creator)
+    }
+
     impl_hash!();
+    impl_fee!();
 }
 
 fn assemble_hash_message(obj: &CreateCurrency) -> Vec<u8> {
@@ -669,7 +669,13 @@ impl CreateMintable {
         unimplemented!();
     }
 
+    /// Returns the address of the account that issued the transaction.
+    pub fn creator(&self) -> Address {
+        Address::Normal(self.creator)
+    }
+
     impl_hash!();
+    impl_fee!();
 }
 
 fn assemble_hash_message(obj: &CreateMintable) -> Vec<u8> {
@@ -16,7 +16,7 @@
   along with the Purple Library. If not, see <http://www.gnu.org/licenses/>.
 */
 
-use account::{Balance, MultiSig, NormalAddress, ShareMap, ShareholdersAddress, Shares};
+use account::{Address, Balance, MultiSig, NormalAddress, ShareMap, ShareholdersAddress, Shares};
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use crypto::{Hash, SecretKey as Sk};
 use patricia_trie::{TrieDBMut, TrieMut};
@@ -436,7 +436,13 @@ impl IssueShares {
         unimplemented!();
     }
 
+    /// Returns the address of the account that issued the transaction.
+    pub fn creator(&self) -> Address {
+        Address::Shareholders(self.issuer)
+    }
+
     impl_hash!();
+    impl_fee!();
 }
 
 fn assemble_hash_message(obj: &IssueShares) -> Vec<u8> {
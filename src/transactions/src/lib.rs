@@ -76,7 +76,8 @@ pub use open_shares::*;
 pub use pay::*;
 pub use send::*;
 
-use crypto::Identity;
+use account::{Address, Balance, Signature};
+use crypto::{Hash, Identity};
 use patricia_trie::{TrieDBMut, TrieMut};
 use persistence::{BlakeDbHasher, Codec};
 use quickcheck::Arbitrary;
@@ -114,6 +115,187 @@ impl Tx {
         }
     }
 
+    /// Deserializes a transaction of any type by dispatching
+    /// on the transaction type byte.
+    pub fn from_bytes(bin: &[u8]) -> Result<Tx, &'static str> {
+        if bin.is_empty() {
+            return Err("Bad transaction type");
+        }
+
+        match bin[0] {
+            Call::TX_TYPE => match Call::from_bytes(bin) {
+                Ok(result) => Ok(Tx::Call(result)),
+                Err(_) => Err("Invalid call transaction"),
+            },
+            OpenContract::TX_TYPE => match OpenContract::from_bytes(bin) {
+                Ok(result) => Ok(Tx::OpenContract(result)),
+                Err(err) => Err(err),
+            },
+            Send::TX_TYPE => match Send::from_bytes(bin) {
+                Ok(result) => Ok(Tx::Send(result)),
+                Err(_) => Err("Invalid send transaction"),
+            },
+            Pay::TX_TYPE => match Pay::from_bytes(bin) {
+                Ok(result) => Ok(Tx::Pay(result)),
+                Err(_) => Err("Invalid pay transaction"),
+            },
+            OpenMultiSig::TX_TYPE => match OpenMultiSig::from_bytes(bin) {
+                Ok(result) => Ok(Tx::OpenMultiSig(result)),
+                Err(_) => Err("Invalid open multi signature transaction"),
+            },
+            OpenShares::TX_TYPE => match OpenShares::from_bytes(bin) {
+                Ok(result) => Ok(Tx::OpenShares(result)),
+                Err(_) => Err("Invalid open shares transaction"),
+            },
+            IssueShares::TX_TYPE => match IssueShares::from_bytes(bin) {
+                Ok(result) => Ok(Tx::IssueShares(result)),
+                Err(_) => Err("Invalid issue shares transaction"),
+            },
+            CreateCurrency::TX_TYPE => match CreateCurrency::from_bytes(bin) {
+                Ok(result) => Ok(Tx::CreateCurrency(result)),
+                Err(_) => Err("Invalid create currency transaction"),
+            },
+            CreateMintable::TX_TYPE => match CreateMintable::from_bytes(bin) {
+                Ok(result) => Ok(Tx::CreateMintable(result)),
+                Err(_) => Err("Invalid create mintable transaction"),
+            },
+            Mint::TX_TYPE => match Mint::from_bytes(bin) {
+                Ok(result) => Ok(Tx::Mint(result)),
+                Err(_) => Err("Invalid mint transaction"),
+            },
+            Burn::TX_TYPE => match Burn::from_bytes(bin) {
+                Ok(result) => Ok(Tx::Burn(result)),
+                Err(_) => Err("Invalid burn transaction"),
+            },
+            _ => Err("Bad transaction type"),
+        }
+    }
+
+    /// Returns the hash of the wrapped transaction.
+    ///
+    /// Returns `None` if the hash has not been computed yet.
+    pub fn hash(&self) -> Option<Hash> {
+        match *self {
+            Tx::Call(ref tx) => tx.tx_hash(),
+            Tx::OpenContract(ref tx) => tx.tx_hash(),
+            Tx::Send(ref tx) => tx.tx_hash(),
+            Tx::Burn(ref tx) => tx.tx_hash(),
+            Tx::CreateCurrency(ref tx) => tx.tx_hash(),
+            Tx::CreateMintable(ref tx) => tx.tx_hash(),
+            Tx::Mint(ref tx) => tx.tx_hash(),
+            Tx::IssueShares(ref tx) => tx.tx_hash(),
+            Tx::OpenMultiSig(ref tx) => tx.tx_hash(),
+            Tx::OpenShares(ref tx) => tx.tx_hash(),
+            Tx::Pay(ref tx) => tx.tx_hash(),
+        }
+    }
+
+    /// Verifies the correctness of the hash of the wrapped transaction.
+    ///
+    /// Returns `false` if the hash field or if the
+    /// signature field is missing.
+    pub fn verify_hash(&mut self) -> bool {
+        match *self {
+            Tx::Call(ref mut tx) => tx.verify_hash(),
+            Tx::OpenContract(ref mut tx) => tx.verify_hash(),
+            Tx::Send(ref mut tx) => tx.verify_hash(),
+            Tx::Burn(ref mut tx) => tx.verify_hash(),
+            Tx::CreateCurrency(ref mut tx) => tx.verify_hash(),
+            Tx::CreateMintable(ref mut tx) => tx.verify_hash(),
+            Tx::Mint(ref mut tx) => tx.verify_hash(),
+            Tx::IssueShares(ref mut tx) => tx.verify_hash(),
+            Tx::OpenMultiSig(ref mut tx) => tx.verify_hash(),
+            Tx::OpenShares(ref mut tx) => tx.verify_hash(),
+            Tx::Pay(ref mut tx) => tx.verify_hash(),
+        }
+    }
+
+    /// Verifies the signature of the wrapped transaction
+    /// against the public key of its creator.
+    ///
+    /// Returns `false` if the signature field is missing or if
+    /// the transaction is not signed by a normal address. The
+    /// signatures of multi signature and shareholders accounts
+    /// can only be verified against the keys stored in the ledger.
+    pub fn verify_sig(&mut self) -> bool {
+        match *self {
+            Tx::Call(ref mut tx) => {
+                is_normally_signed(&tx.creator(), tx.signature()) && tx.verify_sig()
+            }
+            Tx::OpenContract(ref mut tx) => {
+                is_normally_signed(&tx.creator(), tx.signature()) && tx.verify_sig()
+            }
+            Tx::Send(ref mut tx) => {
+                is_normally_signed(&tx.creator(), tx.signature()) && tx.verify_sig()
+            }
+            Tx::Burn(ref mut tx) => {
+                is_normally_signed(&tx.creator(), tx.signature()) && tx.verify_sig()
+            }
+            Tx::CreateCurrency(ref tx) => tx.verify_sig(),
+            Tx::CreateMintable(ref tx) => tx.verify_sig(),
+            Tx::Mint(ref mut tx) => {
+                is_normally_signed(&tx.creator(), tx.signature()) && tx.verify_sig()
+            }
+            Tx::IssueShares(_) => false,
+            Tx::OpenMultiSig(ref mut tx) => tx.verify_sig(),
+            Tx::OpenShares(ref mut tx) => tx.verify_sig(),
+            Tx::Pay(_) => false,
+        }
+    }
+
+    /// Returns the address of the account that
+    /// issued the wrapped transaction.
+    pub fn creator(&self) -> Address {
+        match *self {
+            Tx::Call(ref tx) => tx.creator(),
+            Tx::OpenContract(ref tx) => tx.creator(),
+            Tx::Send(ref tx) => tx.creator(),
+            Tx::Burn(ref tx) => tx.creator(),
+            Tx::CreateCurrency(ref tx) => tx.creator(),
+            Tx::CreateMintable(ref tx) => tx.creator(),
+            Tx::Mint(ref tx) => tx.creator(),
+            Tx::IssueShares(ref tx) => tx.creator(),
+            Tx::OpenMultiSig(ref tx) => tx.creator(),
+            Tx::OpenShares(ref tx) => tx.creator(),
+            Tx::Pay(ref tx) => tx.creator(),
+        }
+    }
+
+    /// Returns the fee of the wrapped transaction.
+    pub fn fee(&self) -> Balance {
+        match *self {
+            Tx::Call(ref tx) => tx.fee(),
+            Tx::OpenContract(ref tx) => tx.fee(),
+            Tx::Send(ref tx) => tx.fee(),
+            Tx::Burn(ref tx) => tx.fee(),
+            Tx::CreateCurrency(ref tx) => tx.fee(),
+            Tx::CreateMintable(ref tx) => tx.fee(),
+            Tx::Mint(ref tx) => tx.fee(),
+            Tx::IssueShares(ref tx) => tx.fee(),
+            Tx::OpenMultiSig(ref tx) => tx.fee(),
+            Tx::OpenShares(ref tx) => tx.fee(),
+            Tx::Pay(ref tx) => tx.fee(),
+        }
+    }
+
+    /// Returns the hash of the asset in which the
+    /// fee of the wrapped transaction is paid.
+    pub fn fee_hash(&self) -> Hash {
+        match *self {
+            Tx::Call(ref tx) => tx.fee_hash(),
+            Tx::OpenContract(ref tx) => tx.fee_hash(),
+            Tx::Send(ref tx) => tx.fee_hash(),
+            Tx::Burn(ref tx) => tx.fee_hash(),
+            Tx::CreateCurrency(ref tx) => tx.fee_hash(),
+            Tx::CreateMintable(ref tx) => tx.fee_hash(),
+            Tx::Mint(ref tx) => tx.fee_hash(),
+            Tx::IssueShares(ref tx) => tx.fee_hash(),
+            Tx::OpenMultiSig(ref tx) => tx.fee_hash(),
+            Tx::OpenShares(ref tx) => tx.fee_hash(),
+            Tx::Pay(ref tx) => tx.fee_hash(),
+        }
+    }
+
     pub fn compute_hash_message(&self) -> Vec<u8> {
         match *self {
             Tx::Call(ref tx) => tx.compute_hash_message(),
@@ -151,6 +333,15 @@ impl Tx {
     }
 }
 
+/// Returns `true` if the transaction is signed with
+/// a normal signature by a normal address.
+fn is_normally_signed(creator: &Address, signature: Option<&Signature>) -> bool {
+    match (creator, signature) {
+        (&Address::Normal(_), Some(&Signature::Normal(_))) => true,
+        _ => false,
+    }
+}
+
 impl Arbitrary for Tx {
     fn arbitrary<G: quickcheck::Gen>(g: &mut G) -> Tx {
         let mut rng = rand::thread_rng();
@@ -172,3 +363,14 @@ impl Arbitrary for Tx {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    quickcheck! {
+        fn serialize_deserialize(tx: Tx) -> bool {
+            tx == Tx::from_bytes(&Tx::to_bytes(&tx).unwrap()).unwrap()
+        }
+    }
+}
@@ -31,6 +31,13 @@ macro_rules! impl_hash {
             self.hash = Some(hash);
         }
 
+        /// Returns the hash of the transaction.
+        ///
+        /// Returns `None` if the hash has not been computed yet.
+        pub fn tx_hash(&self) -> Option<Hash> {
+            self.hash.clone()
+        }
+
         /// Computes the message that is passed to the
         /// hash function of this transaction.
         ///
@@ -39,15 +46,19 @@ macro_rules! impl_hash {
 
         /// Verifies the correctness of the hash of the transaction.
         ///
-        /// This function will panic if the hash field or if the
+        /// Returns `false` if the hash field or if the
         /// signature field is missing.
         pub fn verify_hash(&mut self) -> bool {
             let hash = if let Some(hash) = &self.hash {
                 hash.0
             } else {
-                panic!("Hash field is missing!");
+                return false;
             };
 
+            if self.signature.is_none() {
+                return false;
+            }
+
             let oracle_message = assemble_hash_message(&self);
             let oracle_hash = crypto::hash_slice(&oracle_message);
 
@@ -56,6 +67,30 @@ macro_rules! impl_hash {
     }
 }
 
+macro_rules! impl_fee {
+    () => {
+        /// Returns the fee of the transaction.
+        pub fn fee(&self) -> Balance {
+            self.fee.clone()
+        }
+
+        /// Returns the hash of the asset in
+        /// which the fee is paid.
+        pub fn fee_hash(&self) -> Hash {
+            self.fee_hash.clone()
+        }
+    }
+}
+
+macro_rules! impl_signature {
+    () => {
+        /// Returns the signature of the transaction.
+        pub fn signature(&self) -> Option<&Signature> {
+            self.signature.as_ref()
+        }
+    }
+}
+
 macro_rules! impl_validate_signature {
     () => {
         fn validate_signature(&mut self, creator: &Address, signature: &Option<Signature>, trie: &TrieDBMut<BlakeDbHasher, Codec>) -> bool {
@@ -615,7 +615,14 @@ impl Mint {
         unimplemented!();
     }
 
+    /// Returns the address of the account that issued the transaction.
+    pub fn creator(&self) -> Address {
+        self.minter
+    }
+
     impl_hash!();
+    impl_fee!();
+    impl_signature!();
     impl_validate_signature!();
 }
 
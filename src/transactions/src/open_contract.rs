@@ -619,7 +619,14 @@ impl OpenContract {
         unimplemented!();
     }
 
+    /// Returns the address of the account that issued the transaction.
+    pub fn creator(&self) -> Address {
+        self.owner
+    }
+
     impl_hash!();
+    impl_fee!();
+    impl_signature!();
 }
 
 fn assemble_hash_message(obj: &OpenContract) -> Vec<u8> {
@@ -16,7 +16,7 @@
   along with the Purple Library. If not, see <http://www.gnu.org/licenses/>.
 */
 
-use account::{Balance, MultiSigAddress, NormalAddress};
+use account::{Address, Balance, MultiSigAddress, NormalAddress};
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use crypto::{Hash, SecretKey as Sk, Signature};
 use patricia_trie::{TrieDBMut, TrieMut};
@@ -485,7 +485,13 @@ impl OpenMultiSig {
         unimplemented!();
     }
 
+    /// Returns the address of the account that issued the transaction.
+    pub fn creator(&self) -> Address {
+        Address::Normal(self.creator)
+    }
+
     impl_hash!();
+    impl_fee!();
 }
 
 fn assemble_hash_message(obj: &OpenMultiSig) -> Vec<u8> {
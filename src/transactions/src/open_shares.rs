@@ -16,7 +16,7 @@
   along with the Purple Library. If not, see <http://www.gnu.org/licenses/>.
 */
 
-use account::{Balance, NormalAddress, ShareMap, ShareholdersAddress, Shares};
+use account::{Address, Balance, NormalAddress, ShareMap, ShareholdersAddress, Shares};
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use crypto::{Hash, SecretKey as Sk, Signature};
 use patricia_trie::{TrieDBMut, TrieMut};
@@ -543,7 +543,13 @@ impl OpenShares {
         unimplemented!();
     }
 
+    /// Returns the address of the account that issued the transaction.
+    pub fn creator(&self) -> Address {
+        Address::Normal(self.creator)
+    }
+
     impl_hash!();
+    impl_fee!();
 }
 
 fn assemble_hash_message(obj: &OpenShares) -> Vec<u8> {
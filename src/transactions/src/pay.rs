@@ -16,7 +16,7 @@
   along with the Purple Library. If not, see <http://www.gnu.org/licenses/>.
 */
 
-use account::{Balance, MultiSig, NormalAddress, ShareMap, ShareholdersAddress};
+use account::{Address, Balance, MultiSig, NormalAddress, ShareMap, ShareholdersAddress};
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use crypto::{Hash, SecretKey as Sk};
 use patricia_trie::{TrieDBMut, TrieMut};
@@ -396,7 +396,13 @@ impl Pay {
         unimplemented!();
     }
 
+    /// Returns the address of the account that issued the transaction.
+    pub fn creator(&self) -> Address {
+        Address::Shareholders(self.payer)
+    }
+
     impl_hash!();
+    impl_fee!();
 }
 
 fn pay_dividend(
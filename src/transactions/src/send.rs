@@ -716,7 +716,14 @@ impl Send {
         unimplemented!();
     }
 
+    /// Returns the address of the account that issued the transaction.
+    pub fn creator(&self) -> Address {
+        self.from
+    }
+
     impl_hash!();
+    impl_fee!();
+    impl_signature!();
 }
 
 fn assemble_hash_message(obj: &Send) -> Vec<u8> {
@@ -1184,6 +1191,16 @@ mod tests {
             tx.verify_hash()
         }
 
+        fn verify_hash_without_hash_or_signature(tx: Send) -> bool {
+            let mut unhashed = tx.clone();
+            let mut unsigned = tx;
+
+            unhashed.hash = None;
+            unsigned.signature = None;
+
+            !unhashed.verify_hash() && !unsigned.verify_hash()
+        }
+
         fn verify_signature(
             to: Address,
             amount: Balance,